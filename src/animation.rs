@@ -0,0 +1,242 @@
+// Frame based sprite animation. Clips are ordered sprite sheet frames with
+// their own durations, the Animator plays one clip at a time and switches
+// between them by name (idle, walk, attack...).
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlaybackMode {
+    Loop,
+    Once,     // Stops on the last frame
+    PingPong, // 0, 1, 2, 1, 0, 1...
+}
+
+#[derive(Clone)]
+pub struct AnimationFrame {
+    pub frame: usize, // Sprite sheet frame index
+    pub duration: f32,
+}
+
+#[derive(Clone)]
+pub struct AnimationClip {
+    name: String,
+    frames: Vec<AnimationFrame>,
+    mode: PlaybackMode,
+    events: Vec<(usize, String)>, // Fired when the clip reaches the frame
+    next: Option<String>,         // Clip to play when a Once clip finishes
+    interruptible: bool,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, mode: PlaybackMode) -> Self {
+        Self {
+            name: name.to_string(),
+            frames: Vec::new(),
+            mode,
+            events: Vec::new(),
+            next: None,
+            interruptible: true,
+        }
+    }
+
+    // Every frame gets the same duration
+    pub fn from_frames(name: &str, frames: &[usize], duration: f32, mode: PlaybackMode) -> Self {
+        let mut result = AnimationClip::new(name, mode);
+        for &frame in frames {
+            result.add_frame(frame, duration);
+        }
+
+        result
+    }
+
+    pub fn add_frame(&mut self, frame: usize, duration: f32) {
+        // Zero length frames would spin forever in Animator::update
+        self.frames.push(AnimationFrame {
+            frame,
+            duration: duration.max(0.001),
+        });
+    }
+
+    // Index is the position in the clip, not the sprite sheet frame
+    pub fn add_event(&mut self, index: usize, event: &str) {
+        self.events.push((index, event.to_string()));
+    }
+
+    pub fn set_next(&mut self, clip_name: &str) {
+        self.next = Some(clip_name.to_string());
+    }
+
+    // Uninterruptible clips ignore Animator::set_state until they finish (attacks)
+    pub fn set_interruptible(&mut self, interruptible: bool) {
+        self.interruptible = interruptible;
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Clone)]
+pub struct Animator {
+    clips: Vec<AnimationClip>,
+    current: usize,
+    frame: usize, // Position in the current clip
+    frame_time: f32,
+    direction: i32, // Ping pong direction
+    speed: f32,
+    finished: bool,
+    events: Vec<String>,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self {
+            clips: Vec::new(),
+            current: 0,
+            frame: 0,
+            frame_time: 0.0,
+            direction: 1,
+            speed: 1.0,
+            finished: false,
+            events: Vec::new(),
+        }
+    }
+
+    // The first clip added starts playing
+    pub fn add_clip(&mut self, clip: AnimationClip) {
+        self.clips.push(clip);
+
+        if self.clips.len() == 1 {
+            self.restart(0);
+        }
+    }
+
+    fn find_clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+
+    fn restart(&mut self, clip: usize) {
+        self.current = clip;
+        self.frame = 0;
+        self.frame_time = 0.0;
+        self.direction = 1;
+        self.finished = false;
+        self.fire_events();
+    }
+
+    fn fire_events(&mut self) {
+        let clip = &self.clips[self.current];
+        for (index, event) in &clip.events {
+            if *index == self.frame {
+                self.events.push(event.clone());
+            }
+        }
+    }
+
+    // Always starts the clip from the beginning
+    pub fn play(&mut self, name: &str) {
+        if let Some(clip) = self.find_clip(name) {
+            self.restart(clip);
+        }
+    }
+
+    // Switches clips only when the state actually changes, so it can be called every frame
+    pub fn set_state(&mut self, name: &str) {
+        let current = match self.clips.get(self.current) {
+            Some(current) => current,
+            None => return,
+        };
+        if current.name == name || (!current.interruptible && !self.finished) {
+            return;
+        }
+
+        self.play(name);
+    }
+
+    // Empty without any clips
+    pub fn get_state(&self) -> &str {
+        self.clips
+            .get(self.current)
+            .map_or("", |clip| clip.name.as_str())
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Sprite sheet frame to draw
+    pub fn current_frame(&self) -> Option<usize> {
+        self.clips
+            .get(self.current)
+            .and_then(|clip| clip.frames.get(self.frame))
+            .map(|frame| frame.frame)
+    }
+
+    // Events fired since they were last cleared, including the first frame of a
+    // clip started with play or set_state
+    pub fn events(&self) -> &[String] {
+        &self.events
+    }
+
+    // Hands over the events and forgets them, call once a frame
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    // For callers that only peek at events, run before changing state each frame
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    // Moves to the next frame, returns false when a Once clip runs out
+    fn advance(&mut self) -> bool {
+        let count = self.clips[self.current].frames.len();
+
+        match self.clips[self.current].mode {
+            PlaybackMode::Loop => self.frame = (self.frame + 1) % count,
+            PlaybackMode::Once => {
+                if self.frame + 1 >= count {
+                    return false;
+                }
+                self.frame += 1;
+            }
+            PlaybackMode::PingPong => {
+                if count > 1 {
+                    let next = self.frame as i32 + self.direction;
+                    if next < 0 || next >= count as i32 {
+                        self.direction = -self.direction;
+                    }
+                    self.frame = (self.frame as i32 + self.direction) as usize;
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.clips.is_empty() || self.finished || self.clips[self.current].frames.is_empty() {
+            return;
+        }
+
+        self.frame_time += dt * self.speed;
+
+        while self.frame_time >= self.clips[self.current].frames[self.frame].duration {
+            self.frame_time -= self.clips[self.current].frames[self.frame].duration;
+
+            if !self.advance() {
+                self.finished = true;
+
+                let next = self.clips[self.current].next.clone();
+                if let Some(next) = next {
+                    self.play(&next);
+                }
+                return;
+            }
+
+            self.fire_events();
+        }
+    }
+}
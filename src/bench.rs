@@ -0,0 +1,101 @@
+use std::time::Instant;
+
+use crate::{
+    math::as_fractional_secs,
+    simd::{self, SimdLevel},
+};
+
+// Run the game with --bench to time the simd.rs kernels. Every test draws a
+// 1280x720 frame at each level the CPU supports and checks the pixels match
+// the scalar version.
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const FRAMES: u32 = 100;
+
+type Kernel = fn(SimdLevel, &mut [u32], &Sources);
+
+struct Sources {
+    sprite: Vec<u32>, // Frame sized, with a spread of alpha values
+    small: Vec<u32>,  // Quarter width rows for the scaled blit
+}
+
+pub fn run() {
+    // Cheap deterministic noise so every run blends the same pixels
+    let mut seed = 0x12345678u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    let sources = Sources {
+        sprite: (0..WIDTH * HEIGHT).map(|_| random()).collect(),
+        small: (0..WIDTH / 4 * HEIGHT).map(|_| random()).collect(),
+    };
+
+    println!(
+        "Benchmarking {} frames of {}x{}, best level: {}",
+        FRAMES,
+        WIDTH,
+        HEIGHT,
+        simd::detect().get_name()
+    );
+
+    let tests: [(&str, Kernel); 5] = [
+        ("clear", |level, frame, _| {
+            simd::fill_with(level, frame, 0xFF336699)
+        }),
+        ("fill alpha", |level, frame, _| {
+            simd::blend_color_with(level, frame, 0x80FF8000)
+        }),
+        ("blit alpha", |level, frame, sources| {
+            simd::blend_with(level, frame, &sources.sprite)
+        }),
+        ("blit tinted", |level, frame, sources| {
+            simd::blend_tinted_with(level, frame, &sources.sprite, 0xC0FF8040)
+        }),
+        ("blit scaled 4x", |level, frame, sources| {
+            let mut texels = vec![0; WIDTH];
+            for (y, row) in frame.chunks_mut(WIDTH).enumerate() {
+                let source_row = &sources.small[y * WIDTH / 4..(y + 1) * WIDTH / 4];
+                simd::gather_with(level, &mut texels, source_row, 1 << 13, 1 << 14);
+                simd::blend_with(level, row, &texels);
+            }
+        }),
+    ];
+
+    for (name, kernel) in tests.iter() {
+        let mut scalar_time = 0.0;
+        let mut expected = Vec::new();
+
+        for level in simd::available_levels() {
+            let mut frame = vec![0xFF204060u32; WIDTH * HEIGHT];
+
+            kernel(level, &mut frame, &sources);
+            if level == SimdLevel::Scalar {
+                expected = frame.clone();
+            } else if frame != expected {
+                println!("  {} at {} doesn't match scalar!", name, level.get_name());
+            }
+
+            let start = Instant::now();
+            for _ in 0..FRAMES {
+                kernel(level, &mut frame, &sources);
+            }
+            let time = as_fractional_secs(&start.elapsed()) * 1000.0 / FRAMES as f32;
+
+            if level == SimdLevel::Scalar {
+                scalar_time = time;
+            }
+            println!(
+                "{:>16} {:>7}: {:8.3} ms/frame  {:5.2}x",
+                name,
+                level.get_name(),
+                time,
+                scalar_time / time
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    math::{Point, Rect},
+    simd,
+    win32_engine::Win32GameBitmap,
+};
+
+// Scaled and flipped texture drawing, for pixel art at 2x/3x and sprites
+// that need to face the other way.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,  // Crisp pixels, what pixel art wants
+    Bilinear, // Smooth, for everything else
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Flip {
+    pub horizontal: bool,
+    pub vertical: bool,
+}
+
+impl Flip {
+    pub const NONE: Flip = Flip {
+        horizontal: false,
+        vertical: false,
+    };
+    pub const HORIZONTAL: Flip = Flip {
+        horizontal: true,
+        vertical: false,
+    };
+    pub const VERTICAL: Flip = Flip {
+        horizontal: false,
+        vertical: true,
+    };
+}
+
+// Placement of a rotated sprite. The pivot is in sprite pixels from its top left
+// corner and ends up at pos in the buffer, the sprite spins around it.
+#[derive(Clone, Copy)]
+pub struct SpriteTransform {
+    pub pos: Point<f32>,
+    pub size: Point<f32>,
+    pub pivot: Point<f32>,
+    pub angle: f32, // Radians, clockwise on screen since y points down
+    pub flip: Flip,
+}
+
+impl SpriteTransform {
+    // Spins around the center of the sprite, pos is where the center lands
+    pub fn centered(pos: Point<f32>, size: Point<f32>, angle: f32) -> Self {
+        Self {
+            pos,
+            size,
+            pivot: Point::new(size.x * 0.5, size.y * 0.5),
+            angle,
+            flip: Flip::NONE,
+        }
+    }
+}
+
+// Bilinear sample of the texture at (u, v) in texel space, clamped to the source rect.
+// Colors are weighted by alpha so transparent texels don't bleed dark edges.
+// The source has to be inside the texture, see clip_source.
+pub fn sample_bilinear(texture: &Win32GameBitmap, source: &Rect, u: f32, v: f32) -> u32 {
+    let min_x = source.x as i32;
+    let min_y = source.y as i32;
+    let max_x = (source.x + source.w) as i32 - 1;
+    let max_y = (source.y + source.h) as i32 - 1;
+
+    let u = u - 0.5;
+    let v = v - 0.5;
+    let x0 = u.floor() as i32;
+    let y0 = v.floor() as i32;
+    let fx = u - x0 as f32;
+    let fy = v - y0 as f32;
+
+    let texels = [
+        (x0, y0, (1.0 - fx) * (1.0 - fy)),
+        (x0 + 1, y0, fx * (1.0 - fy)),
+        (x0, y0 + 1, (1.0 - fx) * fy),
+        (x0 + 1, y0 + 1, fx * fy),
+    ];
+
+    let mut alpha = 0.0;
+    let mut red = 0.0;
+    let mut green = 0.0;
+    let mut blue = 0.0;
+
+    for &(x, y, weight) in texels.iter() {
+        let texel = texture.get_pixel(x.max(min_x).min(max_x), y.max(min_y).min(max_y));
+        let weighted_alpha = weight * (texel >> 24) as f32;
+
+        alpha += weighted_alpha;
+        red += weighted_alpha * ((texel >> 16) & 0xFF) as f32;
+        green += weighted_alpha * ((texel >> 8) & 0xFF) as f32;
+        blue += weighted_alpha * (texel & 0xFF) as f32;
+    }
+
+    if alpha < 0.5 {
+        return 0;
+    }
+
+    ((alpha + 0.5) as u32) << 24
+        | ((red / alpha + 0.5) as u32) << 16
+        | ((green / alpha + 0.5) as u32) << 8
+        | (blue / alpha + 0.5) as u32
+}
+
+impl Win32GameBitmap {
+    // The part of the source rect that's inside the texture, None if nothing is.
+    // Frame rects come from data files, a bad one shouldn't read past the pixels.
+    pub fn clip_source(&self, source: &Rect) -> Option<Rect> {
+        let width = self.get_width().max(0) as u32;
+        let height = self.get_height().max(0) as u32;
+
+        let x = source.x.min(width);
+        let y = source.y.min(height);
+        let w = source.w.min(width - x);
+        let h = source.h.min(height - y);
+
+        if w == 0 || h == 0 {
+            None
+        } else {
+            Some(Rect::new(x, y, w, h))
+        }
+    }
+
+    // Stretches the source rect of the texture over size pixels at pos
+    pub fn draw_bmp_scaled(
+        &self,
+        source: &Rect,
+        pos: Point<i32>,
+        size: Point<u32>,
+        flip: Flip,
+        filter: Filter,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        let source = match self.clip_source(source) {
+            Some(source) => source,
+            None => return,
+        };
+        if size.x == 0 || size.y == 0 {
+            return;
+        }
+
+        let lower_x = pos.x.max(0);
+        let lower_y = pos.y.max(0);
+        let upper_x = (pos.x + size.x as i32).min(buffer.get_width());
+        let upper_y = (pos.y + size.y as i32).min(buffer.get_height());
+
+        // Source texels per destination pixel
+        let step_x = source.w as f32 / size.x as f32;
+        let step_y = source.h as f32 / size.y as f32;
+
+        if lower_x >= upper_x {
+            return;
+        }
+
+        // Nearest rows are fetched in one go as 16.16 fixed point texel positions,
+        // stepping backwards through the source when flipped
+        let first_x = (lower_x - pos.x) as f64;
+        let (start, step) = if flip.horizontal {
+            (
+                (size.x as f64 - 0.5 - first_x) * step_x as f64,
+                -(step_x as f64),
+            )
+        } else {
+            ((first_x + 0.5) * step_x as f64, step_x as f64)
+        };
+        let start = (start * 65536.0) as i32;
+        let step = (step * 65536.0).round() as i32;
+        let mut texels = vec![0; (upper_x - lower_x) as usize];
+
+        for y in lower_y..upper_y {
+            let mut dest_y = y - pos.y;
+            if flip.vertical {
+                dest_y = size.y as i32 - 1 - dest_y;
+            }
+            let v = source.y as f32 + (dest_y as f32 + 0.5) * step_y;
+
+            match filter {
+                Filter::Nearest => {
+                    let texel_y = (v as i32).min((source.y + source.h) as i32 - 1);
+                    let source_row =
+                        &self.row(texel_y)[source.x as usize..(source.x + source.w) as usize];
+
+                    simd::gather(&mut texels, source_row, start, step);
+                }
+                Filter::Bilinear => {
+                    for (i, texel) in texels.iter_mut().enumerate() {
+                        let mut dest_x = lower_x + i as i32 - pos.x;
+                        if flip.horizontal {
+                            dest_x = size.x as i32 - 1 - dest_x;
+                        }
+                        let u = source.x as f32 + (dest_x as f32 + 0.5) * step_x;
+
+                        *texel = sample_bilinear(self, &source, u, v);
+                    }
+                }
+            }
+
+            simd::blend(
+                &mut buffer.row_mut(y)[lower_x as usize..upper_x as usize],
+                &texels,
+            );
+        }
+    }
+
+    // Whole texture at an integer scale, the common pixel art case
+    pub fn draw_bmp_upscaled(&self, pos: Point<i32>, scale: u32, buffer: &mut Win32GameBitmap) {
+        let whole = Rect::new(0, 0, self.get_width() as u32, self.get_height() as u32);
+        let size = Point::new(whole.w * scale, whole.h * scale);
+
+        self.draw_bmp_scaled(&whole, pos, size, Flip::NONE, Filter::Nearest, buffer);
+    }
+
+    // Rotates the source rect around the transform pivot. Every buffer pixel in the
+    // rotated bounds is mapped back into the sprite, so there are no gaps.
+    pub fn draw_bmp_rotated(
+        &self,
+        source: &Rect,
+        transform: &SpriteTransform,
+        filter: Filter,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        let source = match self.clip_source(source) {
+            Some(source) => source,
+            None => return,
+        };
+        let size = transform.size;
+        if size.x <= 0.0 || size.y <= 0.0 {
+            return;
+        }
+
+        let (sin, cos) = transform.angle.sin_cos();
+
+        // Screen bounds of the rotated corners
+        let mut min = Point::new(f32::MAX, f32::MAX);
+        let mut max = Point::new(f32::MIN, f32::MIN);
+        for &(corner_x, corner_y) in
+            [(0.0, 0.0), (size.x, 0.0), (0.0, size.y), (size.x, size.y)].iter()
+        {
+            let local_x = corner_x - transform.pivot.x;
+            let local_y = corner_y - transform.pivot.y;
+            let screen_x = transform.pos.x + local_x * cos - local_y * sin;
+            let screen_y = transform.pos.y + local_x * sin + local_y * cos;
+
+            min.x = min.x.min(screen_x);
+            min.y = min.y.min(screen_y);
+            max.x = max.x.max(screen_x);
+            max.y = max.y.max(screen_y);
+        }
+
+        let lower_x = (min.x.floor() as i32).max(0);
+        let lower_y = (min.y.floor() as i32).max(0);
+        let upper_x = (max.x.ceil() as i32).min(buffer.get_width());
+        let upper_y = (max.y.ceil() as i32).min(buffer.get_height());
+
+        let texels_per_x = source.w as f32 / size.x;
+        let texels_per_y = source.h as f32 / size.y;
+
+        for y in lower_y..upper_y {
+            let offset_y = y as f32 + 0.5 - transform.pos.y;
+
+            for x in lower_x..upper_x {
+                let offset_x = x as f32 + 0.5 - transform.pos.x;
+
+                // Inverse rotation takes the pixel center back into sprite space
+                let mut local_x = offset_x * cos + offset_y * sin + transform.pivot.x;
+                let mut local_y = -offset_x * sin + offset_y * cos + transform.pivot.y;
+
+                if local_x < 0.0 || local_y < 0.0 || local_x >= size.x || local_y >= size.y {
+                    continue;
+                }
+
+                if transform.flip.horizontal {
+                    local_x = size.x - local_x;
+                }
+                if transform.flip.vertical {
+                    local_y = size.y - local_y;
+                }
+
+                let u = source.x as f32 + local_x * texels_per_x;
+                let v = source.y as f32 + local_y * texels_per_y;
+
+                let color = match filter {
+                    Filter::Nearest => {
+                        let texel_x = (u as i32)
+                            .max(source.x as i32)
+                            .min((source.x + source.w) as i32 - 1);
+                        let texel_y = (v as i32)
+                            .max(source.y as i32)
+                            .min((source.y + source.h) as i32 - 1);
+                        self.get_pixel(texel_x, texel_y)
+                    }
+                    Filter::Bilinear => sample_bilinear(self, &source, u, v),
+                };
+
+                buffer.blend_pixel(x, y, color);
+            }
+        }
+    }
+}
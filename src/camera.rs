@@ -0,0 +1,290 @@
+use crate::math::Point;
+
+// World space camera. position is the world point at the center of the screen,
+// zoom is screen pixels per world pixel and rotation turns the view (radians,
+// the world turns the other way on screen).
+//
+// Following keeps the target inside a deadzone around the center and eases
+// towards it, bounds keep the view inside the level, and shake jitters the view
+// without moving the camera itself.
+
+pub struct Camera2D {
+    position: Point<f32>,
+    zoom: f32,
+    rotation: f32,
+    viewport: Point<f32>, // Screen size in pixels
+    deadzone: Point<f32>, // Screen pixels the target can move in before the camera follows
+    follow_speed: f32,    // How quickly the camera catches up, 0 snaps
+    bounds: Option<(Point<f32>, Point<f32>)>,
+    shake_strength: f32,
+    shake_duration: f32,
+    shake_time: f32,
+    shake_offset: Point<f32>,
+    shake_seed: u32,
+}
+
+// The camera boiled down to what's needed to move points to the screen
+#[derive(Clone, Copy)]
+pub struct CameraTransform {
+    position: Point<f32>,
+    screen_center: Point<f32>,
+    zoom: f32,
+    sin: f32,
+    cos: f32,
+}
+
+impl CameraTransform {
+    // Screen space, world pixel == screen pixel
+    pub fn identity() -> Self {
+        Self {
+            position: Point::new(0.0, 0.0),
+            screen_center: Point::new(0.0, 0.0),
+            zoom: 1.0,
+            sin: 0.0,
+            cos: 1.0,
+        }
+    }
+
+    pub fn world_to_screen(&self, point: Point<f32>) -> Point<f32> {
+        let x = (point.x - self.position.x) * self.zoom;
+        let y = (point.y - self.position.y) * self.zoom;
+
+        Point::new(
+            self.screen_center.x + x * self.cos + y * self.sin,
+            self.screen_center.y - x * self.sin + y * self.cos,
+        )
+    }
+
+    pub fn screen_to_world(&self, point: Point<f32>) -> Point<f32> {
+        let x = point.x - self.screen_center.x;
+        let y = point.y - self.screen_center.y;
+
+        Point::new(
+            self.position.x + (x * self.cos - y * self.sin) / self.zoom,
+            self.position.y + (x * self.sin + y * self.cos) / self.zoom,
+        )
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    // Angle things in the world turn by on screen
+    pub fn get_screen_angle(&self) -> f32 {
+        -self.sin.atan2(self.cos)
+    }
+
+    pub fn is_rotated(&self) -> bool {
+        self.sin != 0.0
+    }
+}
+
+impl Camera2D {
+    pub fn new(viewport: Point<f32>) -> Self {
+        Self {
+            position: Point::new(viewport.x * 0.5, viewport.y * 0.5),
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport,
+            deadzone: Point::new(0.0, 0.0),
+            follow_speed: 0.0,
+            bounds: None,
+            shake_strength: 0.0,
+            shake_duration: 0.0,
+            shake_time: 0.0,
+            shake_offset: Point::new(0.0, 0.0),
+            shake_seed: 0x9E3779B9,
+        }
+    }
+
+    pub fn set_viewport(&mut self, viewport: Point<f32>) {
+        self.viewport = viewport;
+        self.clamp_to_bounds();
+    }
+
+    pub fn set_position(&mut self, position: Point<f32>) {
+        self.position = position;
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_position(&self) -> Point<f32> {
+        self.position
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(0.01);
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    // Size in screen pixels of the box around the center the target can move in freely
+    pub fn set_deadzone(&mut self, size: Point<f32>) {
+        self.deadzone = size;
+    }
+
+    // Roughly the fraction of the distance covered per 1/speed seconds, 0 snaps straight to the target
+    pub fn set_follow_speed(&mut self, speed: f32) {
+        self.follow_speed = speed.max(0.0);
+    }
+
+    // World rect the view has to stay inside (usually the level)
+    pub fn set_bounds(&mut self, min: Point<f32>, max: Point<f32>) {
+        self.bounds = Some((min, max));
+        self.clamp_to_bounds();
+    }
+
+    pub fn clear_bounds(&mut self) {
+        self.bounds = None;
+    }
+
+    // Strength is in screen pixels and fades out over duration seconds.
+    // A stronger shake replaces a weaker one.
+    pub fn shake(&mut self, strength: f32, duration: f32) {
+        if strength >= self.current_shake() {
+            self.shake_strength = strength;
+            self.shake_duration = duration.max(0.001);
+            self.shake_time = duration.max(0.001);
+        }
+    }
+
+    fn current_shake(&self) -> f32 {
+        if self.shake_time <= 0.0 {
+            return 0.0;
+        }
+
+        let left = self.shake_time / self.shake_duration;
+        self.shake_strength * left * left
+    }
+
+    // Moves towards the target, it only has to be brought back inside the deadzone
+    pub fn follow(&mut self, target: Point<f32>, dt: f32) {
+        let half_x = self.deadzone.x * 0.5 / self.zoom;
+        let half_y = self.deadzone.y * 0.5 / self.zoom;
+
+        let mut desired = self.position;
+        if target.x > self.position.x + half_x {
+            desired.x = target.x - half_x;
+        } else if target.x < self.position.x - half_x {
+            desired.x = target.x + half_x;
+        }
+        if target.y > self.position.y + half_y {
+            desired.y = target.y - half_y;
+        } else if target.y < self.position.y - half_y {
+            desired.y = target.y + half_y;
+        }
+
+        // Frame rate independent easing
+        let amount = if self.follow_speed == 0.0 {
+            1.0
+        } else {
+            1.0 - (-self.follow_speed * dt).exp()
+        };
+
+        self.position.x += (desired.x - self.position.x) * amount;
+        self.position.y += (desired.y - self.position.y) * amount;
+        self.clamp_to_bounds();
+    }
+
+    // Advances the shake, call once a frame
+    pub fn update(&mut self, dt: f32) {
+        self.shake_time = (self.shake_time - dt).max(0.0);
+
+        let strength = self.current_shake();
+        if strength <= 0.0 {
+            self.shake_offset = Point::new(0.0, 0.0);
+            return;
+        }
+
+        // Seeded noise, the same shake always plays out the same way
+        let mut random = || {
+            self.shake_seed ^= self.shake_seed << 13;
+            self.shake_seed ^= self.shake_seed >> 17;
+            self.shake_seed ^= self.shake_seed << 5;
+            self.shake_seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+        };
+
+        self.shake_offset = Point::new(random() * strength, random() * strength);
+    }
+
+    // Half the size of the world area on screen, including what rotation brings into view
+    fn half_extent(&self) -> Point<f32> {
+        let (sin, cos) = self.rotation.sin_cos();
+        let half_x = self.viewport.x * 0.5 / self.zoom;
+        let half_y = self.viewport.y * 0.5 / self.zoom;
+
+        Point::new(
+            half_x * cos.abs() + half_y * sin.abs(),
+            half_x * sin.abs() + half_y * cos.abs(),
+        )
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        if let Some((min, max)) = self.bounds {
+            let half = self.half_extent();
+
+            // Levels smaller than the view stay centered
+            self.position.x = if max.x - min.x <= half.x * 2.0 {
+                (min.x + max.x) * 0.5
+            } else {
+                self.position.x.clamp(min.x + half.x, max.x - half.x)
+            };
+            self.position.y = if max.y - min.y <= half.y * 2.0 {
+                (min.y + max.y) * 0.5
+            } else {
+                self.position.y.clamp(min.y + half.y, max.y - half.y)
+            };
+        }
+    }
+
+    pub fn get_transform(&self) -> CameraTransform {
+        let (sin, cos) = self.rotation.sin_cos();
+
+        CameraTransform {
+            position: self.position,
+            screen_center: Point::new(
+                self.viewport.x * 0.5 + self.shake_offset.x,
+                self.viewport.y * 0.5 + self.shake_offset.y,
+            ),
+            zoom: self.zoom,
+            sin,
+            cos,
+        }
+    }
+
+    pub fn world_to_screen(&self, point: Point<f32>) -> Point<f32> {
+        self.get_transform().world_to_screen(point)
+    }
+
+    pub fn screen_to_world(&self, point: Point<f32>) -> Point<f32> {
+        self.get_transform().screen_to_world(point)
+    }
+
+    // World area that can end up on screen, for culling
+    pub fn visible_area(&self) -> (Point<f32>, Point<f32>) {
+        let half = self.half_extent();
+        let shake = self.current_shake() / self.zoom;
+
+        (
+            Point::new(
+                self.position.x - half.x - shake,
+                self.position.y - half.y - shake,
+            ),
+            Point::new(
+                self.position.x + half.x + shake,
+                self.position.y + half.y + shake,
+            ),
+        )
+    }
+}
@@ -0,0 +1,379 @@
+use crate::input::InputSnapshot;
+
+// Fighting game style input sequences. A combo is a list of steps (stick
+// directions, button presses, charges) where each step has to come within its
+// window of the one before. The detector keeps a short history of what the
+// player did, built from the snapshots, and checks it backwards from the newest
+// input every frame, so stray inputs in between don't break a combo.
+//
+// Directions come from the "move_x" and "move_y" actions. They're written for a
+// player facing right and mirrored when facing left, so Right is forward.
+//
+// Finished combos are kept for a moment so the game can use them when it's
+// ready, a special typed out during the last frames of another attack still
+// comes out after it.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    Neutral,
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    // Down is positive, same as "move_y"
+    pub fn from_axes(x: f32, y: f32) -> Self {
+        let horizontal = if x <= -0.5 {
+            -1
+        } else if x >= 0.5 {
+            1
+        } else {
+            0
+        };
+        let vertical = if y <= -0.5 {
+            -1
+        } else if y >= 0.5 {
+            1
+        } else {
+            0
+        };
+
+        match (horizontal, vertical) {
+            (0, -1) => Direction::Up,
+            (0, 1) => Direction::Down,
+            (-1, 0) => Direction::Left,
+            (1, 0) => Direction::Right,
+            (-1, -1) => Direction::UpLeft,
+            (1, -1) => Direction::UpRight,
+            (-1, 1) => Direction::DownLeft,
+            (1, 1) => Direction::DownRight,
+            _ => Direction::Neutral,
+        }
+    }
+
+    pub fn mirrored(self) -> Self {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::UpRight,
+            Direction::UpRight => Direction::UpLeft,
+            Direction::DownLeft => Direction::DownRight,
+            Direction::DownRight => Direction::DownLeft,
+            other => other,
+        }
+    }
+
+    // If this points the way of one of Up, Down, Left or Right, down left has
+    // both down and left
+    pub fn has(self, component: Direction) -> bool {
+        match component {
+            Direction::Up => matches!(self, Direction::Up | Direction::UpLeft | Direction::UpRight),
+            Direction::Down => matches!(
+                self,
+                Direction::Down | Direction::DownLeft | Direction::DownRight
+            ),
+            Direction::Left => matches!(
+                self,
+                Direction::Left | Direction::UpLeft | Direction::DownLeft
+            ),
+            Direction::Right => matches!(
+                self,
+                Direction::Right | Direction::UpRight | Direction::DownRight
+            ),
+            _ => false,
+        }
+    }
+}
+
+const COMPONENTS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ComboInput {
+    Direction(Direction),              // The stick moving into the direction
+    Press(String),                     // An action being pressed
+    DirectionPress(Direction, String), // Pressed while holding the direction
+    Charge(Direction, f32), // Held the way of the direction for this many seconds, then let go
+}
+
+#[derive(Clone, Debug)]
+pub struct ComboStep {
+    pub input: ComboInput,
+    pub window: f32, // Most seconds since the step before, unused on the first step
+}
+
+#[derive(Clone, Debug)]
+pub struct Combo {
+    name: String,
+    steps: Vec<ComboStep>,
+}
+
+impl Combo {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    // Adds a step that has to come within window seconds of the last one
+    pub fn then(mut self, input: ComboInput, window: f32) -> Self {
+        self.steps.push(ComboStep { input, window });
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_steps(&self) -> &[ComboStep] {
+        &self.steps
+    }
+
+    // How far back the history has to go to see the whole combo
+    fn length(&self) -> f32 {
+        self.steps
+            .iter()
+            .skip(1)
+            .map(|step| step.window)
+            .sum::<f32>()
+            + self
+                .steps
+                .iter()
+                .map(|step| match step.input {
+                    ComboInput::Charge(_, time) => time,
+                    _ => 0.0,
+                })
+                .sum::<f32>()
+    }
+}
+
+#[derive(Clone)]
+enum Record {
+    Direction(Direction),
+    Press(String),
+    Release { component: Direction, held: f32 }, // Stopped holding Up, Down, Left or Right
+}
+
+#[derive(Clone)]
+struct TimedRecord {
+    frame: u64,
+    time: f32,
+    direction: Direction, // What was held at the time
+    record: Record,
+}
+
+impl TimedRecord {
+    fn satisfies(&self, input: &ComboInput) -> bool {
+        match (input, &self.record) {
+            (ComboInput::Direction(wanted), Record::Direction(direction)) => wanted == direction,
+            (ComboInput::Press(wanted), Record::Press(action)) => wanted == action,
+            (ComboInput::DirectionPress(direction, wanted), Record::Press(action)) => {
+                wanted == action && *direction == self.direction
+            }
+            (ComboInput::Charge(direction, time), Record::Release { component, held }) => {
+                direction.has(*component) && held >= time
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ComboDetector {
+    combos: Vec<Combo>,
+    records: Vec<TimedRecord>,
+    direction: Direction,
+    held_since: [Option<f32>; 4], // When each of COMPONENTS started being held
+    frame: u64,
+    time: f32,
+    completed: Vec<String>,
+    buffered: Vec<(String, f32)>, // Combo and when it finished
+    buffer_time: f32,
+}
+
+impl ComboDetector {
+    pub fn new() -> Self {
+        Self {
+            combos: Vec::new(),
+            records: Vec::new(),
+            direction: Direction::Neutral,
+            held_since: [None; 4],
+            frame: 0,
+            time: 0.0,
+            completed: Vec::new(),
+            buffered: Vec::new(),
+            buffer_time: 0.15,
+        }
+    }
+
+    pub fn add_combo(&mut self, combo: Combo) {
+        self.combos.push(combo);
+    }
+
+    // How long a finished combo waits to be used
+    pub fn set_buffer_time(&mut self, seconds: f32) {
+        self.buffer_time = seconds;
+    }
+
+    // Forgets everything, for when the player gets hit or the round restarts
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.held_since = [None; 4];
+        self.completed.clear();
+        self.buffered.clear();
+    }
+
+    // Once a frame after the input update. Returns the combos that finished
+    // this frame, longest first so a special beats the button press at its end.
+    pub fn update(&mut self, snapshot: &InputSnapshot, facing_left: bool, dt: f32) -> &[String] {
+        self.frame += 1;
+        self.time += dt;
+        self.completed.clear();
+
+        let mut direction =
+            Direction::from_axes(snapshot.value("move_x"), snapshot.value("move_y"));
+        if facing_left {
+            direction = direction.mirrored();
+        }
+
+        // Let go of charges first, moving forward ends a back charge on the
+        // same frame as the forward input
+        for (index, component) in COMPONENTS.iter().enumerate() {
+            match (direction.has(*component), self.held_since[index]) {
+                (true, None) => self.held_since[index] = Some(self.time),
+                (false, Some(since)) => {
+                    self.held_since[index] = None;
+                    self.push(
+                        direction,
+                        Record::Release {
+                            component: *component,
+                            held: self.time - since,
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if direction != self.direction {
+            self.direction = direction;
+            self.push(direction, Record::Direction(direction));
+        }
+
+        let mut actions: Vec<&str> = Vec::new();
+        for combo in &self.combos {
+            for step in &combo.steps {
+                if let ComboInput::Press(action) | ComboInput::DirectionPress(_, action) =
+                    &step.input
+                {
+                    if !actions.contains(&action.as_str()) {
+                        actions.push(action);
+                    }
+                }
+            }
+        }
+        let pressed: Vec<String> = actions
+            .into_iter()
+            .filter(|action| snapshot.was_pressed(action))
+            .map(|action| action.to_string())
+            .collect();
+        for action in pressed {
+            self.push(direction, Record::Press(action));
+        }
+
+        // Only keep as much history as the longest combo needs
+        let history = self
+            .combos
+            .iter()
+            .map(|combo| combo.length())
+            .fold(0.0f32, f32::max);
+        let time = self.time;
+        self.records
+            .retain(|record| time - record.time <= history + dt);
+
+        let mut completed: Vec<&Combo> = self
+            .combos
+            .iter()
+            .filter(|combo| self.matches(combo))
+            .collect();
+        completed.sort_by_key(|combo| std::cmp::Reverse(combo.steps.len()));
+        self.completed = completed.iter().map(|combo| combo.name.clone()).collect();
+
+        let buffer_time = self.buffer_time;
+        self.buffered
+            .retain(|(_, finished)| time - finished <= buffer_time);
+        for name in &self.completed {
+            self.buffered.push((name.clone(), time));
+        }
+
+        &self.completed
+    }
+
+    fn push(&mut self, direction: Direction, record: Record) {
+        self.records.push(TimedRecord {
+            frame: self.frame,
+            time: self.time,
+            direction,
+            record,
+        });
+    }
+
+    // Works back from the last step, which has to have happened this frame so a
+    // combo only finishes once. Each earlier step is the newest match before the
+    // step after it, within that step's window.
+    fn matches(&self, combo: &Combo) -> bool {
+        let mut before = self.records.len();
+        let mut next_time = self.time;
+
+        for (index, step) in combo.steps.iter().enumerate().rev() {
+            let window = combo.steps.get(index + 1).map(|next| next.window);
+
+            let found = self.records[..before]
+                .iter()
+                .enumerate()
+                .rev()
+                .take_while(|(_, record)| match window {
+                    Some(window) => next_time - record.time <= window,
+                    None => record.frame == self.frame,
+                })
+                .find(|(_, record)| record.satisfies(&step.input));
+
+            match found {
+                Some((at, record)) => {
+                    before = at;
+                    next_time = record.time;
+                }
+                None => return false,
+            }
+        }
+
+        !combo.steps.is_empty()
+    }
+
+    // The combos that finished on the last update
+    pub fn get_completed(&self) -> &[String] {
+        &self.completed
+    }
+
+    // True if the combo finished within the buffer time and hasn't been used yet
+    pub fn consume(&mut self, name: &str) -> bool {
+        match self.buffered.iter().position(|(combo, _)| combo == name) {
+            Some(index) => {
+                self.buffered.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,16 +1,44 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
 use crate::{
-    math::{Color, Rect},
+    animation::Animator,
+    blit::{Filter, Flip},
+    math::{Color, Point, Rect},
+    render_queue::{DrawOrder, Layer, RenderQueue},
+    sprite_sheet::SpriteSheet,
+    tilemap::Tilemap,
     win32_engine::{Win32Drawable, Win32Engine, Win32GameBitmap, Win32Input},
 };
 
+#[derive(Clone, Copy)]
 pub enum EntityType {
     RECT,
+    SPRITE,
+}
+
+impl EntityType {
+    // Type names used by level files
+    pub fn from_name(name: &str) -> Option<EntityType> {
+        match name.to_lowercase().as_str() {
+            "rect" => Some(EntityType::RECT),
+            "sprite" => Some(EntityType::SPRITE),
+            _ => None,
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Entity {
     rect: Rect,
     ent_type: EntityType,
     color: Color,
+    velocity: Point<i32>,
+    properties: HashMap<String, String>, // Custom values from level files
+    sprite_sheet: Option<Rc<SpriteSheet>>, // Shared between entities using the same art
+    animator: Option<Animator>,
+    facing_left: bool,
+    player: Option<usize>, // Which local player controls it, if any
 }
 
 impl Entity {
@@ -19,51 +47,143 @@ impl Entity {
             rect,
             ent_type,
             color: Color::new(0, 0, 0, 0),
+            velocity: Point::new(0, 0),
+            properties: HashMap::new(),
+            sprite_sheet: None,
+            animator: None,
+            facing_left: false,
+            player: None,
         }
     }
 
-    pub fn input(&mut self, engine: &Win32Engine, input: &mut Win32Input) {
-        // Only process input if the game window has focus
-        if engine.check_focus() {
-            // Input
-            if input.left() {
-                self.rect.x -= 3;
-            }
-            if input.right() {
-                self.rect.x += 3;
-            }
-            if input.up() {
-                self.rect.y -= 3;
-            }
-            if input.down() {
-                self.rect.y += 3;
-            }
+    // Nothing is held while the window doesn't have focus, see Win32Input::update
+    pub fn input(&mut self, input: &Win32Input) {
+        self.velocity = Point::new(0, 0);
+
+        if let Some(player) = self.player.and_then(|player| input.get_player(player)) {
+            self.velocity.x = (player.value("move_x") * 3.0).round() as i32;
+            self.velocity.y = (player.value("move_y") * 3.0).round() as i32;
         }
     }
 
-    pub fn update(&mut self, engine: &Win32Engine) {
-        // Screen collision
-        if self.rect.x >= (engine.get_width() - self.rect.w) + 1 {
-            self.rect.x = engine.get_width() - self.rect.w - 1;
-        }
-        if self.rect.x <= 0 {
-            self.rect.x = 1;
-        }
-        if self.rect.y >= (engine.get_height() - self.rect.h) + 1 {
-            self.rect.y = engine.get_height() - self.rect.h - 1
+    pub fn update(&mut self, tilemap: &Tilemap, dt: f32) {
+        // Tile collision, the map edges count as solid
+        tilemap.move_rect(&mut self.rect, self.velocity.x, self.velocity.y);
+
+        // Sprites face the way they last moved
+        if self.velocity.x != 0 {
+            self.facing_left = self.velocity.x < 0;
         }
-        if self.rect.y == 0 {
-            self.rect.y = 1;
+
+        // Animation state follows movement. Last frame's events go first so the
+        // ones from a clip starting now are still there after the update.
+        if let Some(animator) = &mut self.animator {
+            animator.clear_events();
+            if self.velocity.x != 0 || self.velocity.y != 0 {
+                animator.set_state("walk");
+            } else {
+                animator.set_state("idle");
+            }
+
+            animator.update(dt);
         }
     }
 
     pub fn draw(&self, engine: &Win32Engine, buffer: &mut Win32GameBitmap) {
         match self.ent_type {
             EntityType::RECT => engine.draw_rectangle(&self.color, &self.rect, buffer),
+            EntityType::SPRITE => {
+                if let (Some(sheet), Some(animator)) = (&self.sprite_sheet, &self.animator) {
+                    if let Some(frame) = animator.current_frame() {
+                        // Art is stretched to the entity size and mirrored instead of
+                        // needing left facing frames
+                        let pos = Point::new(self.rect.x as i32, self.rect.y as i32);
+                        let size = Point::new(self.rect.w, self.rect.h);
+                        let flip = if self.facing_left {
+                            Flip::HORIZONTAL
+                        } else {
+                            Flip::NONE
+                        };
+
+                        sheet.draw_frame_scaled(frame, pos, size, flip, Filter::Nearest, buffer);
+                    }
+                }
+            }
+        }
+    }
+
+    // Same as draw but queued, sorted by the bottom edge so entities lower on
+    // screen overlap the ones behind them
+    pub fn queue_draw<'a>(&'a self, queue: &mut RenderQueue<'a>) {
+        let pos = Point::new(self.rect.x as i32, self.rect.y as i32);
+        let size = Point::new(self.rect.w, self.rect.h);
+        let order = DrawOrder::new(Layer::Entities, (self.rect.y + self.rect.h) as f32);
+
+        match self.ent_type {
+            // draw_rectangle ignores alpha, keep it that way
+            EntityType::RECT => {
+                let color = Color::from_u32(self.color.to_u32() | 0xFF000000);
+                queue.push_rect(order, pos, size, &color);
+            }
+            EntityType::SPRITE => {
+                if let (Some(sheet), Some(animator)) = (&self.sprite_sheet, &self.animator) {
+                    if let Some(frame) = animator.current_frame() {
+                        let flip = if self.facing_left {
+                            Flip::HORIZONTAL
+                        } else {
+                            Flip::NONE
+                        };
+
+                        queue.push_sprite(order, sheet, frame, pos, size, flip);
+                    }
+                }
+            }
         }
     }
 
+    pub fn get_rect(&self) -> &Rect {
+        &self.rect
+    }
+
     pub fn get_type(&self) -> &EntityType {
         &self.ent_type
     }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn set_player(&mut self, player: Option<usize>) {
+        self.player = player;
+    }
+
+    pub fn get_player(&self) -> Option<usize> {
+        self.player
+    }
+
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        self.properties.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn get_property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| value.as_str())
+    }
+
+    // Animator clips index frames of this sprite sheet
+    pub fn set_sprite(&mut self, sprite_sheet: Rc<SpriteSheet>, animator: Animator) {
+        self.sprite_sheet = Some(sprite_sheet);
+        self.animator = Some(animator);
+    }
+
+    pub fn is_facing_left(&self) -> bool {
+        self.facing_left
+    }
+
+    pub fn get_animator(&self) -> Option<&Animator> {
+        self.animator.as_ref()
+    }
+
+    pub fn get_animator_mut(&mut self) -> Option<&mut Animator> {
+        self.animator.as_mut()
+    }
 }
@@ -1,8 +1,12 @@
 use crate::{
     entity::{self, Entity},
+    math::{Color, Rect},
+    render_queue::RenderQueue,
+    tilemap::Tilemap,
     win32_engine::{Win32Engine, Win32GameBitmap, Win32Input},
 };
 
+#[derive(Clone)]
 pub struct EntityManager {
     entities: Vec<Entity>,
 }
@@ -18,18 +22,96 @@ impl EntityManager {
         self.entities.push(entity);
     }
 
-    pub fn input(&mut self, engine: &Win32Engine, input: &mut Win32Input) {
+    // Creates an entity from its type name, used when loading levels.
+    // Returns false if nothing was spawned.
+    pub fn spawn(&mut self, type_name: &str, rect: Rect, properties: &[(String, String)]) -> bool {
+        let ent_type = match entity::EntityType::from_name(type_name) {
+            Some(ent_type) => ent_type,
+            None => {
+                println!("Unknown entity type '{}', skipping it.", type_name);
+                return false;
+            }
+        };
+
+        let mut entity = Entity::new(rect, ent_type);
+
+        for (name, value) in properties {
+            if name == "color" {
+                if let Some(color) = Color::from_hex(value) {
+                    entity.set_color(color);
+                }
+            }
+
+            // Local player index, starting at 0
+            if name == "player" {
+                entity.set_player(value.parse().ok());
+            }
+
+            entity.set_property(name, value);
+        }
+
+        self.create(entity);
+
+        true
+    }
+
+    // Finds an entity by its "name" property
+    pub fn find(&self, name: &str) -> Option<&Entity> {
+        self.entities
+            .iter()
+            .find(|entity| entity.get_property("name") == Some(name))
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Entity> {
+        self.entities
+            .iter_mut()
+            .find(|entity| entity.get_property("name") == Some(name))
+    }
+
+    pub fn input(&mut self, input: &Win32Input) {
         for entity in &mut self.entities {
             // Only allow input depending on the type
             match entity.get_type() {
-                entity::EntityType::RECT => entity.input(engine, input),
+                entity::EntityType::RECT => entity.input(input),
+                entity::EntityType::SPRITE => entity.input(input),
             }
         }
     }
 
-    pub fn update(&mut self, engine: &Win32Engine) {
+    // Hash of where everything is and what it's showing, for checking a replay
+    // ends up the same
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+
+        for entity in &self.entities {
+            let rect = entity.get_rect();
+            for value in &[rect.x, rect.y, rect.w, rect.h] {
+                add(&value.to_le_bytes());
+            }
+            add(&[entity.is_facing_left() as u8]);
+
+            if let Some(animator) = entity.get_animator() {
+                add(animator.get_state().as_bytes());
+                let frame = animator
+                    .current_frame()
+                    .map_or(u64::MAX, |frame| frame as u64);
+                add(&frame.to_le_bytes());
+            }
+        }
+
+        hash
+    }
+
+    pub fn update(&mut self, tilemap: &Tilemap, dt: f32) {
         for entity in &mut self.entities {
-            entity.update(engine);
+            entity.update(tilemap, dt);
         }
     }
 
@@ -38,4 +120,10 @@ impl EntityManager {
             entity.draw(engine, buffer);
         }
     }
+
+    pub fn queue_draw<'a>(&'a self, queue: &mut RenderQueue<'a>) {
+        for entity in &self.entities {
+            entity.queue_draw(queue);
+        }
+    }
 }
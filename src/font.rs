@@ -0,0 +1,823 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::{
+    math::{Point, Rect},
+    simd,
+    win32_engine::Win32GameBitmap,
+};
+
+// Text drawing. A Font only has to know its glyphs, drawing whole strings,
+// kerning, measuring, alignment and wrapping are shared.
+//
+// Two fonts come with the engine: BuiltinFont, a 5x7 monospace font that's
+// compiled in so there's always something to print with, and BitmapFont, which
+// loads AngelCode BMFont .fnt files (text or binary) made with tools like
+// Hiero or bmfont. TrueType fonts are in ttf.rs.
+
+// Where a glyph draws relative to the pen (top of the line) and how far it moves the pen
+#[derive(Clone, Copy)]
+pub struct Glyph {
+    pub offset: Point<i32>,
+    pub size: Point<u32>,
+    pub advance: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+// Anything that can draw a line of text. Text goes through render groups and
+// queues like everything else, so fonts are drawn from the render workers.
+pub trait Font: Sync {
+    fn line_height(&self) -> u32;
+
+    // None for characters the font doesn't have
+    fn glyph(&self, c: char) -> Option<Glyph>;
+
+    // Extra pen movement between a pair of characters
+    fn kerning(&self, _first: char, _second: char) -> i32 {
+        0
+    }
+
+    // pos is the pen position, nothing outside clip gets touched
+    fn draw_glyph(
+        &self,
+        c: char,
+        pos: Point<i32>,
+        color: u32,
+        clip: &Rect,
+        buffer: &mut Win32GameBitmap,
+    );
+
+    // pos is the top left corner of the text, color is 0xAARRGGBB. Handles
+    // newlines, characters the font doesn't have are drawn as '?'.
+    fn draw_text(&self, text: &str, pos: Point<i32>, color: u32, buffer: &mut Win32GameBitmap) {
+        let (min, max) = self.ink_bounds(text);
+        let lower_x = pos.x.saturating_add(min.x).max(0);
+        let lower_y = pos.y.saturating_add(min.y).max(0);
+        let upper_x = pos.x.saturating_add(max.x).min(buffer.get_width());
+        let upper_y = pos.y.saturating_add(max.y).min(buffer.get_height());
+        if lower_x >= upper_x || lower_y >= upper_y {
+            return;
+        }
+        let clip = Rect::new(
+            lower_x as u32,
+            lower_y as u32,
+            (upper_x - lower_x) as u32,
+            (upper_y - lower_y) as u32,
+        );
+
+        layout_pen(self, text, |c, pen| {
+            self.draw_glyph(
+                c,
+                Point::new(pos.x + pen.x, pos.y + pen.y),
+                color,
+                &clip,
+                buffer,
+            )
+        });
+    }
+
+    // Layout size in pixels, for aligning and wrapping. Glyphs can still poke
+    // out of it on any side, see ink_bounds.
+    fn measure(&self, text: &str) -> Point<u32> {
+        let mut size = Point::new(0, 0);
+        let mut lines = 1;
+
+        layout_pen(self, text, |c, pen| {
+            if c == '\n' {
+                lines += 1;
+            } else if let Some(glyph) = self.glyph(c) {
+                let right = pen.x + (glyph.offset.x + glyph.size.x as i32).max(glyph.advance);
+                let bottom = pen.y + glyph.offset.y + glyph.size.y as i32;
+                size.x = size.x.max(right);
+                size.y = size.y.max(bottom);
+            }
+        });
+
+        Point::new(
+            size.x.max(0) as u32,
+            (size.y.max(0) as u32).max(lines * self.line_height()),
+        )
+    }
+
+    // Pixels draw_text can touch relative to pos, min inclusive and max
+    // exclusive. Offsets and bearings can put ink left of or above pos, and
+    // descenders below the last line.
+    fn ink_bounds(&self, text: &str) -> (Point<i32>, Point<i32>) {
+        let mut min = Point::new(i32::MAX, i32::MAX);
+        let mut max = Point::new(i32::MIN, i32::MIN);
+
+        layout_pen(self, text, |c, pen| {
+            let glyph = match self.glyph(c) {
+                Some(glyph) if c != '\n' && glyph.size.x > 0 && glyph.size.y > 0 => glyph,
+                _ => return,
+            };
+
+            let left = pen.x.saturating_add(glyph.offset.x);
+            let top = pen.y.saturating_add(glyph.offset.y);
+            min.x = min.x.min(left);
+            min.y = min.y.min(top);
+            max.x = max.x.max(left.saturating_add(glyph.size.x as i32));
+            max.y = max.y.max(top.saturating_add(glyph.size.y as i32));
+        });
+
+        if min.x >= max.x || min.y >= max.y {
+            (Point::new(0, 0), Point::new(0, 0))
+        } else {
+            (min, max)
+        }
+    }
+}
+
+// Walks the text calling back with every character and the pen position it's
+// drawn at. Newlines are passed through so measuring can count them.
+fn layout_pen<F: Font + ?Sized>(font: &F, text: &str, mut callback: impl FnMut(char, Point<i32>)) {
+    let mut pen = Point::new(0, 0);
+    let mut previous = None;
+
+    for c in text.chars() {
+        if c == '\n' {
+            callback(c, pen);
+            pen = Point::new(0, pen.y + font.line_height() as i32);
+            previous = None;
+            continue;
+        }
+
+        if let Some(previous) = previous {
+            pen.x += font.kerning(previous, c);
+        }
+
+        let drawn = if font.glyph(c).is_some() { c } else { '?' };
+        if let Some(glyph) = font.glyph(drawn) {
+            callback(drawn, pen);
+            pen.x += glyph.advance;
+        }
+        previous = Some(c);
+    }
+}
+
+// Breaks text into lines no wider than max_width, at spaces where it can.
+// Words longer than a whole line get split.
+pub fn wrap_text(font: &dyn Font, text: &str, max_width: u32) -> Vec<String> {
+    let fits = |line: &str| font.measure(line).x <= max_width;
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+
+            if fits(&candidate) {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(line);
+            }
+            line = String::new();
+
+            // Split the word wherever it runs out of room, at least one character a line
+            for c in word.chars() {
+                line.push(c);
+                if !fits(&line) && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(line);
+                    line = c.to_string();
+                }
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Wraps the text to the rect and lines it up inside it, returns every line with
+// where it goes. Lines that don't fit under the rect are dropped.
+pub fn layout_text(
+    font: &dyn Font,
+    text: &str,
+    rect: &Rect,
+    align: TextAlign,
+) -> Vec<(String, Point<i32>)> {
+    let line_height = font.line_height() as i32;
+    let max_lines = (rect.h as i32 / line_height.max(1)) as usize;
+
+    wrap_text(font, text, rect.w)
+        .into_iter()
+        .take(max_lines)
+        .enumerate()
+        .map(|(index, line)| {
+            let spare = rect.w as i32 - font.measure(&line).x as i32;
+            let x = match align {
+                TextAlign::Left => 0,
+                TextAlign::Center => spare / 2,
+                TextAlign::Right => spare,
+            };
+
+            let pos = Point::new(
+                rect.x as i32 + x,
+                rect.y as i32 + index as i32 * line_height,
+            );
+            (line, pos)
+        })
+        .collect()
+}
+
+pub fn draw_text_in_rect(
+    font: &dyn Font,
+    text: &str,
+    rect: &Rect,
+    align: TextAlign,
+    color: u32,
+    buffer: &mut Win32GameBitmap,
+) {
+    for (line, pos) in layout_text(font, text, rect, align) {
+        font.draw_text(&line, pos, color, buffer);
+    }
+}
+
+/*
+    Builtin font
+*/
+
+// Printable ascii from ' ' to '~'. A row per byte with the leftmost pixel in
+// bit 4, the last row is for descenders.
+const BUILTIN_GLYPHS: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04, 0x00], // '!'
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A, 0x00], // '#'
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04, 0x00], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03, 0x00], // '%'
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D, 0x00], // '&'
+    [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02, 0x00], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08, 0x00], // ')'
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00, 0x00], // '/'
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E, 0x00], // '0'
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // '1'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F, 0x00], // '2'
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E, 0x00], // '3'
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02, 0x00], // '4'
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E, 0x00], // '5'
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E, 0x00], // '6'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08, 0x00], // '7'
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E, 0x00], // '8'
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08, 0x00], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02, 0x00], // '<'
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08, 0x00], // '>'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04, 0x00], // '?'
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E, 0x00], // '@'
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x00], // 'A'
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E, 0x00], // 'B'
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E, 0x00], // 'C'
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C, 0x00], // 'D'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F, 0x00], // 'E'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10, 0x00], // 'F'
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F, 0x00], // 'G'
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00], // 'H'
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C, 0x00], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11, 0x00], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F, 0x00], // 'L'
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11, 0x00], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x00], // 'N'
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // 'O'
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10, 0x00], // 'P'
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D, 0x00], // 'Q'
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11, 0x00], // 'R'
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E, 0x00], // 'S'
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A, 0x00], // 'W'
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11, 0x00], // 'X'
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x00], // 'Y'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F, 0x00], // 'Z'
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E, 0x00], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00, 0x00], // '\\'
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E, 0x00], // ']'
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F, 0x00], // '_'
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F, 0x00], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E, 0x00], // 'b'
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E, 0x00], // 'c'
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F, 0x00], // 'd'
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E, 0x00], // 'e'
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08, 0x00], // 'f'
+    [0x00, 0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // 'h'
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E, 0x00], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x02, 0x12, 0x0C], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12, 0x00], // 'k'
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // 'l'
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11, 0x00], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // 'n'
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E, 0x00], // 'o'
+    [0x00, 0x00, 0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10], // 'p'
+    [0x00, 0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x01], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10, 0x00], // 'r'
+    [0x00, 0x00, 0x0F, 0x10, 0x0E, 0x01, 0x1E, 0x00], // 's'
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06, 0x00], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D, 0x00], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A, 0x00], // 'w'
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x00], // 'x'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 'y'
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F, 0x00], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02, 0x00], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08, 0x00], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00, 0x00], // '~'
+];
+
+const BUILTIN_GLYPH_WIDTH: u32 = 5;
+const BUILTIN_GLYPH_HEIGHT: u32 = 8;
+
+// Monospace, every glyph is 6 pixels wide and lines are 9 pixels, times the scale
+pub struct BuiltinFont {
+    scale: u32,
+}
+
+impl BuiltinFont {
+    pub fn new(scale: u32) -> Self {
+        Self {
+            scale: scale.max(1),
+        }
+    }
+
+    fn rows(c: char) -> Option<&'static [u8; 8]> {
+        match c {
+            ' '..='~' => Some(&BUILTIN_GLYPHS[c as usize - ' ' as usize]),
+            _ => None,
+        }
+    }
+}
+
+impl Font for BuiltinFont {
+    fn line_height(&self) -> u32 {
+        (BUILTIN_GLYPH_HEIGHT + 1) * self.scale
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        BuiltinFont::rows(c).map(|_| Glyph {
+            offset: Point::new(0, 0),
+            size: Point::new(
+                BUILTIN_GLYPH_WIDTH * self.scale,
+                BUILTIN_GLYPH_HEIGHT * self.scale,
+            ),
+            advance: ((BUILTIN_GLYPH_WIDTH + 1) * self.scale) as i32,
+        })
+    }
+
+    fn draw_glyph(
+        &self,
+        c: char,
+        pos: Point<i32>,
+        color: u32,
+        clip: &Rect,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        let rows = match BuiltinFont::rows(c) {
+            Some(rows) => rows,
+            None => return,
+        };
+        let scale = self.scale as i32;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..BUILTIN_GLYPH_WIDTH as i32 {
+                if bits >> (4 - column) & 1 == 0 {
+                    continue;
+                }
+
+                // Every font pixel is a scale x scale block
+                for y in 0..scale {
+                    for x in 0..scale {
+                        let x = pos.x + column * scale + x;
+                        let y = pos.y + row as i32 * scale + y;
+                        if inside(clip, x, y) {
+                            buffer.blend_pixel(x, y, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn inside(clip: &Rect, x: i32, y: i32) -> bool {
+    x >= clip.x as i32
+        && y >= clip.y as i32
+        && x < (clip.x + clip.w) as i32
+        && y < (clip.y + clip.h) as i32
+}
+
+/*
+    BMFont
+*/
+
+struct BitmapGlyph {
+    source: Rect, // In the page texture
+    offset: Point<i32>,
+    advance: i32,
+    page: usize,
+    channel: u8, // 1 blue, 2 green, 4 red, 8 alpha, 15 all of them
+}
+
+struct BitmapPage {
+    texture: Win32GameBitmap,
+    opaque: bool, // No alpha channel, the glyphs are white on black
+}
+
+pub struct BitmapFont {
+    line_height: u32,
+    base: u32, // Pixels from the top of the line to the baseline
+    pages: Vec<BitmapPage>,
+    glyphs: HashMap<u32, BitmapGlyph>,
+    kerning: HashMap<(u32, u32), i32>,
+}
+
+impl BitmapFont {
+    // Binary files start with "BMF", anything else is read as the text format.
+    // Page images are looked up next to the .fnt file.
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+
+        let mut font = BitmapFont {
+            line_height: 0,
+            base: 0,
+            pages: Vec::new(),
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+        };
+
+        let page_files = if bytes.starts_with(b"BMF") {
+            font.read_binary(&bytes)?
+        } else {
+            let text = String::from_utf8_lossy(&bytes);
+            font.read_text(&text)?
+        };
+
+        let directory = Path::new(file_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+
+        for page_file in page_files {
+            let path = directory.join(&page_file);
+            if !path.exists() {
+                return Err(format!("Font page {} doesn't exist", path.display()));
+            }
+
+            let texture = Win32GameBitmap::try_load_image(&path.to_string_lossy())?;
+            let opaque = (0..texture.get_height())
+                .all(|y| texture.row(y).iter().all(|pixel| pixel >> 24 == 0xFF));
+
+            font.pages.push(BitmapPage { texture, opaque });
+        }
+
+        for glyph in font.glyphs.values() {
+            if glyph.page >= font.pages.len() {
+                return Err(format!(
+                    "Glyph uses page {} which doesn't exist",
+                    glyph.page
+                ));
+            }
+        }
+
+        Ok(font)
+    }
+
+    pub fn get_base(&self) -> u32 {
+        self.base
+    }
+
+    // Returns the page file names by page id
+    fn read_text(&mut self, text: &str) -> Result<Vec<String>, String> {
+        let mut page_files = Vec::new();
+
+        for line in text.lines() {
+            let (tag, fields) = parse_tag(line);
+            let field = |name: &str| -> i32 {
+                fields
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .and_then(|(_, value)| value.parse().ok())
+                    .unwrap_or(0)
+            };
+
+            match tag {
+                "common" => {
+                    self.line_height = field("lineHeight").max(0) as u32;
+                    self.base = field("base").max(0) as u32;
+                }
+                "page" => {
+                    let id = field("id").max(0) as usize;
+                    let file = fields
+                        .iter()
+                        .find(|(key, _)| key == "file")
+                        .map(|(_, value)| value.clone())
+                        .ok_or_else(|| "Font page has no file".to_string())?;
+
+                    if page_files.len() <= id {
+                        page_files.resize(id + 1, String::new());
+                    }
+                    page_files[id] = file;
+                }
+                "char" => {
+                    self.glyphs.insert(
+                        field("id") as u32,
+                        BitmapGlyph {
+                            source: Rect::new(
+                                field("x").max(0) as u32,
+                                field("y").max(0) as u32,
+                                field("width").max(0) as u32,
+                                field("height").max(0) as u32,
+                            ),
+                            offset: Point::new(field("xoffset"), field("yoffset")),
+                            advance: field("xadvance"),
+                            page: field("page").max(0) as usize,
+                            channel: field("chnl") as u8,
+                        },
+                    );
+                }
+                "kerning" => {
+                    self.kerning.insert(
+                        (field("first") as u32, field("second") as u32),
+                        field("amount"),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if self.line_height == 0 {
+            return Err("Font has no common line".to_string());
+        }
+
+        Ok(page_files)
+    }
+
+    fn read_binary(&mut self, bytes: &[u8]) -> Result<Vec<String>, String> {
+        if bytes.len() < 4 || bytes[3] != 3 {
+            return Err("Only version 3 binary fonts are supported".to_string());
+        }
+
+        let u16_at = |data: &[u8], at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let u32_at = |data: &[u8], at: usize| {
+            u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+        };
+
+        let mut page_files = Vec::new();
+        let mut at = 4;
+
+        // Blocks are a type byte, a u32 size and the data
+        while at < bytes.len() {
+            if at + 5 > bytes.len() {
+                return Err("Font block header is cut off".to_string());
+            }
+            let block_type = bytes[at];
+            let size = u32_at(bytes, at + 1) as usize;
+            let data = bytes
+                .get(at + 5..at + 5 + size)
+                .ok_or_else(|| "Font block is cut off".to_string())?;
+            at += 5 + size;
+
+            match block_type {
+                2 => {
+                    if data.len() < 4 {
+                        return Err("Font common block is too short".to_string());
+                    }
+                    self.line_height = u16_at(data, 0) as u32;
+                    self.base = u16_at(data, 2) as u32;
+                }
+                3 => {
+                    page_files = data
+                        .split(|&byte| byte == 0)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                        .collect();
+                }
+                4 => {
+                    for char_data in data.chunks_exact(20) {
+                        self.glyphs.insert(
+                            u32_at(char_data, 0),
+                            BitmapGlyph {
+                                source: Rect::new(
+                                    u16_at(char_data, 4) as u32,
+                                    u16_at(char_data, 6) as u32,
+                                    u16_at(char_data, 8) as u32,
+                                    u16_at(char_data, 10) as u32,
+                                ),
+                                offset: Point::new(
+                                    u16_at(char_data, 12) as i16 as i32,
+                                    u16_at(char_data, 14) as i16 as i32,
+                                ),
+                                advance: u16_at(char_data, 16) as i16 as i32,
+                                page: char_data[18] as usize,
+                                channel: char_data[19],
+                            },
+                        );
+                    }
+                }
+                5 => {
+                    for pair in data.chunks_exact(10) {
+                        self.kerning.insert(
+                            (u32_at(pair, 0), u32_at(pair, 4)),
+                            u16_at(pair, 8) as i16 as i32,
+                        );
+                    }
+                }
+                _ => {} // Info block
+            }
+        }
+
+        if self.line_height == 0 {
+            return Err("Font has no common block".to_string());
+        }
+
+        Ok(page_files)
+    }
+}
+
+// Splits `tag key=value key="quoted value"` into the tag and its fields
+fn parse_tag(line: &str) -> (&str, Vec<(String, String)>) {
+    let line = line.trim();
+    let (tag, mut rest) = match line.find(' ') {
+        Some(space) => (&line[..space], &line[space..]),
+        None => (line, ""),
+    };
+
+    let mut fields = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        let equals = match rest.find('=') {
+            Some(equals) => equals,
+            None => break,
+        };
+        let key = rest[..equals].trim().to_string();
+        rest = &rest[equals + 1..];
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            rest = quoted.get(end + 1..).unwrap_or("");
+            quoted[..end].to_string()
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            let value = rest[..end].to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        fields.push((key, value));
+    }
+
+    (tag, fields)
+}
+
+impl Font for BitmapFont {
+    fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        self.glyphs.get(&(c as u32)).map(|glyph| Glyph {
+            offset: glyph.offset,
+            size: Point::new(glyph.source.w, glyph.source.h),
+            advance: glyph.advance,
+        })
+    }
+
+    fn kerning(&self, first: char, second: char) -> i32 {
+        self.kerning
+            .get(&(first as u32, second as u32))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn draw_glyph(
+        &self,
+        c: char,
+        pos: Point<i32>,
+        color: u32,
+        clip: &Rect,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        let glyph = match self.glyphs.get(&(c as u32)) {
+            Some(glyph) => glyph,
+            None => return,
+        };
+        let page = &self.pages[glyph.page];
+
+        // Packed fonts keep a glyph in one channel
+        let channel = match glyph.channel {
+            1 | 2 | 4 | 8 => glyph.channel,
+            _ if page.opaque => 4,
+            _ => 15,
+        };
+
+        let pos = Point::new(pos.x + glyph.offset.x, pos.y + glyph.offset.y);
+        draw_glyph_texture(
+            &page.texture,
+            &glyph.source,
+            pos,
+            channel,
+            color,
+            clip,
+            buffer,
+        );
+    }
+}
+
+// Blits a glyph out of a font texture at pos (its top left corner), tinted by
+// color. channel is where the coverage is, 1 blue, 2 green, 4 red or 8 alpha.
+// Anything else reads the alpha and tints with the texel color too, that's a
+// plain tinted blit and goes through the simd row kernels like sprites do.
+pub fn draw_glyph_texture(
+    texture: &Win32GameBitmap,
+    source: &Rect,
+    pos: Point<i32>,
+    channel: u8,
+    color: u32,
+    clip: &Rect,
+    buffer: &mut Win32GameBitmap,
+) {
+    // Keep the source inside the texture
+    let source_w = (source.w as i32).min(texture.get_width() - source.x as i32);
+    let source_h = (source.h as i32).min(texture.get_height() - source.y as i32);
+
+    let lower_x = pos.x.max(clip.x as i32).max(0);
+    let lower_y = pos.y.max(clip.y as i32).max(0);
+    let upper_x = (pos.x + source_w)
+        .min((clip.x + clip.w) as i32)
+        .min(buffer.get_width());
+    let upper_y = (pos.y + source_h)
+        .min((clip.y + clip.h) as i32)
+        .min(buffer.get_height());
+
+    if lower_x >= upper_x {
+        return;
+    }
+
+    if !matches!(channel, 1 | 2 | 4 | 8) {
+        let first_x = (source.x as i32 + lower_x - pos.x) as usize;
+        let width = (upper_x - lower_x) as usize;
+
+        for y in lower_y..upper_y {
+            let source_row = &texture.row(source.y as i32 + y - pos.y)[first_x..first_x + width];
+            simd::blend_tinted(
+                &mut buffer.row_mut(y)[lower_x as usize..upper_x as usize],
+                source_row,
+                color,
+            );
+        }
+        return;
+    }
+
+    for y in lower_y..upper_y {
+        for x in lower_x..upper_x {
+            let texel = texture.get_pixel(source.x as i32 + x - pos.x, source.y as i32 + y - pos.y);
+
+            let (coverage, tint) = match channel {
+                1 => (texel & 0xFF, 0xFFFFFF),
+                2 => (texel >> 8 & 0xFF, 0xFFFFFF),
+                4 => (texel >> 16 & 0xFF, 0xFFFFFF),
+                8 => (texel >> 24, 0xFFFFFF),
+                _ => (texel >> 24, texel & 0xFFFFFF),
+            };
+
+            let alpha = multiply(color >> 24, coverage);
+            let red = multiply(color >> 16 & 0xFF, tint >> 16 & 0xFF);
+            let green = multiply(color >> 8 & 0xFF, tint >> 8 & 0xFF);
+            let blue = multiply(color & 0xFF, tint & 0xFF);
+
+            buffer.blend_pixel(x, y, alpha << 24 | red << 16 | green << 8 | blue);
+        }
+    }
+}
+
+fn multiply(a: u32, b: u32) -> u32 {
+    (a * b + 127) / 255
+}
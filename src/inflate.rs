@@ -0,0 +1,309 @@
+// DEFLATE decoder (RFC 1951) with zlib and gzip wrappers.
+// Written in the spirit of zlib's puff.c, small and slow-ish but plenty for
+// level files and textures loaded once at startup.
+
+const MAX_BITS: usize = 15;
+
+// Base lengths and extra bits for length codes 257..285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// Base distances and extra bits for distance codes 0..29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Order the code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or("Unexpected end of deflate data")?;
+            self.pos += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let result = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+
+        Ok(result)
+    }
+
+    // Stored blocks start on a byte boundary
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+// Canonical huffman table, counts of each code length and symbols sorted by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0; // bits read so far
+        let mut first: i32 = 0; // first code of the current length
+        let mut index: i32 = 0; // index of the first code of the current length in symbols
+
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err("Invalid huffman code in deflate data".to_string())
+    }
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+
+        if symbol < 256 {
+            output.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err("Invalid length code in deflate data".to_string());
+            }
+            let length =
+                LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+            let symbol = distances.decode(reader)? as usize;
+            if symbol >= DIST_BASE.len() {
+                return Err("Invalid distance code in deflate data".to_string());
+            }
+            let distance =
+                DIST_BASE[symbol] as usize + reader.bits(DIST_EXTRA[symbol] as u32)? as usize;
+
+            if distance > output.len() {
+                return Err("Deflate distance goes back too far".to_string());
+            }
+
+            // Byte by byte since the copy can overlap itself
+            let start = output.len() - distance;
+            for i in 0..length {
+                let byte = output[start + i];
+                output.push(byte);
+            }
+        }
+    }
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let length_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&code_lengths);
+
+    // Literal/length and distance code lengths are run length encoded together
+    let mut lengths = vec![0u8; length_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_table.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if index == 0 {
+                    return Err("Deflate repeat with no previous length".to_string());
+                }
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+
+        if index + repeat > lengths.len() {
+            return Err("Too many code lengths in deflate data".to_string());
+        }
+        for length in lengths.iter_mut().skip(index).take(repeat) {
+            *length = value;
+        }
+        index += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..length_count]),
+        Huffman::new(&lengths[length_count..]),
+    ))
+}
+
+// Raw deflate stream with no header
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let last_block = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let pos = reader.pos;
+                if pos + 4 > data.len() {
+                    return Err("Unexpected end of deflate data".to_string());
+                }
+
+                let length = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+                let inv_length = u16::from_le_bytes([data[pos + 2], data[pos + 3]]) as usize;
+                if length != !inv_length & 0xFFFF {
+                    return Err("Corrupt stored block in deflate data".to_string());
+                }
+                if pos + 4 + length > data.len() {
+                    return Err("Unexpected end of deflate data".to_string());
+                }
+
+                output.extend_from_slice(&data[pos + 4..pos + 4 + length]);
+                reader.pos = pos + 4 + length;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                for (symbol, length) in lengths.iter_mut().enumerate() {
+                    *length = match symbol {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        _ => 8,
+                    };
+                }
+
+                let fixed_lengths = Huffman::new(&lengths);
+                let fixed_distances = Huffman::new(&[5u8; 30]);
+                inflate_block(&mut reader, &mut output, &fixed_lengths, &fixed_distances)?;
+            }
+            2 => {
+                let (lengths, distances) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &lengths, &distances)?;
+            }
+            _ => return Err("Invalid deflate block type".to_string()),
+        }
+
+        if last_block {
+            return Ok(output);
+        }
+    }
+}
+
+// Two byte header, deflate stream, then an adler32 we don't bother checking
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 2 || data[0] & 0x0F != 8 || (data[0] as u16 * 256 + data[1] as u16) % 31 != 0 {
+        return Err("Invalid zlib header".to_string());
+    }
+    if data[1] & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".to_string());
+    }
+
+    inflate(&data[2..])
+}
+
+pub fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 18 || data[0] != 0x1F || data[1] != 0x8B || data[2] != 8 {
+        return Err("Invalid gzip header".to_string());
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+
+    // Optional extra field, file name, comment and header crc
+    if flags & 0x04 != 0 {
+        pos += 2 + u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+    }
+    for flag in [0x08, 0x10].iter() {
+        if flags & flag != 0 {
+            while pos < data.len() && data[pos] != 0 {
+                pos += 1;
+            }
+            pos += 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        pos += 2;
+    }
+
+    if pos >= data.len() {
+        return Err("Invalid gzip header".to_string());
+    }
+
+    inflate(&data[pos..])
+}
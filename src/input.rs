@@ -0,0 +1,1163 @@
+use std::collections::HashMap;
+
+use crate::camera::Camera2D;
+use crate::math::Point;
+
+use winapi::um::winuser::{
+    MapVirtualKeyW, MAPVK_VSC_TO_VK, VK_BACK, VK_CONTROL, VK_DOWN, VK_ESCAPE, VK_LEFT, VK_RETURN,
+    VK_RIGHT, VK_SPACE, VK_UP,
+};
+use winapi::um::xinput::{
+    XINPUT_GAMEPAD_A, XINPUT_GAMEPAD_B, XINPUT_GAMEPAD_BACK, XINPUT_GAMEPAD_DPAD_DOWN,
+    XINPUT_GAMEPAD_DPAD_LEFT, XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP,
+    XINPUT_GAMEPAD_LEFT_SHOULDER, XINPUT_GAMEPAD_LEFT_THUMB, XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE,
+    XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_RIGHT_THUMB, XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE,
+    XINPUT_GAMEPAD_START, XINPUT_GAMEPAD_TRIGGER_THRESHOLD, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y,
+};
+
+// Gameplay asks for actions by name ("move_x", "jump") instead of keys and
+// buttons. An InputMap binds every action to any number of sources, and an
+// InputDevice says what those sources are doing right now.
+//
+// Every action has a value from -1 to 1, the sum of its bindings. Buttons are 0
+// or 1 times the binding's scale, so "move_x" can be left arrow at -1 and right
+// arrow at 1. Button style actions are down past half way.
+//
+// Bindings can be saved to and loaded from a config file, one section per
+// action with a line per binding:
+//
+//     [move_x]
+//     Key A = -1
+//     Key D = 1
+//     Pad DPadLeft = -1
+//     Axis LeftX = 1
+//
+// Sources are "Key <name>", "Mouse Left/Right/Middle", "Pad <button>" or
+// "Axis <axis>". Keys without a name are written as hex virtual key codes.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY, // Up is positive, like XInput
+    RightX,
+    RightY,
+    LeftTrigger, // 0 to 1
+    RightTrigger,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputSource {
+    Key(i32), // Virtual key code
+    Mouse(MouseButton),
+    GamepadButton(u16), // XINPUT_GAMEPAD_* bit
+    GamepadAxis(GamepadAxis),
+}
+
+const MOUSE_BUTTONS: [(MouseButton, &str); 3] = [
+    (MouseButton::Left, "Left"),
+    (MouseButton::Right, "Right"),
+    (MouseButton::Middle, "Middle"),
+];
+
+const GAMEPAD_BUTTONS: [(u16, &str); 14] = [
+    (XINPUT_GAMEPAD_DPAD_UP, "DPadUp"),
+    (XINPUT_GAMEPAD_DPAD_DOWN, "DPadDown"),
+    (XINPUT_GAMEPAD_DPAD_LEFT, "DPadLeft"),
+    (XINPUT_GAMEPAD_DPAD_RIGHT, "DPadRight"),
+    (XINPUT_GAMEPAD_START, "Start"),
+    (XINPUT_GAMEPAD_BACK, "Back"),
+    (XINPUT_GAMEPAD_LEFT_THUMB, "LeftThumb"),
+    (XINPUT_GAMEPAD_RIGHT_THUMB, "RightThumb"),
+    (XINPUT_GAMEPAD_LEFT_SHOULDER, "LeftShoulder"),
+    (XINPUT_GAMEPAD_RIGHT_SHOULDER, "RightShoulder"),
+    (XINPUT_GAMEPAD_A, "A"),
+    (XINPUT_GAMEPAD_B, "B"),
+    (XINPUT_GAMEPAD_X, "X"),
+    (XINPUT_GAMEPAD_Y, "Y"),
+];
+
+const GAMEPAD_AXES: [(GamepadAxis, &str); 6] = [
+    (GamepadAxis::LeftX, "LeftX"),
+    (GamepadAxis::LeftY, "LeftY"),
+    (GamepadAxis::RightX, "RightX"),
+    (GamepadAxis::RightY, "RightY"),
+    (GamepadAxis::LeftTrigger, "LeftTrigger"),
+    (GamepadAxis::RightTrigger, "RightTrigger"),
+];
+
+// Virtual keys with names that aren't just their character
+const KEY_NAMES: [(i32, &str); 46] = [
+    (0x08, "Backspace"),
+    (0x09, "Tab"),
+    (0x0D, "Enter"),
+    (0x10, "Shift"),
+    (0x11, "Ctrl"),
+    (0x12, "Alt"),
+    (0x13, "Pause"),
+    (0x14, "CapsLock"),
+    (0x1B, "Escape"),
+    (0x20, "Space"),
+    (0x21, "PageUp"),
+    (0x22, "PageDown"),
+    (0x23, "End"),
+    (0x24, "Home"),
+    (0x25, "Left"),
+    (0x26, "Up"),
+    (0x27, "Right"),
+    (0x28, "Down"),
+    (0x2D, "Insert"),
+    (0x2E, "Delete"),
+    (0x6A, "NumpadMultiply"),
+    (0x6B, "NumpadAdd"),
+    (0x6D, "NumpadSubtract"),
+    (0x6E, "NumpadDecimal"),
+    (0x6F, "NumpadDivide"),
+    (0xA0, "LeftShift"),
+    (0xA1, "RightShift"),
+    (0xA2, "LeftCtrl"),
+    (0xA3, "RightCtrl"),
+    (0xA4, "LeftAlt"),
+    (0xA5, "RightAlt"),
+    (0xBA, "Semicolon"),
+    (0xBB, "Equals"),
+    (0xBC, "Comma"),
+    (0xBD, "Minus"),
+    (0xBE, "Period"),
+    (0xBF, "Slash"),
+    (0xC0, "Grave"),
+    (0xDB, "LeftBracket"),
+    (0xDC, "Backslash"),
+    (0xDD, "RightBracket"),
+    (0xDE, "Quote"),
+    (0x5B, "LeftWindows"),
+    (0x5C, "RightWindows"),
+    (0x5D, "Menu"),
+    (0x90, "NumLock"),
+];
+
+pub fn key_name(key: i32) -> String {
+    match key {
+        0x30..=0x39 | 0x41..=0x5A => ((key as u8) as char).to_string(),
+        0x60..=0x69 => format!("Numpad{}", key - 0x60),
+        0x70..=0x87 => format!("F{}", key - 0x70 + 1),
+        _ => KEY_NAMES
+            .iter()
+            .find(|(code, _)| *code == key)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| format!("0x{:02X}", key)),
+    }
+}
+
+// Case insensitive, also takes hex codes
+pub fn key_from_name(name: &str) -> Option<i32> {
+    let upper = name.to_uppercase();
+    let bytes = upper.as_bytes();
+
+    if bytes.len() == 1 && (bytes[0].is_ascii_digit() || bytes[0].is_ascii_uppercase()) {
+        return Some(bytes[0] as i32);
+    }
+    if let Some(hex) = upper.strip_prefix("0X") {
+        return i32::from_str_radix(hex, 16).ok();
+    }
+    if let Some(number) = upper.strip_prefix("NUMPAD") {
+        if let Ok(digit @ 0..=9) = number.parse::<i32>() {
+            return Some(0x60 + digit);
+        }
+    }
+    if let Some(number) = upper.strip_prefix('F') {
+        if let Ok(function @ 1..=24) = number.parse::<i32>() {
+            return Some(0x70 + function - 1);
+        }
+    }
+
+    KEY_NAMES
+        .iter()
+        .find(|(_, key_name)| key_name.eq_ignore_ascii_case(name))
+        .map(|(code, _)| *code)
+}
+
+fn find_name<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T> {
+    names
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(name))
+        .map(|(value, _)| *value)
+}
+
+impl InputSource {
+    // How the source is written in the config file
+    pub fn get_name(&self) -> String {
+        match self {
+            InputSource::Key(key) => format!("Key {}", key_name(*key)),
+            InputSource::Mouse(button) => {
+                let name = MOUSE_BUTTONS.iter().find(|(b, _)| b == button).unwrap().1;
+                format!("Mouse {}", name)
+            }
+            InputSource::GamepadButton(button) => {
+                match GAMEPAD_BUTTONS.iter().find(|(b, _)| b == button) {
+                    Some((_, name)) => format!("Pad {}", name),
+                    None => format!("Pad 0x{:04X}", button),
+                }
+            }
+            InputSource::GamepadAxis(axis) => {
+                let name = GAMEPAD_AXES.iter().find(|(a, _)| a == axis).unwrap().1;
+                format!("Axis {}", name)
+            }
+        }
+    }
+
+    pub fn from_name(text: &str) -> Option<InputSource> {
+        let mut parts = text.split_whitespace();
+        let kind = parts.next()?;
+        let name = parts.next()?;
+        if parts.next().is_some() {
+            return None;
+        }
+
+        match kind.to_lowercase().as_str() {
+            "key" => key_from_name(name).map(InputSource::Key),
+            "mouse" => find_name(&MOUSE_BUTTONS, name).map(InputSource::Mouse),
+            "pad" => match find_name(&GAMEPAD_BUTTONS, name) {
+                Some(button) => Some(InputSource::GamepadButton(button)),
+                None => {
+                    let hex = name
+                        .strip_prefix("0x")
+                        .or_else(|| name.strip_prefix("0X"))?;
+                    u16::from_str_radix(hex, 16)
+                        .ok()
+                        .map(InputSource::GamepadButton)
+                }
+            },
+            "axis" => find_name(&GAMEPAD_AXES, name).map(InputSource::GamepadAxis),
+            _ => None,
+        }
+    }
+}
+
+// The key in the given spot on the keyboard for the current layout, so the
+// defaults are in the same place on QWERTY and AZERTY
+fn physical_key(scan_code: u32, fallback: i32) -> i32 {
+    match unsafe { MapVirtualKeyW(scan_code, MAPVK_VSC_TO_VK) } {
+        0 => fallback,
+        key => key as i32,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Binding {
+    pub source: InputSource,
+    pub scale: f32,
+}
+
+// Where the raw state comes from, Win32Input for the real hardware
+pub trait InputDevice {
+    fn key_down(&self, key: i32) -> bool;
+    fn mouse_down(&self, button: MouseButton) -> bool;
+    fn gamepad_button_down(&self, button: u16) -> bool;
+    fn gamepad_axis(&self, axis: GamepadAxis) -> f32;
+
+    fn source_value(&self, source: InputSource) -> f32 {
+        let down = |down: bool| if down { 1.0 } else { 0.0 };
+
+        match source {
+            InputSource::Key(key) => down(self.key_down(key)),
+            InputSource::Mouse(button) => down(self.mouse_down(button)),
+            InputSource::GamepadButton(button) => down(self.gamepad_button_down(button)),
+            InputSource::GamepadAxis(axis) => self.gamepad_axis(axis),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InputMap {
+    actions: HashMap<String, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Self {
+            actions: HashMap::new(),
+        }
+    }
+
+    // The controls the game starts with
+    pub fn with_defaults() -> Self {
+        let mut map = InputMap::new();
+
+        // WASD by position, ZQSD on AZERTY
+        let w = InputSource::Key(physical_key(0x11, 0x57));
+        let a = InputSource::Key(physical_key(0x1E, 0x41));
+        let s = InputSource::Key(physical_key(0x1F, 0x53));
+        let d = InputSource::Key(physical_key(0x20, 0x44));
+
+        map.bind_axis("move_x", a, d);
+        map.bind_axis(
+            "move_x",
+            InputSource::Key(VK_LEFT),
+            InputSource::Key(VK_RIGHT),
+        );
+        map.bind_axis(
+            "move_x",
+            InputSource::GamepadButton(XINPUT_GAMEPAD_DPAD_LEFT),
+            InputSource::GamepadButton(XINPUT_GAMEPAD_DPAD_RIGHT),
+        );
+        map.bind("move_x", InputSource::GamepadAxis(GamepadAxis::LeftX), 1.0);
+
+        // Down is positive, same as the screen
+        map.bind_axis("move_y", w, s);
+        map.bind_axis("move_y", InputSource::Key(VK_UP), InputSource::Key(VK_DOWN));
+        map.bind_axis(
+            "move_y",
+            InputSource::GamepadButton(XINPUT_GAMEPAD_DPAD_UP),
+            InputSource::GamepadButton(XINPUT_GAMEPAD_DPAD_DOWN),
+        );
+        map.bind("move_y", InputSource::GamepadAxis(GamepadAxis::LeftY), -1.0);
+
+        map.bind_button("jump", InputSource::Key(VK_SPACE));
+        map.bind_button("jump", InputSource::GamepadButton(XINPUT_GAMEPAD_A));
+
+        map.bind_button("fire", InputSource::Key(VK_CONTROL));
+        map.bind_button("fire", InputSource::Mouse(MouseButton::Left));
+        map.bind_button("fire", InputSource::GamepadButton(XINPUT_GAMEPAD_X));
+        map.bind_button("fire", InputSource::GamepadAxis(GamepadAxis::RightTrigger));
+
+        map.bind_button("pause", InputSource::Key(VK_ESCAPE));
+        map.bind_button("pause", InputSource::GamepadButton(XINPUT_GAMEPAD_START));
+
+        map
+    }
+
+    pub fn bind(&mut self, action: &str, source: InputSource, scale: f32) {
+        self.actions
+            .entry(action.to_string())
+            .or_default()
+            .push(Binding { source, scale });
+    }
+
+    pub fn bind_button(&mut self, action: &str, source: InputSource) {
+        self.bind(action, source, 1.0);
+    }
+
+    // Two buttons pushing the action opposite ways
+    pub fn bind_axis(&mut self, action: &str, negative: InputSource, positive: InputSource) {
+        self.bind(action, negative, -1.0);
+        self.bind(action, positive, 1.0);
+    }
+
+    // Removes every binding of the action
+    pub fn clear(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn get_bindings(&self, action: &str) -> &[Binding] {
+        self.actions
+            .get(action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // -1 to 1, 0 for actions nothing is bound to
+    pub fn value(&self, device: &dyn InputDevice, action: &str) -> f32 {
+        self.get_bindings(action)
+            .iter()
+            .map(|binding| device.source_value(binding.source) * binding.scale)
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    pub fn is_down(&self, device: &dyn InputDevice, action: &str) -> bool {
+        self.value(device, action).abs() >= 0.5
+    }
+
+    // Changes the source of one binding, or adds a binding when there's no slot
+    // with that index. Returns the other actions that also use the source.
+    pub fn rebind(
+        &mut self,
+        action: &str,
+        slot: Option<usize>,
+        source: InputSource,
+        scale: f32,
+    ) -> Vec<String> {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+
+        match slot.and_then(|slot| bindings.get_mut(slot)) {
+            Some(binding) => *binding = Binding { source, scale },
+            None => bindings.push(Binding { source, scale }),
+        }
+
+        self.actions_using(source)
+            .into_iter()
+            .filter(|other| other != action)
+            .collect()
+    }
+
+    // Actions with a binding to the source, sorted by name
+    pub fn actions_using(&self, source: InputSource) -> Vec<String> {
+        let mut actions: Vec<String> = self
+            .actions
+            .iter()
+            .filter(|(_, bindings)| bindings.iter().any(|binding| binding.source == source))
+            .map(|(action, _)| action.clone())
+            .collect();
+        actions.sort();
+
+        actions
+    }
+
+    // Every source bound to more than one action, with the actions
+    pub fn conflicts(&self) -> Vec<(InputSource, Vec<String>)> {
+        let mut result: Vec<(InputSource, Vec<String>)> = Vec::new();
+
+        for bindings in self.actions.values() {
+            for binding in bindings {
+                if result.iter().any(|(source, _)| *source == binding.source) {
+                    continue;
+                }
+
+                let actions = self.actions_using(binding.source);
+                if actions.len() > 1 {
+                    result.push((binding.source, actions));
+                }
+            }
+        }
+        result.sort_by_key(|(source, _)| source.get_name());
+
+        result
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+
+        InputMap::parse(&text).map_err(|error| format!("{}: {}", file_path, error))
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), String> {
+        std::fs::write(file_path, self.to_config())
+            .map_err(|error| format!("Failed to write {}: {}", file_path, error))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = InputMap::new();
+        let mut action: Option<String> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix('[') {
+                let name = section
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("line {}: missing ']'", number + 1))?
+                    .trim();
+
+                // An empty section still counts, it unbinds the action
+                map.actions.entry(name.to_string()).or_default();
+                action = Some(name.to_string());
+                continue;
+            }
+
+            let action = action
+                .as_ref()
+                .ok_or_else(|| format!("line {}: binding outside of an [action]", number + 1))?;
+
+            let (source, scale) = match line.find('=') {
+                Some(equals) => (line[..equals].trim(), line[equals + 1..].trim()),
+                None => (line, "1"),
+            };
+
+            let source = InputSource::from_name(source)
+                .ok_or_else(|| format!("line {}: unknown input '{}'", number + 1, source))?;
+            let scale = scale
+                .parse::<f32>()
+                .map_err(|_| format!("line {}: invalid scale '{}'", number + 1, scale))?;
+
+            map.bind(action, source, scale);
+        }
+
+        Ok(map)
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut actions: Vec<&String> = self.actions.keys().collect();
+        actions.sort();
+
+        let mut text = String::from("# Controls, a [section] per action and a line per binding\n");
+        for action in actions {
+            text += &format!("\n[{}]\n", action);
+            for binding in &self.actions[action] {
+                text += &format!("{} = {}\n", binding.source.get_name(), binding.scale);
+            }
+        }
+
+        text
+    }
+}
+
+// Waits for the next button, key or stick push so it can be bound to an action.
+// Anything already held when the capture starts has to be let go first, so the
+// key that opened the menu doesn't bind itself.
+pub struct RebindCapture {
+    action: String,
+    slot: Option<usize>,
+    scale: f32,
+    held: Option<Vec<InputSource>>,
+}
+
+pub enum RebindResult {
+    Bound {
+        action: String,
+        source: InputSource,
+        conflicts: Vec<String>, // Other actions using the same source
+    },
+    Cancelled,
+}
+
+impl RebindCapture {
+    // slot is which binding of the action to replace, None adds one
+    pub fn new(action: &str, slot: Option<usize>, scale: f32) -> Self {
+        Self {
+            action: action.to_string(),
+            slot,
+            scale,
+            held: None,
+        }
+    }
+
+    // Everything the capture can pick up, with which way it was pushed
+    fn active_sources(device: &dyn InputDevice) -> Vec<(InputSource, f32)> {
+        let mut sources = Vec::new();
+
+        // 0x01 to 0x06 are the mouse buttons
+        for key in 0x08..0xFF {
+            if device.key_down(key) {
+                sources.push((InputSource::Key(key), 1.0));
+            }
+        }
+        for (button, _) in MOUSE_BUTTONS.iter() {
+            if device.mouse_down(*button) {
+                sources.push((InputSource::Mouse(*button), 1.0));
+            }
+        }
+        for (button, _) in GAMEPAD_BUTTONS.iter() {
+            if device.gamepad_button_down(*button) {
+                sources.push((InputSource::GamepadButton(*button), 1.0));
+            }
+        }
+        for (axis, _) in GAMEPAD_AXES.iter() {
+            let value = device.gamepad_axis(*axis);
+            if value.abs() >= 0.5 {
+                sources.push((InputSource::GamepadAxis(*axis), value.signum()));
+            }
+        }
+
+        sources
+    }
+
+    // Call once a frame until it returns something. Escape cancels.
+    pub fn poll(&mut self, device: &dyn InputDevice, map: &mut InputMap) -> Option<RebindResult> {
+        let active = RebindCapture::active_sources(device);
+
+        let held = match &mut self.held {
+            Some(held) => held,
+            None => {
+                self.held = Some(active.iter().map(|(source, _)| *source).collect());
+                return None;
+            }
+        };
+        held.retain(|source| active.iter().any(|(active, _)| active == source));
+
+        let (source, direction) = *active.iter().find(|(source, _)| !held.contains(source))?;
+        if source == InputSource::Key(VK_ESCAPE) {
+            return Some(RebindResult::Cancelled);
+        }
+
+        // A stick pushed the other way flips the binding so the action still goes the right way
+        let conflicts = map.rebind(&self.action, self.slot, source, self.scale * direction);
+
+        Some(RebindResult::Bound {
+            action: self.action.clone(),
+            source,
+            conflicts,
+        })
+    }
+}
+
+/* Sticks and triggers */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeadzoneShape {
+    Radial, // On the stick's distance from center, keeps diagonals smooth
+    Axial,  // On each axis by itself, snaps to straight lines near the axes
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResponseCurve {
+    Linear,
+    Power(f32), // 2 gives finer control near the center
+}
+
+impl ResponseCurve {
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Power(exponent) => value.powf(*exponent),
+        }
+    }
+}
+
+// Inner and outer are fractions of the stick's range. Anything inside the inner
+// deadzone reads 0, anything past the outer one reads 1, and the rest is
+// stretched to fill 0 to 1 before the curve.
+#[derive(Clone, Copy, Debug)]
+pub struct StickSettings {
+    pub inner_deadzone: f32,
+    pub outer_deadzone: f32,
+    pub shape: DeadzoneShape,
+    pub curve: ResponseCurve,
+}
+
+impl StickSettings {
+    pub fn new(inner_deadzone: f32) -> Self {
+        Self {
+            inner_deadzone,
+            outer_deadzone: 1.0,
+            shape: DeadzoneShape::Radial,
+            curve: ResponseCurve::Linear,
+        }
+    }
+
+    fn rescale(&self, amount: f32) -> f32 {
+        let range = (self.outer_deadzone - self.inner_deadzone).max(0.0001);
+        let amount = ((amount - self.inner_deadzone) / range).clamp(0.0, 1.0);
+
+        self.curve.apply(amount)
+    }
+
+    // Raw stick position from -1 to 1 on each axis, up is positive
+    pub fn apply(&self, raw: Point<f32>) -> Point<f32> {
+        match self.shape {
+            DeadzoneShape::Radial => {
+                let length = (raw.x * raw.x + raw.y * raw.y).sqrt();
+                if length <= self.inner_deadzone {
+                    return Point::new(0.0, 0.0);
+                }
+
+                let scale = self.rescale(length) / length;
+                Point::new(raw.x * scale, raw.y * scale)
+            }
+            DeadzoneShape::Axial => Point::new(
+                self.rescale(raw.x.abs()).copysign(raw.x),
+                self.rescale(raw.y.abs()).copysign(raw.y),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GamepadSettings {
+    pub left_stick: StickSettings,
+    pub right_stick: StickSettings,
+    pub trigger_deadzone: f32, // 0 to 1, the rest is stretched to fill 0 to 1
+}
+
+impl GamepadSettings {
+    // The deadzones XInput recommends
+    pub fn new() -> Self {
+        Self {
+            left_stick: StickSettings::new(XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE as f32 / 32767.0),
+            right_stick: StickSettings::new(XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE as f32 / 32767.0),
+            trigger_deadzone: XINPUT_GAMEPAD_TRIGGER_THRESHOLD as f32 / 255.0,
+        }
+    }
+
+    pub fn get_stick(&self, stick: Stick) -> &StickSettings {
+        match stick {
+            Stick::Left => &self.left_stick,
+            Stick::Right => &self.right_stick,
+        }
+    }
+
+    pub fn apply_trigger(&self, raw: f32) -> f32 {
+        if raw <= self.trigger_deadzone {
+            return 0.0;
+        }
+
+        ((raw - self.trigger_deadzone) / (1.0 - self.trigger_deadzone).max(0.0001)).min(1.0)
+    }
+}
+
+/* Snapshots */
+
+// A key going down or up, in the order the window got them. Catches taps that
+// start and end between two frames, which polling would miss.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEvent {
+    pub key: i32,
+    pub down: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum MouseEvent {
+    Button {
+        button: MouseButton,
+        down: bool,
+    },
+    Wheel(f32),           // Notches, positive is away from the user
+    HorizontalWheel(f32), // Notches, positive is right
+    Move {
+        position: Option<Point<i32>>, // In the window, None when it's outside
+        delta: Point<i32>,            // Pixels moved, even when outside
+    },
+}
+
+// Typed text, for name entry and the console. Separate from the key state, it
+// follows the keyboard layout, dead keys, the IME and the OS key repeat.
+#[derive(Clone, PartialEq, Debug)]
+pub enum TextEvent {
+    Char(char), // Printable characters only, editing keys come as KeyDown
+    KeyDown { key: i32, repeat: bool }, // Every key down including auto repeat
+    Composition { text: String, cursor: usize }, // IME text not typed yet, empty when done
+}
+
+// What the window got since the last frame
+#[derive(Clone)]
+pub struct InputEvents {
+    pub keys: Vec<KeyEvent>,
+    pub mouse: Vec<MouseEvent>,
+    pub text: Vec<TextEvent>,
+}
+
+impl InputEvents {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            mouse: Vec::new(),
+            text: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ButtonState {
+    pub ended_down: bool,
+    pub half_transitions: u32, // Downs and ups this frame, a tap is 2
+    pub held_time: f32,        // Seconds since it went down, 0 when up
+}
+
+impl ButtonState {
+    // At least `transitions` changes happened this frame, there's always an odd
+    // number when the state flipped and an even number when it didn't
+    fn advance(&mut self, down: bool, transitions: u32, dt: f32) {
+        let mut transitions = transitions;
+        if (transitions % 2 == 1) != (down != self.ended_down) {
+            transitions += 1;
+        }
+
+        self.held_time = match (down, transitions) {
+            (false, _) => 0.0,
+            (true, 0) => self.held_time + dt,
+            (true, _) => 0.0,
+        };
+        self.ended_down = down;
+        self.half_transitions = transitions;
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.ended_down
+    }
+
+    pub fn was_pressed(&self) -> bool {
+        self.half_transitions > 1 || (self.half_transitions == 1 && self.ended_down)
+    }
+
+    pub fn was_released(&self) -> bool {
+        self.half_transitions > 1 || (self.half_transitions == 1 && !self.ended_down)
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ActionState {
+    pub value: f32,
+    pub button: ButtonState, // Down past half way
+}
+
+// Everything the game reads for one frame, captured once at the start of it so
+// every system sees the same input and presses only show up on one frame.
+#[derive(Clone)]
+pub struct InputSnapshot {
+    keys: Vec<ButtonState>, // By virtual key code
+    mouse: [ButtonState; 3],
+    gamepad_buttons: [ButtonState; 16], // By XINPUT_GAMEPAD_* bit
+    gamepad_axes: [f32; 6],
+    mouse_position: Option<Point<i32>>, // Window pixels, same as the screen buffer
+    mouse_delta: Point<i32>,
+    wheel: Point<f32>,
+    text: Vec<TextEvent>,
+    composition: Option<(String, usize)>,
+    actions: HashMap<String, ActionState>,
+}
+
+fn mouse_index(button: MouseButton) -> usize {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+    }
+}
+
+fn axis_index(axis: GamepadAxis) -> usize {
+    match axis {
+        GamepadAxis::LeftX => 0,
+        GamepadAxis::LeftY => 1,
+        GamepadAxis::RightX => 2,
+        GamepadAxis::RightY => 3,
+        GamepadAxis::LeftTrigger => 4,
+        GamepadAxis::RightTrigger => 5,
+    }
+}
+
+impl InputSnapshot {
+    pub fn new() -> Self {
+        Self {
+            keys: vec![ButtonState::default(); 256],
+            mouse: [ButtonState::default(); 3],
+            gamepad_buttons: [ButtonState::default(); 16],
+            gamepad_axes: [0.0; 6],
+            mouse_position: None,
+            mouse_delta: Point::new(0, 0),
+            wheel: Point::new(0.0, 0.0),
+            text: Vec::new(),
+            composition: None,
+            actions: HashMap::new(),
+        }
+    }
+
+    // Moves on to the next frame. The device says where everything ended up,
+    // the events are what happened since the last capture.
+    pub fn capture(
+        &mut self,
+        device: &dyn InputDevice,
+        map: &InputMap,
+        events: &InputEvents,
+        dt: f32,
+    ) {
+        let key_events = &events.keys;
+        let mouse_events = &events.mouse;
+
+        for (key, state) in self.keys.iter_mut().enumerate() {
+            let key = key as i32;
+            let transitions = key_events.iter().filter(|event| event.key == key).count();
+            state.advance(device.key_down(key), transitions as u32, dt);
+        }
+
+        for (button, _) in MOUSE_BUTTONS.iter() {
+            let transitions = mouse_events
+                .iter()
+                .filter(
+                    |event| matches!(event, MouseEvent::Button { button: b, .. } if b == button),
+                )
+                .count();
+            self.mouse[mouse_index(*button)].advance(
+                device.mouse_down(*button),
+                transitions as u32,
+                dt,
+            );
+        }
+
+        self.mouse_delta = Point::new(0, 0);
+        self.wheel = Point::new(0.0, 0.0);
+        for event in mouse_events {
+            match *event {
+                MouseEvent::Wheel(notches) => self.wheel.y += notches,
+                MouseEvent::HorizontalWheel(notches) => self.wheel.x += notches,
+                MouseEvent::Move { position, delta } => {
+                    self.mouse_position = position;
+                    self.mouse_delta.x += delta.x;
+                    self.mouse_delta.y += delta.y;
+                }
+                MouseEvent::Button { .. } => {}
+            }
+        }
+
+        self.text = events.text.clone();
+        for event in &self.text {
+            if let TextEvent::Composition { text, cursor } = event {
+                self.composition = if text.is_empty() {
+                    None
+                } else {
+                    Some((text.clone(), *cursor))
+                };
+            }
+        }
+
+        for (bit, state) in self.gamepad_buttons.iter_mut().enumerate() {
+            state.advance(device.gamepad_button_down(1 << bit), 0, dt);
+        }
+
+        for (axis, _) in GAMEPAD_AXES.iter() {
+            self.gamepad_axes[axis_index(*axis)] = device.gamepad_axis(*axis);
+        }
+
+        // Actions are worked out from what was just captured, a tap on any of
+        // the buttons bound to it is a tap on the action
+        let mut actions = HashMap::new();
+        for (action, bindings) in &map.actions {
+            let value = bindings
+                .iter()
+                .map(|binding| self.source_value(binding.source) * binding.scale)
+                .sum::<f32>()
+                .clamp(-1.0, 1.0);
+
+            let transitions = bindings
+                .iter()
+                .map(|binding| self.get_source(binding.source).half_transitions)
+                .max()
+                .unwrap_or(0);
+
+            let mut state = self.actions.get(action).copied().unwrap_or_default();
+            state.value = value;
+            state.button.advance(value.abs() >= 0.5, transitions, dt);
+            actions.insert(action.clone(), state);
+        }
+        self.actions = actions;
+    }
+
+    pub fn get_key(&self, key: i32) -> ButtonState {
+        self.keys.get(key as usize).copied().unwrap_or_default()
+    }
+
+    pub fn get_mouse(&self, button: MouseButton) -> ButtonState {
+        self.mouse[mouse_index(button)]
+    }
+
+    // None when the cursor is outside the window or it doesn't have focus
+    pub fn get_mouse_position(&self) -> Option<Point<i32>> {
+        self.mouse_position
+    }
+
+    // Where the cursor is pointing in the world, for clicking on things
+    pub fn get_mouse_world_position(&self, camera: &Camera2D) -> Option<Point<f32>> {
+        self.mouse_position.map(|position| {
+            camera.screen_to_world(Point::new(position.x as f32, position.y as f32))
+        })
+    }
+
+    // Pixels moved since the last frame, keeps working in relative mouse mode
+    pub fn get_mouse_delta(&self) -> Point<i32> {
+        self.mouse_delta
+    }
+
+    // Notches scrolled since the last frame, y is the normal wheel and positive
+    // away from the user, x is tilting it and positive to the right
+    pub fn get_wheel(&self) -> Point<f32> {
+        self.wheel
+    }
+
+    pub fn get_gamepad_button(&self, button: u16) -> ButtonState {
+        match button {
+            0 => ButtonState::default(),
+            _ => self.gamepad_buttons[button.trailing_zeros() as usize],
+        }
+    }
+
+    pub fn get_gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes[axis_index(axis)]
+    }
+
+    pub fn get_stick(&self, stick: Stick) -> Point<f32> {
+        match stick {
+            Stick::Left => Point::new(
+                self.get_gamepad_axis(GamepadAxis::LeftX),
+                self.get_gamepad_axis(GamepadAxis::LeftY),
+            ),
+            Stick::Right => Point::new(
+                self.get_gamepad_axis(GamepadAxis::RightX),
+                self.get_gamepad_axis(GamepadAxis::RightY),
+            ),
+        }
+    }
+
+    // Axes count as down past half way, they don't keep a held time
+    pub fn get_source(&self, source: InputSource) -> ButtonState {
+        match source {
+            InputSource::Key(key) => self.get_key(key),
+            InputSource::Mouse(button) => self.get_mouse(button),
+            InputSource::GamepadButton(button) => self.get_gamepad_button(button),
+            InputSource::GamepadAxis(axis) => ButtonState {
+                ended_down: self.get_gamepad_axis(axis).abs() >= 0.5,
+                ..ButtonState::default()
+            },
+        }
+    }
+
+    pub fn source_value(&self, source: InputSource) -> f32 {
+        match source {
+            InputSource::GamepadAxis(axis) => self.get_gamepad_axis(axis),
+            _ => {
+                if self.get_source(source).is_down() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    // Typing since the last frame, in order. Only filled in while the engine has
+    // text input turned on.
+    pub fn get_text_events(&self) -> &[TextEvent] {
+        &self.text
+    }
+
+    // The IME text being put together and the cursor in it, in chars. Draw it
+    // at the text cursor, it isn't part of the text until it comes as Chars.
+    pub fn get_composition(&self) -> Option<(&str, usize)> {
+        self.composition
+            .as_ref()
+            .map(|(text, cursor)| (text.as_str(), *cursor))
+    }
+
+    // Simple line editing for text fields, adds typed characters and handles
+    // backspace. Returns true when enter was pressed.
+    pub fn edit_text(&self, text: &mut String, max_chars: usize) -> bool {
+        let mut entered = false;
+
+        for event in &self.text {
+            match event {
+                TextEvent::Char(c) if text.chars().count() < max_chars => text.push(*c),
+                TextEvent::KeyDown { key: VK_BACK, .. } => {
+                    text.pop();
+                }
+                TextEvent::KeyDown { key: VK_RETURN, .. } => entered = true,
+                _ => {}
+            }
+        }
+
+        entered
+    }
+
+    pub fn get_action(&self, action: &str) -> ActionState {
+        self.actions.get(action).copied().unwrap_or_default()
+    }
+
+    pub fn value(&self, action: &str) -> f32 {
+        self.get_action(action).value
+    }
+
+    pub fn is_down(&self, action: &str) -> bool {
+        self.get_action(action).button.is_down()
+    }
+
+    pub fn was_pressed(&self, action: &str) -> bool {
+        self.get_action(action).button.was_pressed()
+    }
+
+    pub fn was_released(&self, action: &str) -> bool {
+        self.get_action(action).button.was_released()
+    }
+
+    pub fn held_time(&self, action: &str) -> f32 {
+        self.get_action(action).button.held_time
+    }
+}
+
+/* Frames */
+
+// Everything one player's devices said in one frame, which is all a snapshot is
+// captured from. Recording these and capturing from them again later gives the
+// exact same snapshots.
+#[derive(Clone)]
+pub struct PlayerFrame {
+    pub keys: Vec<u8>,     // Virtual keys that are down
+    pub mouse_buttons: u8, // Bit per MouseButton
+    pub gamepad_buttons: u16,
+    pub gamepad_axes: [f32; 6],
+    pub events: InputEvents,
+}
+
+impl PlayerFrame {
+    // Nothing held, for players without devices or when the window isn't focused
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            mouse_buttons: 0,
+            gamepad_buttons: 0,
+            gamepad_axes: [0.0; 6],
+            events: InputEvents::new(),
+        }
+    }
+
+    pub fn read(device: &dyn InputDevice, events: InputEvents) -> Self {
+        let mut frame = PlayerFrame::new();
+
+        frame.keys = (0..=255u8)
+            .filter(|key| device.key_down(*key as i32))
+            .collect();
+        for (button, _) in MOUSE_BUTTONS.iter() {
+            if device.mouse_down(*button) {
+                frame.mouse_buttons |= 1 << mouse_index(*button);
+            }
+        }
+        for bit in 0..16 {
+            if device.gamepad_button_down(1 << bit) {
+                frame.gamepad_buttons |= 1 << bit;
+            }
+        }
+        for (axis, _) in GAMEPAD_AXES.iter() {
+            frame.gamepad_axes[axis_index(*axis)] = device.gamepad_axis(*axis);
+        }
+        frame.events = events;
+
+        frame
+    }
+}
+
+impl InputDevice for PlayerFrame {
+    fn key_down(&self, key: i32) -> bool {
+        (0..=255).contains(&key) && self.keys.contains(&(key as u8))
+    }
+
+    fn mouse_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons & (1 << mouse_index(button)) != 0
+    }
+
+    fn gamepad_button_down(&self, button: u16) -> bool {
+        self.gamepad_buttons & button != 0
+    }
+
+    fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes[axis_index(axis)]
+    }
+}
+
+// One frame of input for every player, with the frame time so a replay runs
+// the game at the same steps
+#[derive(Clone)]
+pub struct InputFrame {
+    pub dt: f32,
+    pub players: Vec<PlayerFrame>,
+}
+
+impl InputFrame {
+    pub fn new(dt: f32) -> Self {
+        Self {
+            dt,
+            players: Vec::new(),
+        }
+    }
+}
@@ -2,52 +2,405 @@ mod language_layer;
 mod math;
 mod win32_engine;
 
+mod inflate;
+mod json;
+mod png;
+mod xml;
+
+mod animation;
+mod bench;
+mod blit;
+mod camera;
+mod combo;
 mod entity;
 mod entity_manager;
+mod font;
+mod input;
+mod parallax;
+mod raster;
+mod recording;
+mod render_group;
+mod render_queue;
+mod rumble;
+mod simd;
+mod sprite_sheet;
+mod tiled;
+mod tilemap;
+mod triangle;
+mod ttf;
+
+use std::rc::Rc;
+use std::time::Instant;
 
-use entity::Entity;
+use animation::{AnimationClip, Animator, PlaybackMode};
+use camera::Camera2D;
+use combo::{Combo, ComboDetector, ComboInput, Direction};
 use entity_manager::EntityManager;
-use math::{Color, Point, Rect};
+use font::BuiltinFont;
+use input::{InputMap, InputSnapshot};
+use math::{as_fractional_secs, Color, Point};
+use recording::{InputPlayback, InputRecorder};
+use render_group::TiledRenderer;
+use render_queue::{DrawOrder, Layer, RenderQueue};
+use rumble::RumbleEffect;
+use sprite_sheet::SpriteSheet;
+use tilemap::Tilemap;
 use win32_engine::{Win32Drawable, Win32Engine, Win32GameBitmap, Win32Input};
+use winapi::um::winuser::VK_F9;
+
+const CONTROLS_PATH: &str = "controls.ini";
+
+// The level and everything in it
+fn load_level() -> (EntityManager, Tilemap) {
+    let mut entity_manager = EntityManager::new();
+
+    // Level made in Tiled, the player is spawned from its object layer
+    let tilemap = tiled::load_tiled_map("Assets/level.tmx", &mut entity_manager)
+        .expect("Failed to load level");
+
+    // Player art packed into one atlas
+    let player_sheet = Rc::new(SpriteSheet::pack(
+        &[
+            ("standing", "Assets/soldier_standing.bmpx"),
+            ("walking", "Assets/soldier.bmpx"),
+        ],
+        32,
+    ));
+
+    let standing = player_sheet.get_frame("standing").unwrap();
+    let walking = player_sheet.get_frame("walking").unwrap();
+
+    let mut player_animator = Animator::new();
+    player_animator.add_clip(AnimationClip::from_frames(
+        "idle",
+        &[standing],
+        1.0,
+        PlaybackMode::Loop,
+    ));
+    player_animator.add_clip(AnimationClip::from_frames(
+        "walk",
+        &[walking, standing],
+        0.15,
+        PlaybackMode::Loop,
+    ));
+
+    if let Some(player) = entity_manager.find_mut("player") {
+        player.set_sprite(player_sheet, player_animator);
+    }
+
+    (entity_manager, tilemap)
+}
+
+// Everything a looping replay puts back when it wraps, so the first frame of
+// every pass sees the same state before it
+struct LoopState {
+    entities: EntityManager,
+    input: Vec<InputSnapshot>,
+    combos: ComboDetector,
+    background_scroll: Vec<Point<f32>>,
+}
+
+// One step of the game, everything that has to come out the same when a
+// recording is played back
+fn update_game(
+    input: &Win32Input,
+    entity_manager: &mut EntityManager,
+    tilemap: &mut Tilemap,
+    dt: f32,
+) {
+    entity_manager.input(input);
+    entity_manager.update(tilemap, dt);
+    tilemap.update(dt);
+}
+
+// Plays a recording as fast as possible without a window and checks the game
+// ends up where it did when it was recorded. Returns false if it doesn't.
+fn run_headless(recording_path: &str) -> bool {
+    let mut playback = match InputPlayback::load(recording_path) {
+        Ok(playback) => playback,
+        Err(error) => {
+            println!("{}", error);
+            return false;
+        }
+    };
+
+    let mut input = Win32Input::new();
+    input.set_bindings(playback.get_bindings().clone());
+    input.set_player_count(playback.get_player_count().max(1));
+
+    let (mut entity_manager, mut tilemap) = load_level();
+
+    while let Some(frame) = playback.next_frame() {
+        input.apply_frame(frame);
+        update_game(&input, &mut entity_manager, &mut tilemap, frame.dt);
+    }
+
+    let checksum = entity_manager.checksum();
+    match playback.get_checksum() {
+        Some(expected) if expected == checksum => {
+            println!(
+                "{}: {} frames, matches",
+                recording_path,
+                playback.get_frame_count()
+            );
+            true
+        }
+        Some(expected) => {
+            println!(
+                "{}: ended at {:016x}, recorded {:016x}",
+                recording_path, checksum, expected
+            );
+            false
+        }
+        None => {
+            println!(
+                "{}: no checksum, the recording wasn't finished",
+                recording_path
+            );
+            false
+        }
+    }
+}
+
+// The value after a command line flag
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // Times the SIMD drawing kernels instead of running the game
+    if args.iter().any(|arg| arg == "--bench") {
+        bench::run();
+        return;
+    }
+
+    // --record <file> saves the input, F9 drops a marker while recording.
+    // --play <file> plays it back, --loop repeats between the first two markers
+    // and --headless runs it without a window as a regression test.
+    let record_path = arg_value(&args, "--record");
+    let play_path = arg_value(&args, "--play");
+
+    if args.iter().any(|arg| arg == "--headless") {
+        let passed = match &play_path {
+            Some(path) => run_headless(path),
+            None => {
+                println!("--headless needs a recording to --play");
+                false
+            }
+        };
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     // Create Win32 window n stuff
     let mut win32_engine = Win32Engine::new("Cheese Game");
 
     // Win32 xinput (only works for xbox controllers)
     let mut win32_input = Win32Input::new(); // Put inside win32engine?
 
+    // Controls can be edited in controls.ini, it's written with the defaults if missing
+    if std::path::Path::new(CONTROLS_PATH).exists() {
+        match InputMap::load(CONTROLS_PATH) {
+            Ok(bindings) => {
+                for (source, actions) in bindings.conflicts() {
+                    println!("{} is bound to {}", source.get_name(), actions.join(", "));
+                }
+                win32_input.set_bindings(bindings);
+            }
+            Err(error) => println!("{}, using default controls", error),
+        }
+    } else if let Err(error) = win32_input.get_bindings().save(CONTROLS_PATH) {
+        println!("{}", error);
+    }
+
+    let mut recorder = record_path.map(|path| {
+        InputRecorder::create(&path, win32_input.get_bindings()).expect("Failed to start recording")
+    });
+
+    let mut playback = play_path.map(|path| {
+        let mut playback = InputPlayback::load(&path).expect("Failed to load recording");
+        win32_input.set_bindings(playback.get_bindings().clone());
+        win32_input.set_player_count(playback.get_player_count().max(1));
+        if args.iter().any(|arg| arg == "--loop") {
+            playback.loop_markers(0);
+        }
+        playback
+    });
+
+    // Specials for player one, written facing right
+    let mut combos = ComboDetector::new();
+    combos.add_combo(
+        Combo::new("fireball")
+            .then(ComboInput::Direction(Direction::Down), 0.0)
+            .then(ComboInput::Direction(Direction::DownRight), 0.2)
+            .then(
+                ComboInput::DirectionPress(Direction::Right, "fire".to_string()),
+                0.25,
+            ),
+    );
+    combos.add_combo(
+        Combo::new("charge shot")
+            .then(ComboInput::Charge(Direction::Left, 0.8), 0.0)
+            .then(
+                ComboInput::DirectionPress(Direction::Right, "fire".to_string()),
+                0.25,
+            ),
+    );
+
     // The window buffer
     let mut buffer = Win32GameBitmap::new(win32_engine.get_window());
 
-    let mut entity_manager = EntityManager::new();
+    let (mut entity_manager, mut tilemap) = load_level();
+
+    // Looped playback puts everything back the way it was at the loop start
+    let mut loop_start_state: Option<LoopState> = None;
 
-    let player = Entity::new(Rect::new(5, 5, 64, 64), entity::EntityType::RECT);
+    let renderer = TiledRenderer::new(0);
 
-    entity_manager.create(player);
+    // Levels can be bigger than the window, the camera keeps the player in view
+    let mut camera = Camera2D::new(Point::new(
+        buffer.get_width() as f32,
+        buffer.get_height() as f32,
+    ));
+    camera.set_bounds(
+        Point::new(0.0, 0.0),
+        Point::new(tilemap.pixel_width() as f32, tilemap.pixel_height() as f32),
+    );
+    camera.set_deadzone(Point::new(160.0, 96.0));
+    camera.set_follow_speed(8.0);
+
+    // FPS counter, smoothed so it's readable
+    let debug_font = BuiltinFont::new(2);
+    let mut frame_time = 1.0 / 60.0;
+
+    let mut last_frame = Instant::now();
 
     // let mut _test_read = Win32GameBitmap::load_bmp("Assets/test_file.bmpx");
 
     while win32_engine.is_running() {
+        let now = Instant::now();
+        let mut dt = as_fractional_secs(&(now - last_frame));
+        last_frame = now;
+        frame_time += (dt - frame_time) * 0.05;
+
         // Events and input
         win32_engine.handle_events();
 
-        // Always try to get controller
-        win32_input.get_controller();
+        // Controllers are picked up as they're plugged in. A recording being
+        // played replaces what the devices say, and the frame time with it.
+        if let Some(playing) = &mut playback {
+            win32_input.poll(&mut win32_engine, dt);
+
+            if playing.is_at_loop_start() {
+                match &loop_start_state {
+                    Some(state) => {
+                        entity_manager = state.entities.clone();
+                        win32_input.restore_state(&state.input);
+                        combos = state.combos.clone();
+                        tilemap
+                            .get_background_mut()
+                            .set_scroll(&state.background_scroll);
+                    }
+                    None => {
+                        loop_start_state = Some(LoopState {
+                            entities: entity_manager.clone(),
+                            input: win32_input.save_state(),
+                            combos: combos.clone(),
+                            background_scroll: tilemap.get_background().get_scroll(),
+                        })
+                    }
+                }
+            }
+
+            match playing.next_frame() {
+                Some(frame) => {
+                    win32_input.apply_frame(frame);
+                    dt = frame.dt;
+                }
+                None => {
+                    println!("Playback finished");
+                    playback = None;
+                }
+            }
+        }
+        if playback.is_none() {
+            win32_input.update(&mut win32_engine, dt);
+        }
+
+        if let Some(recording) = &mut recorder {
+            if win32_input.get_snapshot().get_key(VK_F9).was_pressed() {
+                println!("Marker at frame {}", recording.get_frame_count());
+                recording.add_marker().expect("Failed to write recording");
+            }
+            recording
+                .write_frame(win32_input.get_frame())
+                .expect("Failed to write recording");
+        }
+
+        // Nothing to face yet, the player turns with their movement which
+        // would flip a back charge into forward
+        let specials = match win32_input.get_player(0) {
+            Some(snapshot) => combos.update(snapshot, false, dt).to_vec(),
+            None => Vec::new(),
+        };
+        for name in &specials {
+            println!("{}!", name);
+            // A kick on the controller when a special comes out
+            win32_input.rumble(
+                0,
+                RumbleEffect::new(0.6, 0.3, 0.25).with_envelope(0.0, 0.15),
+            );
+        }
 
-        // Input
-        entity_manager.input(&win32_engine, &mut win32_input);
+        update_game(&win32_input, &mut entity_manager, &mut tilemap, dt);
 
-        // Update
-        entity_manager.update(&win32_engine);
+        // The buffer follows the window size, so the camera has to as well
+        camera.set_viewport(Point::new(
+            buffer.get_width() as f32,
+            buffer.get_height() as f32,
+        ));
 
-        // Draw
-        win32_engine.clear_screen(0x0FFda025, &mut buffer);
+        if let Some(player) = entity_manager.find("player") {
+            let rect = player.get_rect();
+            let center = Point::new(
+                rect.x as f32 + rect.w as f32 * 0.5,
+                rect.y as f32 + rect.h as f32 * 0.5,
+            );
+            camera.follow(center, dt);
+        }
+        camera.update(dt);
 
-        entity_manager.draw(&win32_engine, &mut buffer);
+        // Draw, queued by layer and then rasterized on all cores
+        let mut render_queue = RenderQueue::new();
+        render_queue.push_clear(0x0FFda025);
+
+        render_queue.set_camera(&camera);
+        tilemap.queue_draw(&camera, &mut render_queue);
+
+        entity_manager.queue_draw(&mut render_queue);
+
+        render_queue.push_text(
+            DrawOrder::new(Layer::Ui, 0.0),
+            &debug_font,
+            &format!("{:.0} fps", 1.0 / frame_time),
+            Point::new(8, 8),
+            &Color::new(255, 255, 255, 255),
+        );
+
+        render_queue.flush(&renderer, &mut buffer);
 
         win32_engine.render_buffer_to_screen(&mut buffer);
     }
 
+    if let Some(recording) = recorder {
+        recording
+            .finish(entity_manager.checksum())
+            .expect("Failed to write recording");
+    }
+
     win32_engine.release(); // Release DC
 }
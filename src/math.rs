@@ -1,7 +1,8 @@
-pub fn _as_fractional_secs(dur: &std::time::Duration) -> f32 {
+pub fn as_fractional_secs(dur: &std::time::Duration) -> f32 {
     (dur.as_secs() as f64 + f64::from(dur.subsec_nanos()) / 1_000_000_000.0) as f32
 }
 
+#[derive(Clone, Copy)]
 pub struct Point<T> {
     pub x: T,
     pub y: T,
@@ -13,6 +14,7 @@ impl<T> Point<T> {
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -24,8 +26,35 @@ impl Color {
     pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self { r, g, b, a }
     }
+
+    // Accepts "#RRGGBB" and "#AARRGGBB" (the way Tiled writes colors)
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim_start_matches('#');
+        let value = u32::from_str_radix(hex, 16).ok()?;
+
+        match hex.len() {
+            6 => Some(Color::from_u32(0xFF000000 | value)),
+            8 => Some(Color::from_u32(value)),
+            _ => None,
+        }
+    }
+
+    // Packed 0xAARRGGBB, same layout as the pixels in a Win32GameBitmap
+    pub fn from_u32(color: u32) -> Self {
+        Self {
+            r: (color >> 16) as u8,
+            g: (color >> 8) as u8,
+            b: color as u8,
+            a: (color >> 24) as u8,
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        (self.a as u32) << 24 | (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
 }
 
+#[derive(Clone, Copy)]
 pub struct Rect {
     pub x: u32,
     pub y: u32,
@@ -38,3 +67,18 @@ impl Rect {
         Self { x, y, w, h }
     }
 }
+
+// Blends a 0xAARRGGBB source over the destination using the source alpha
+pub fn alpha_blend(dest: u32, src: u32) -> u32 {
+    let alpha = src >> 24;
+    let inv_alpha = 255 - alpha;
+
+    let mut result = 0xFF000000;
+    for shift in [0, 8, 16].iter() {
+        let s = (src >> shift) & 0xFF;
+        let d = (dest >> shift) & 0xFF;
+        result |= ((s * alpha + d * inv_alpha + 127) / 255) << shift;
+    }
+
+    result
+}
@@ -0,0 +1,195 @@
+use crate::{
+    blit::Flip,
+    camera::Camera2D,
+    math::{Point, Rect},
+    render_queue::{DrawOrder, Layer, RenderQueue},
+    win32_engine::Win32GameBitmap,
+};
+
+// Background images that scroll slower (or faster) than the world. The factor is
+// how much of the camera's movement a layer follows, 1 moves with the world, 0
+// stays put on screen. Same as Tiled: layers sit at their offset when the camera
+// is centered on the parallax origin.
+//
+// Layers can repeat to fill the view and scroll on their own (clouds, water).
+// Everything goes in the Background layer behind the tile layers.
+
+pub struct ParallaxLayer {
+    texture: Win32GameBitmap,
+    offset: Point<f32>, // World position of the top left corner
+    factor: Point<f32>,
+    repeat_x: bool,
+    repeat_y: bool,
+    scroll_speed: Point<f32>, // Pixels per second
+    scroll: Point<f32>,
+    visible: bool,
+}
+
+impl ParallaxLayer {
+    pub fn new(texture: Win32GameBitmap, factor: Point<f32>) -> Self {
+        Self {
+            texture,
+            offset: Point::new(0.0, 0.0),
+            factor,
+            repeat_x: false,
+            repeat_y: false,
+            scroll_speed: Point::new(0.0, 0.0),
+            scroll: Point::new(0.0, 0.0),
+            visible: true,
+        }
+    }
+
+    pub fn set_offset(&mut self, offset: Point<f32>) {
+        self.offset = offset;
+    }
+
+    pub fn set_factor(&mut self, factor: Point<f32>) {
+        self.factor = factor;
+    }
+
+    pub fn set_repeat(&mut self, repeat_x: bool, repeat_y: bool) {
+        self.repeat_x = repeat_x;
+        self.repeat_y = repeat_y;
+    }
+
+    pub fn set_scroll_speed(&mut self, speed: Point<f32>) {
+        self.scroll_speed = speed;
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn get_texture(&self) -> &Win32GameBitmap {
+        &self.texture
+    }
+
+    fn update(&mut self, dt: f32) {
+        let width = self.texture.get_width() as f32;
+        let height = self.texture.get_height() as f32;
+
+        self.scroll.x += self.scroll_speed.x * dt;
+        self.scroll.y += self.scroll_speed.y * dt;
+
+        // Repeating layers wrap so the scroll never loses precision
+        if self.repeat_x && width > 0.0 {
+            self.scroll.x = self.scroll.x.rem_euclid(width);
+        }
+        if self.repeat_y && height > 0.0 {
+            self.scroll.y = self.scroll.y.rem_euclid(height);
+        }
+    }
+}
+
+// Copies needed along one axis to cover min..max, starting at start
+fn covering(start: f32, size: f32, min: f32, max: f32, repeat: bool) -> Vec<f32> {
+    if !repeat {
+        return if start < max && start + size > min {
+            vec![start]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let mut result = Vec::new();
+    let mut position = start + ((min - start) / size).floor() * size;
+    while position < max {
+        result.push(position);
+        position += size;
+    }
+
+    result
+}
+
+pub struct ParallaxBackground {
+    layers: Vec<ParallaxLayer>,
+    origin: Point<f32>,
+}
+
+impl ParallaxBackground {
+    pub fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+            origin: Point::new(0.0, 0.0),
+        }
+    }
+
+    // Layers draw in the order they were added, returns the layer index
+    pub fn add_layer(&mut self, layer: ParallaxLayer) -> usize {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    pub fn get_layer_mut(&mut self, index: usize) -> Option<&mut ParallaxLayer> {
+        self.layers.get_mut(index)
+    }
+
+    pub fn set_origin(&mut self, origin: Point<f32>) {
+        self.origin = origin;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    // Advances the auto scrolling layers
+    pub fn update(&mut self, dt: f32) {
+        for layer in &mut self.layers {
+            layer.update(dt);
+        }
+    }
+
+    // Where every layer has scrolled to, for putting it back when a replay loops
+    pub fn get_scroll(&self) -> Vec<Point<f32>> {
+        self.layers.iter().map(|layer| layer.scroll).collect()
+    }
+
+    pub fn set_scroll(&mut self, scroll: &[Point<f32>]) {
+        for (layer, scroll) in self.layers.iter_mut().zip(scroll) {
+            layer.scroll = *scroll;
+        }
+    }
+
+    pub fn queue_draw<'a>(&'a self, camera: &Camera2D, queue: &mut RenderQueue<'a>) {
+        let (min, max) = camera.visible_area();
+        let center = camera.get_position();
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            let width = layer.texture.get_width();
+            let height = layer.texture.get_height();
+            if !layer.visible || width <= 0 || height <= 0 {
+                continue;
+            }
+
+            // Moving the layer along with the camera is what makes it look further away
+            let start = Point::new(
+                layer.offset.x
+                    + layer.scroll.x
+                    + (center.x - self.origin.x) * (1.0 - layer.factor.x),
+                layer.offset.y
+                    + layer.scroll.y
+                    + (center.y - self.origin.y) * (1.0 - layer.factor.y),
+            );
+
+            // Negative keys sort behind the tile layers
+            let order = DrawOrder::new(Layer::Background, index as f32 - self.layers.len() as f32);
+            let whole = Rect::new(0, 0, width as u32, height as u32);
+
+            let columns = covering(start.x, width as f32, min.x, max.x, layer.repeat_x);
+            let rows = covering(start.y, height as f32, min.y, max.y, layer.repeat_y);
+
+            for &y in &rows {
+                for &x in &columns {
+                    queue.push_bitmap(
+                        order,
+                        &layer.texture,
+                        &whole,
+                        Point::new(x.floor() as i32, y.floor() as i32),
+                        Point::new(width as u32, height as u32),
+                        Flip::NONE,
+                    );
+                }
+            }
+        }
+    }
+}
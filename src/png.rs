@@ -0,0 +1,162 @@
+use crate::inflate::zlib_decompress;
+
+// Decodes non-interlaced 8 bit PNGs into top-down 0xAARRGGBB pixels.
+// Returns (width, height, pixels).
+pub fn decode_png(bytes: &[u8]) -> Result<(i32, i32, Vec<u32>), String> {
+    if bytes.len() < 8 || &bytes[..8] != b"\x89PNG\r\n\x1a\n" {
+        return Err("Not a png file".to_string());
+    }
+
+    let mut width = 0;
+    let mut height = 0;
+    let mut color_type = 0;
+    let mut palette: Vec<u32> = Vec::new();
+    let mut compressed = Vec::new();
+
+    let mut pos = 8;
+    while pos + 8 <= bytes.len() {
+        let length =
+            u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+                as usize;
+        let chunk_type = &bytes[pos + 4..pos + 8];
+        let data = bytes
+            .get(pos + 8..pos + 8 + length)
+            .ok_or("Truncated png chunk")?;
+
+        match chunk_type {
+            b"IHDR" => {
+                if data.len() < 13 {
+                    return Err("Truncated png header".to_string());
+                }
+                let png_width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                let png_height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                if png_width == 0
+                    || png_height == 0
+                    || png_width > i32::MAX as u32
+                    || png_height > i32::MAX as u32
+                {
+                    return Err(format!("Invalid png size {}x{}", png_width, png_height));
+                }
+                width = png_width as i32;
+                height = png_height as i32;
+                color_type = data[9];
+
+                if data[8] != 8 {
+                    return Err(format!("Unsupported png bit depth {}", data[8]));
+                }
+                if data[12] != 0 {
+                    return Err("Interlaced pngs are not supported".to_string());
+                }
+            }
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| {
+                        0xFF000000 | (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32
+                    })
+                    .collect();
+            }
+            b"tRNS" if color_type == 3 => {
+                for (entry, &alpha) in palette.iter_mut().zip(data) {
+                    *entry = (*entry & 0x00FFFFFF) | (alpha as u32) << 24;
+                }
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+
+        pos += 12 + length; // length, type, data and crc
+    }
+
+    if width == 0 {
+        return Err("Missing png header".to_string());
+    }
+
+    let channels = match color_type {
+        0 => 1, // gray
+        2 => 3, // rgb
+        3 => 1, // palette index
+        4 => 2, // gray + alpha
+        6 => 4, // rgba
+        _ => return Err(format!("Unsupported png color type {}", color_type)),
+    };
+
+    let raw = zlib_decompress(&compressed)?;
+    let stride = (width as usize)
+        .checked_mul(channels)
+        .ok_or("Png is too large")?;
+    let image_size = stride
+        .checked_mul(height as usize)
+        .ok_or("Png is too large")?;
+    if raw.len() < image_size.saturating_add(height as usize) {
+        return Err("Not enough png image data".to_string());
+    }
+
+    // Every row starts with a filter type byte
+    let mut image = vec![0u8; image_size];
+    for y in 0..height as usize {
+        let filter = raw[y * (stride + 1)];
+        let source = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+        for x in 0..stride {
+            let left = if x >= channels {
+                image[y * stride + x - channels] as i32
+            } else {
+                0
+            };
+            let up = if y > 0 {
+                image[(y - 1) * stride + x] as i32
+            } else {
+                0
+            };
+            let up_left = if y > 0 && x >= channels {
+                image[(y - 1) * stride + x - channels] as i32
+            } else {
+                0
+            };
+
+            let predictor = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => (left + up) / 2,
+                4 => {
+                    let estimate = left + up - up_left;
+                    let distance_left = (estimate - left).abs();
+                    let distance_up = (estimate - up).abs();
+                    let distance_up_left = (estimate - up_left).abs();
+
+                    if distance_left <= distance_up && distance_left <= distance_up_left {
+                        left
+                    } else if distance_up <= distance_up_left {
+                        up
+                    } else {
+                        up_left
+                    }
+                }
+                _ => return Err(format!("Invalid png filter type {}", filter)),
+            };
+
+            image[y * stride + x] = source[x].wrapping_add(predictor as u8);
+        }
+    }
+
+    let pixels = image
+        .chunks(channels)
+        .map(|pixel| match color_type {
+            0 => 0xFF000000 | ((pixel[0] as u32) * 0x010101),
+            2 => 0xFF000000 | (pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32,
+            3 => palette.get(pixel[0] as usize).copied().unwrap_or(0),
+            4 => (pixel[1] as u32) << 24 | ((pixel[0] as u32) * 0x010101),
+            _ => {
+                (pixel[3] as u32) << 24
+                    | (pixel[0] as u32) << 16
+                    | (pixel[1] as u32) << 8
+                    | pixel[2] as u32
+            }
+        })
+        .collect();
+
+    Ok((width, height, pixels))
+}
@@ -0,0 +1,453 @@
+use crate::{math::Point, simd, win32_engine::Win32GameBitmap};
+
+// Vector primitives for the software renderer. Everything is clipped to the
+// buffer and blended with the color's alpha, colors are packed 0xAARRGGBB.
+//
+// Filled shapes cover a pixel when its center is inside the shape, with
+// top-left edges included and bottom-right edges excluded, so shapes that
+// share an edge never draw a pixel twice.
+
+pub fn plot(buffer: &mut Win32GameBitmap, x: i32, y: i32, color: u32) {
+    if x >= 0 && y >= 0 && x < buffer.get_width() && y < buffer.get_height() {
+        buffer.blend_pixel(x, y, color);
+    }
+}
+
+// Plots with the alpha scaled by coverage (0.0 to 1.0), for anti-aliasing
+pub fn plot_coverage(buffer: &mut Win32GameBitmap, x: i32, y: i32, color: u32, coverage: f32) {
+    let alpha = ((color >> 24) as f32 * coverage.clamp(0.0, 1.0) + 0.5) as u32;
+    plot(buffer, x, y, (color & 0x00FFFFFF) | alpha << 24);
+}
+
+// Horizontal run of pixels from x0 up to but not including x1
+pub fn fill_span(buffer: &mut Win32GameBitmap, y: i32, x0: i32, x1: i32, color: u32) {
+    if y < 0 || y >= buffer.get_height() {
+        return;
+    }
+
+    let x0 = x0.max(0);
+    let x1 = x1.min(buffer.get_width());
+    if x0 < x1 {
+        simd::blend_color(&mut buffer.row_mut(y)[x0 as usize..x1 as usize], color);
+    }
+}
+
+pub fn fill_rect(buffer: &mut Win32GameBitmap, pos: Point<i32>, size: Point<u32>, color: u32) {
+    for y in pos.y..pos.y + size.y as i32 {
+        fill_span(buffer, y, pos.x, pos.x + size.x as i32, color);
+    }
+}
+
+// The outline grows inwards and the sides don't overlap, so alpha stays even
+pub fn draw_rect_outline(
+    buffer: &mut Win32GameBitmap,
+    pos: Point<i32>,
+    size: Point<u32>,
+    thickness: u32,
+    color: u32,
+) {
+    if thickness == 0 {
+        return;
+    }
+
+    // Thick enough to meet in the middle, it's just a filled rect
+    if thickness.saturating_mul(2) >= size.x.min(size.y) {
+        fill_rect(buffer, pos, size, color);
+        return;
+    }
+
+    let side_height = size.y - thickness * 2;
+    let bottom = pos.y + (size.y - thickness) as i32;
+    let right = pos.x + (size.x - thickness) as i32;
+
+    fill_rect(buffer, pos, Point::new(size.x, thickness), color);
+    fill_rect(
+        buffer,
+        Point::new(pos.x, bottom),
+        Point::new(size.x, thickness),
+        color,
+    );
+    fill_rect(
+        buffer,
+        Point::new(pos.x, pos.y + thickness as i32),
+        Point::new(thickness, side_height),
+        color,
+    );
+    fill_rect(
+        buffer,
+        Point::new(right, pos.y + thickness as i32),
+        Point::new(thickness, side_height),
+        color,
+    );
+}
+
+// Bresenham, both end points are drawn
+pub fn draw_line(buffer: &mut Win32GameBitmap, from: Point<i32>, to: Point<i32>, color: u32) {
+    // Skip lines that can't touch the buffer at all
+    if from.x.max(to.x) < 0
+        || from.y.max(to.y) < 0
+        || from.x.min(to.x) >= buffer.get_width()
+        || from.y.min(to.y) >= buffer.get_height()
+    {
+        return;
+    }
+
+    line_points(from, to, |x, y| plot(buffer, x, y, color));
+}
+
+// Xiaolin Wu's anti-aliased line, end points can be anywhere between pixels
+pub fn draw_line_aa(buffer: &mut Win32GameBitmap, from: Point<f32>, to: Point<f32>, color: u32) {
+    let steep = (to.y - from.y).abs() > (to.x - from.x).abs();
+
+    // Work along the major axis from left to right
+    let (mut x0, mut y0, mut x1, mut y1) = if steep {
+        (from.y, from.x, to.y, to.x)
+    } else {
+        (from.x, from.y, to.x, to.y)
+    };
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+
+    let dx = x1 - x0;
+    let gradient = if dx.abs() < f32::EPSILON {
+        1.0
+    } else {
+        (y1 - y0) / dx
+    };
+
+    let mut plot_pair = |major: i32, minor: f32, coverage: f32| {
+        let minor_floor = minor.floor();
+        let fraction = minor - minor_floor;
+        let minor = minor_floor as i32;
+
+        if steep {
+            plot_coverage(buffer, minor, major, color, (1.0 - fraction) * coverage);
+            plot_coverage(buffer, minor + 1, major, color, fraction * coverage);
+        } else {
+            plot_coverage(buffer, major, minor, color, (1.0 - fraction) * coverage);
+            plot_coverage(buffer, major, minor + 1, color, fraction * coverage);
+        }
+    };
+
+    // Sample at pixel centers along the major axis
+    let first = (x0 - 0.5).round() as i32;
+    let last = (x1 - 0.5).round() as i32;
+
+    for major in first..=last {
+        let center = major as f32 + 0.5;
+
+        // End pixels only get the part of the line that actually covers them
+        let coverage = if first == last {
+            dx
+        } else if major == first {
+            1.0 - (x0 - major as f32)
+        } else if major == last {
+            x1 - major as f32
+        } else {
+            1.0
+        };
+
+        let minor = y0 + gradient * (center - x0) - 0.5;
+        plot_pair(major, minor, coverage.clamp(0.0, 1.0));
+    }
+}
+
+// Midpoint ellipse, the four quadrants share the points on the axes so those are
+// only plotted once
+pub fn draw_ellipse(
+    buffer: &mut Win32GameBitmap,
+    center: Point<i32>,
+    radius: Point<i32>,
+    color: u32,
+) {
+    let rx = radius.x.abs() as i64;
+    let ry = radius.y.abs() as i64;
+
+    if rx == 0 || ry == 0 {
+        draw_line(
+            buffer,
+            Point::new(center.x - rx as i32, center.y - ry as i32),
+            Point::new(center.x + rx as i32, center.y + ry as i32),
+            color,
+        );
+        return;
+    }
+
+    let mut plot4 = |x: i64, y: i64| {
+        let (x, y) = (x as i32, y as i32);
+        plot(buffer, center.x + x, center.y + y, color);
+        if x != 0 {
+            plot(buffer, center.x - x, center.y + y, color);
+        }
+        if y != 0 {
+            plot(buffer, center.x + x, center.y - y, color);
+            if x != 0 {
+                plot(buffer, center.x - x, center.y - y, color);
+            }
+        }
+    };
+
+    let rx2 = rx * rx;
+    let ry2 = ry * ry;
+
+    // Region 1, slope above -1
+    let mut x = 0;
+    let mut y = ry;
+    let mut dx = 0;
+    let mut dy = 2 * rx2 * y;
+    let mut decision = ry2 * 4 - rx2 * ry * 4 + rx2; // Scaled by 4 to stay in integers
+
+    while dx < dy {
+        plot4(x, y);
+
+        x += 1;
+        dx += 2 * ry2;
+        if decision < 0 {
+            decision += 4 * (dx + ry2);
+        } else {
+            y -= 1;
+            dy -= 2 * rx2;
+            decision += 4 * (dx - dy + ry2);
+        }
+    }
+
+    // Region 2
+    let mut decision =
+        ry2 * (2 * x + 1) * (2 * x + 1) + 4 * rx2 * (y - 1) * (y - 1) - 4 * rx2 * ry2;
+
+    while y >= 0 {
+        plot4(x, y);
+
+        y -= 1;
+        dy -= 2 * rx2;
+        if decision > 0 {
+            decision += 4 * (rx2 - dy);
+        } else {
+            x += 1;
+            dx += 2 * ry2;
+            decision += 4 * (dx - dy + rx2);
+        }
+    }
+}
+
+pub fn fill_ellipse(
+    buffer: &mut Win32GameBitmap,
+    center: Point<i32>,
+    radius: Point<i32>,
+    color: u32,
+) {
+    let rx = radius.x.abs() as f32;
+    let ry = radius.y.abs() as f32;
+    if rx == 0.0 || ry == 0.0 {
+        return;
+    }
+
+    // Same outer edge as draw_ellipse, the radius reaches the middle of the edge pixels
+    let rx = rx + 0.5;
+    let ry = ry + 0.5;
+
+    for y in -(ry as i32)..=ry as i32 {
+        let offset = y as f32 / ry;
+        if offset.abs() > 1.0 {
+            continue;
+        }
+
+        let half_width = rx * (1.0 - offset * offset).sqrt();
+        let x0 = (center.x as f32 + 0.5 - half_width).ceil() as i32;
+        let x1 = (center.x as f32 + 0.5 + half_width).ceil() as i32;
+
+        fill_span(buffer, center.y + y, x0, x1, color);
+    }
+}
+
+pub fn draw_circle(buffer: &mut Win32GameBitmap, center: Point<i32>, radius: i32, color: u32) {
+    draw_ellipse(buffer, center, Point::new(radius, radius), color);
+}
+
+pub fn fill_circle(buffer: &mut Win32GameBitmap, center: Point<i32>, radius: i32, color: u32) {
+    fill_ellipse(buffer, center, Point::new(radius, radius), color);
+}
+
+// Closed outline through the points
+pub fn draw_polygon(buffer: &mut Win32GameBitmap, points: &[Point<f32>], color: u32) {
+    let rounded: Vec<Point<i32>> = points
+        .iter()
+        .map(|point| Point::new(point.x.floor() as i32, point.y.floor() as i32))
+        .collect();
+
+    if rounded.len() < 3 {
+        if let (Some(&from), Some(&to)) = (rounded.first(), rounded.last()) {
+            draw_line(buffer, from, to, color);
+        }
+        return;
+    }
+
+    // Each edge skips its first point, the previous edge already drew the corner
+    for (i, &from) in rounded.iter().enumerate() {
+        let to = rounded[(i + 1) % rounded.len()];
+
+        line_points(from, to, |x, y| {
+            if x != from.x || y != from.y {
+                plot(buffer, x, y, color);
+            }
+        });
+    }
+}
+
+// Walks the Bresenham line from one end to the other
+fn line_points<F: FnMut(i32, i32)>(from: Point<i32>, to: Point<i32>, mut visit: F) {
+    let dx = (to.x - from.x).abs();
+    let dy = -(to.y - from.y).abs();
+    let step_x = if from.x < to.x { 1 } else { -1 };
+    let step_y = if from.y < to.y { 1 } else { -1 };
+
+    let mut x = from.x;
+    let mut y = from.y;
+    let mut error = dx + dy;
+
+    loop {
+        visit(x, y);
+
+        if x == to.x && y == to.y {
+            break;
+        }
+
+        let error2 = error * 2;
+        if error2 >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if error2 <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+// Scanline fill with the non-zero winding rule, works for concave and
+// self-intersecting polygons
+pub fn fill_polygon(buffer: &mut Win32GameBitmap, points: &[Point<f32>], color: u32) {
+    if points.len() < 3 {
+        return;
+    }
+
+    let min_y = points.iter().map(|point| point.y).fold(f32::MAX, f32::min);
+    let max_y = points.iter().map(|point| point.y).fold(f32::MIN, f32::max);
+
+    // Scanlines whose pixel centers are inside the polygon's height
+    let first_y = ((min_y - 0.5).ceil() as i32).max(0);
+    let last_y = ((max_y - 0.5).ceil() as i32).min(buffer.get_height());
+
+    let mut crossings: Vec<(f32, i32)> = Vec::with_capacity(points.len());
+
+    for y in first_y..last_y {
+        let center_y = y as f32 + 0.5;
+        crossings.clear();
+
+        for (i, from) in points.iter().enumerate() {
+            let to = &points[(i + 1) % points.len()];
+
+            // Half open so a vertex on the scanline is only counted once
+            let (upper, lower, winding) = if from.y < to.y {
+                (from, to, 1)
+            } else {
+                (to, from, -1)
+            };
+            if center_y < upper.y || center_y >= lower.y {
+                continue;
+            }
+
+            let t = (center_y - upper.y) / (lower.y - upper.y);
+            crossings.push((upper.x + t * (lower.x - upper.x), winding));
+        }
+
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut winding = 0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].1;
+
+            if winding != 0 {
+                let x0 = (pair[0].0 - 0.5).ceil() as i32;
+                let x1 = (pair[1].0 - 0.5).ceil() as i32;
+                fill_span(buffer, y, x0, x1, color);
+            }
+        }
+    }
+}
+
+// Edge function rasterizer with 8 bits of sub-pixel precision and the top-left
+// fill rule. Works with either winding order.
+pub fn fill_triangle(
+    buffer: &mut Win32GameBitmap,
+    a: Point<f32>,
+    b: Point<f32>,
+    c: Point<f32>,
+    color: u32,
+) {
+    const SUB_PIXEL: f32 = 256.0;
+
+    let fixed = |point: Point<f32>| {
+        Point::new(
+            (point.x * SUB_PIXEL).round() as i64,
+            (point.y * SUB_PIXEL).round() as i64,
+        )
+    };
+    let (a, mut b, mut c) = (fixed(a), fixed(b), fixed(c));
+
+    // Make the winding consistent so inside is always positive
+    let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+    if area == 0 {
+        return;
+    }
+    if area < 0 {
+        std::mem::swap(&mut b, &mut c);
+    }
+
+    let sub_pixel = SUB_PIXEL as i64;
+    let min_x = (a.x.min(b.x).min(c.x) / sub_pixel).max(0) as i32;
+    let min_y = (a.y.min(b.y).min(c.y) / sub_pixel).max(0) as i32;
+    let max_x =
+        ((a.x.max(b.x).max(c.x) + sub_pixel - 1) / sub_pixel).min(buffer.get_width() as i64) as i32;
+    let max_y = ((a.y.max(b.y).max(c.y) + sub_pixel - 1) / sub_pixel)
+        .min(buffer.get_height() as i64) as i32;
+
+    // With y pointing down the winding is now clockwise on screen, so top edges run
+    // right and left edges run up. Pixel centers exactly on any other edge belong
+    // to the neighbouring triangle.
+    let bias = |from: Point<i64>, to: Point<i64>| {
+        let edge_y = to.y - from.y;
+        let edge_x = to.x - from.x;
+        let is_top_left = (edge_y == 0 && edge_x > 0) || edge_y < 0;
+        if is_top_left {
+            0
+        } else {
+            -1
+        }
+    };
+    let edge = |from: Point<i64>, to: Point<i64>, x: i64, y: i64| {
+        (to.x - from.x) * (y - from.y) - (to.y - from.y) * (x - from.x)
+    };
+
+    let bias_0 = bias(b, c);
+    let bias_1 = bias(c, a);
+    let bias_2 = bias(a, b);
+
+    for y in min_y..max_y {
+        let center_y = y as i64 * sub_pixel + sub_pixel / 2;
+
+        for x in min_x..max_x {
+            let center_x = x as i64 * sub_pixel + sub_pixel / 2;
+
+            let w0 = edge(b, c, center_x, center_y) + bias_0;
+            let w1 = edge(c, a, center_x, center_y) + bias_1;
+            let w2 = edge(a, b, center_x, center_y) + bias_2;
+
+            if w0 >= 0 && w1 >= 0 && w2 >= 0 {
+                buffer.blend_pixel(x, y, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::input::{
+    InputEvents, InputFrame, InputMap, KeyEvent, MouseButton, MouseEvent, PlayerFrame, TextEvent,
+};
+use crate::math::Point;
+
+// Input recording and playback, like Handmade Hero's looped live code editing.
+// Every frame's raw input (what the snapshots are captured from) is written out
+// with its frame time, so playing it back drives the game through exactly the
+// same steps. Markers split a recording up so a part of it can be looped.
+//
+// The file is little endian binary:
+//
+//     "HMIR", version u32, bindings (config text, see input.rs)
+//     then blocks until the end, each starting with a tag byte:
+//         0 frame, dt f32, player count u8, a PlayerFrame per player
+//         1 marker, before the next frame
+//         2 checksum u64, the game state at the end for regression runs
+
+const MAGIC: &[u8; 4] = b"HMIR";
+const VERSION: u32 = 1;
+
+const TAG_FRAME: u8 = 0;
+const TAG_MARKER: u8 = 1;
+const TAG_CHECKSUM: u8 = 2;
+
+/* Writing */
+
+fn put_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn mouse_button_id(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+    }
+}
+
+fn put_player(out: &mut Vec<u8>, player: &PlayerFrame) {
+    put_u16(out, player.keys.len() as u16);
+    out.extend_from_slice(&player.keys);
+    put_u8(out, player.mouse_buttons);
+    put_u16(out, player.gamepad_buttons);
+    for axis in &player.gamepad_axes {
+        put_f32(out, *axis);
+    }
+
+    let events = &player.events;
+
+    put_u32(out, events.keys.len() as u32);
+    for event in &events.keys {
+        put_i32(out, event.key);
+        put_u8(out, event.down as u8);
+    }
+
+    put_u32(out, events.mouse.len() as u32);
+    for event in &events.mouse {
+        match *event {
+            MouseEvent::Button { button, down } => {
+                put_u8(out, 0);
+                put_u8(out, mouse_button_id(button));
+                put_u8(out, down as u8);
+            }
+            MouseEvent::Wheel(notches) => {
+                put_u8(out, 1);
+                put_f32(out, notches);
+            }
+            MouseEvent::HorizontalWheel(notches) => {
+                put_u8(out, 2);
+                put_f32(out, notches);
+            }
+            MouseEvent::Move { position, delta } => {
+                put_u8(out, 3);
+                put_u8(out, position.is_some() as u8);
+                let position = position.unwrap_or_else(|| Point::new(0, 0));
+                put_i32(out, position.x);
+                put_i32(out, position.y);
+                put_i32(out, delta.x);
+                put_i32(out, delta.y);
+            }
+        }
+    }
+
+    put_u32(out, events.text.len() as u32);
+    for event in &events.text {
+        match event {
+            TextEvent::Char(c) => {
+                put_u8(out, 0);
+                put_u32(out, *c as u32);
+            }
+            TextEvent::KeyDown { key, repeat } => {
+                put_u8(out, 1);
+                put_i32(out, *key);
+                put_u8(out, *repeat as u8);
+            }
+            TextEvent::Composition { text, cursor } => {
+                put_u8(out, 2);
+                put_str(out, text);
+                put_u32(out, *cursor as u32);
+            }
+        }
+    }
+}
+
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    frames: usize,
+}
+
+impl InputRecorder {
+    // The bindings are saved too, playback needs the same ones to turn the raw
+    // input into the same actions
+    pub fn create(file_path: &str, bindings: &InputMap) -> Result<Self, String> {
+        let file = File::create(file_path)
+            .map_err(|error| format!("Failed to create {}: {}", file_path, error))?;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        put_u32(&mut header, VERSION);
+        put_str(&mut header, &bindings.to_config());
+
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+            frames: 0,
+        };
+        recorder.write(&header)?;
+
+        Ok(recorder)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|error| format!("Failed to write recording: {}", error))
+    }
+
+    pub fn write_frame(&mut self, frame: &InputFrame) -> Result<(), String> {
+        let mut out = Vec::new();
+        put_u8(&mut out, TAG_FRAME);
+        put_f32(&mut out, frame.dt);
+        put_u8(&mut out, frame.players.len() as u8);
+        for player in &frame.players {
+            put_player(&mut out, player);
+        }
+
+        self.frames += 1;
+        self.write(&out)
+    }
+
+    // Marks the start of the next frame
+    pub fn add_marker(&mut self) -> Result<(), String> {
+        self.write(&[TAG_MARKER])
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames
+    }
+
+    // The checksum is whatever the game wants to compare after a replay
+    pub fn finish(mut self, checksum: u64) -> Result<(), String> {
+        let mut out = vec![TAG_CHECKSUM];
+        out.extend_from_slice(&checksum.to_le_bytes());
+        self.write(&out)?;
+
+        self.writer
+            .flush()
+            .map_err(|error| format!("Failed to write recording: {}", error))
+    }
+}
+
+/* Reading */
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.at + count > self.data.len() {
+            return Err("Recording ends in the middle of a frame".to_string());
+        }
+
+        let bytes = &self.data[self.at..self.at + count];
+        self.at += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let low = self.u32()? as u64;
+        let high = self.u32()? as u64;
+        Ok(low | high << 32)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.bytes(length)?.to_vec())
+            .map_err(|_| "Recording has invalid text".to_string())
+    }
+
+    fn is_done(&self) -> bool {
+        self.at >= self.data.len()
+    }
+}
+
+fn read_player(reader: &mut Reader) -> Result<PlayerFrame, String> {
+    let mut player = PlayerFrame::new();
+
+    let key_count = reader.u16()? as usize;
+    player.keys = reader.bytes(key_count)?.to_vec();
+    player.mouse_buttons = reader.u8()?;
+    player.gamepad_buttons = reader.u16()?;
+    for axis in &mut player.gamepad_axes {
+        *axis = reader.f32()?;
+    }
+
+    let mut events = InputEvents::new();
+
+    for _ in 0..reader.u32()? {
+        events.keys.push(KeyEvent {
+            key: reader.i32()?,
+            down: reader.u8()? != 0,
+        });
+    }
+
+    for _ in 0..reader.u32()? {
+        let event = match reader.u8()? {
+            0 => {
+                let button = match reader.u8()? {
+                    0 => MouseButton::Left,
+                    1 => MouseButton::Right,
+                    _ => MouseButton::Middle,
+                };
+                MouseEvent::Button {
+                    button,
+                    down: reader.u8()? != 0,
+                }
+            }
+            1 => MouseEvent::Wheel(reader.f32()?),
+            2 => MouseEvent::HorizontalWheel(reader.f32()?),
+            3 => {
+                let inside = reader.u8()? != 0;
+                let position = Point::new(reader.i32()?, reader.i32()?);
+                let delta = Point::new(reader.i32()?, reader.i32()?);
+                MouseEvent::Move {
+                    position: if inside { Some(position) } else { None },
+                    delta,
+                }
+            }
+            tag => return Err(format!("Unknown mouse event {} in recording", tag)),
+        };
+        events.mouse.push(event);
+    }
+
+    for _ in 0..reader.u32()? {
+        let event = match reader.u8()? {
+            0 => TextEvent::Char(std::char::from_u32(reader.u32()?).unwrap_or('?')),
+            1 => TextEvent::KeyDown {
+                key: reader.i32()?,
+                repeat: reader.u8()? != 0,
+            },
+            2 => TextEvent::Composition {
+                text: reader.string()?,
+                cursor: reader.u32()? as usize,
+            },
+            tag => return Err(format!("Unknown text event {} in recording", tag)),
+        };
+        events.text.push(event);
+    }
+
+    player.events = events;
+
+    Ok(player)
+}
+
+pub struct InputPlayback {
+    bindings: InputMap,
+    frames: Vec<InputFrame>,
+    markers: Vec<usize>, // Frame index each marker comes before
+    checksum: Option<u64>,
+    position: usize,
+    loop_range: Option<(usize, usize)>, // Frames start..end played over and over
+}
+
+impl InputPlayback {
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let data = std::fs::read(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+
+        InputPlayback::from_bytes(&data).map_err(|error| format!("{}: {}", file_path, error))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { data, at: 0 };
+
+        if reader.bytes(4)? != MAGIC {
+            return Err("Not an input recording".to_string());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported recording version {}", version));
+        }
+
+        let bindings = InputMap::parse(&reader.string()?)?;
+
+        let mut frames = Vec::new();
+        let mut markers = Vec::new();
+        let mut checksum = None;
+
+        while !reader.is_done() {
+            match reader.u8()? {
+                TAG_FRAME => {
+                    let mut frame = InputFrame::new(reader.f32()?);
+                    for _ in 0..reader.u8()? {
+                        frame.players.push(read_player(&mut reader)?);
+                    }
+                    frames.push(frame);
+                }
+                TAG_MARKER => markers.push(frames.len()),
+                TAG_CHECKSUM => checksum = Some(reader.u64()?),
+                tag => return Err(format!("Unknown block {} in recording", tag)),
+            }
+        }
+
+        Ok(Self {
+            bindings,
+            frames,
+            markers,
+            checksum,
+            position: 0,
+            loop_range: None,
+        })
+    }
+
+    pub fn get_bindings(&self) -> &InputMap {
+        &self.bindings
+    }
+
+    pub fn get_markers(&self) -> &[usize] {
+        &self.markers
+    }
+
+    // None if the recording wasn't finished properly
+    pub fn get_checksum(&self) -> Option<u64> {
+        self.checksum
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Most players the recording has input for
+    pub fn get_player_count(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| frame.players.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    // Plays from one marker to the next over and over, or the whole recording
+    // if there aren't enough markers
+    pub fn loop_markers(&mut self, first: usize) {
+        let start = self.markers.get(first).copied().unwrap_or(0);
+        let end = self
+            .markers
+            .get(first + 1)
+            .copied()
+            .unwrap_or(self.frames.len());
+
+        self.set_loop(Some((start, end)));
+    }
+
+    pub fn set_loop(&mut self, range: Option<(usize, usize)>) {
+        self.loop_range = range
+            .map(|(start, end)| (start.min(self.frames.len()), end.min(self.frames.len())))
+            .filter(|(start, end)| start < end);
+    }
+
+    // True right before the loop's first frame is played, the first time and
+    // every time it goes around. Save the game state the first time and put it
+    // back after, or the loop won't play out the same.
+    pub fn is_at_loop_start(&self) -> bool {
+        self.loop_range
+            .is_some_and(|(start, end)| self.position == start || self.position >= end)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.loop_range.is_none() && self.position >= self.frames.len()
+    }
+
+    pub fn next_frame(&mut self) -> Option<&InputFrame> {
+        if let Some((start, end)) = self.loop_range {
+            if self.position >= end {
+                self.position = start;
+            }
+        }
+
+        let frame = self.frames.get(self.position)?;
+        self.position += 1;
+
+        Some(frame)
+    }
+}
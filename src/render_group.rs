@@ -0,0 +1,600 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    blit::{Filter, Flip, SpriteTransform},
+    font::Font,
+    math::{Color, Point, Rect},
+    raster, simd,
+    triangle::{self, TriangleTexture, Vertex},
+    win32_engine::Win32GameBitmap,
+};
+
+// Deferred rendering in the spirit of Handmade Hero. Draw calls are recorded into
+// a RenderGroup, then the TiledRenderer splits the back buffer into tiles and
+// worker threads rasterize the tiles in parallel.
+//
+// Every tile runs the whole command list in order, clipped to itself, so the
+// output doesn't depend on how many threads there are or which one got a tile.
+
+pub const TILE_SIZE: i32 = 64;
+
+pub enum RenderCommand<'a> {
+    Clear {
+        color: u32,
+    },
+    Rectangle {
+        pos: Point<i32>,
+        size: Point<u32>,
+        color: u32,
+    },
+    Line {
+        from: Point<i32>,
+        to: Point<i32>,
+        color: u32,
+    },
+    Bitmap {
+        texture: &'a Win32GameBitmap,
+        source: Rect,
+        pos: Point<i32>,
+        size: Point<u32>,
+        flip: Flip,
+        filter: Filter,
+    },
+    RotatedBitmap {
+        texture: &'a Win32GameBitmap,
+        source: Rect,
+        transform: SpriteTransform,
+        filter: Filter,
+    },
+    Triangle {
+        vertices: [Vertex; 3],
+        texture: Option<TriangleTexture<'a>>,
+    },
+    Text {
+        font: &'a dyn Font,
+        text: String,
+        pos: Point<i32>,
+        color: u32,
+    },
+}
+
+// Pixels a command can touch, min inclusive and max exclusive
+#[derive(Clone, Copy)]
+struct Bounds {
+    min: Point<i32>,
+    max: Point<i32>,
+}
+
+impl Bounds {
+    fn everything() -> Self {
+        Self {
+            min: Point::new(i32::MIN, i32::MIN),
+            max: Point::new(i32::MAX, i32::MAX),
+        }
+    }
+
+    fn from_size(pos: Point<i32>, size: Point<u32>) -> Self {
+        Self {
+            min: pos,
+            max: Point::new(
+                pos.x.saturating_add(size.x.min(i32::MAX as u32) as i32),
+                pos.y.saturating_add(size.y.min(i32::MAX as u32) as i32),
+            ),
+        }
+    }
+
+    fn from_ink(pos: Point<i32>, (min, max): (Point<i32>, Point<i32>)) -> Self {
+        Self {
+            min: Point::new(pos.x.saturating_add(min.x), pos.y.saturating_add(min.y)),
+            max: Point::new(pos.x.saturating_add(max.x), pos.y.saturating_add(max.y)),
+        }
+    }
+
+    fn from_points(points: &[Point<f32>]) -> Self {
+        let mut result = Self {
+            min: Point::new(i32::MAX, i32::MAX),
+            max: Point::new(i32::MIN, i32::MIN),
+        };
+        for point in points {
+            result.min.x = result.min.x.min(point.x.floor() as i32);
+            result.min.y = result.min.y.min(point.y.floor() as i32);
+            // The casts saturate, so the +1 has to as well
+            result.max.x = result.max.x.max((point.x.ceil() as i32).saturating_add(1));
+            result.max.y = result.max.y.max((point.y.ceil() as i32).saturating_add(1));
+        }
+
+        result
+    }
+
+    fn overlaps(&self, other: &Bounds) -> bool {
+        self.min.x < other.max.x
+            && other.min.x < self.max.x
+            && self.min.y < other.max.y
+            && other.min.y < self.max.y
+    }
+}
+
+pub struct RenderGroup<'a> {
+    commands: Vec<(Bounds, RenderCommand<'a>)>,
+}
+
+impl<'a> RenderGroup<'a> {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn push_clear(&mut self, color: u32) {
+        self.commands
+            .push((Bounds::everything(), RenderCommand::Clear { color }));
+    }
+
+    // Blended with the color's alpha
+    pub fn push_rectangle(&mut self, pos: Point<i32>, size: Point<u32>, color: &Color) {
+        self.commands.push((
+            Bounds::from_size(pos, size),
+            RenderCommand::Rectangle {
+                pos,
+                size,
+                color: color.to_u32(),
+            },
+        ));
+    }
+
+    pub fn push_line(&mut self, from: Point<i32>, to: Point<i32>, color: &Color) {
+        let bounds = Bounds {
+            min: Point::new(from.x.min(to.x), from.y.min(to.y)),
+            max: Point::new(from.x.max(to.x) + 1, from.y.max(to.y) + 1),
+        };
+
+        self.commands.push((
+            bounds,
+            RenderCommand::Line {
+                from,
+                to,
+                color: color.to_u32(),
+            },
+        ));
+    }
+
+    // The source rect stretched over size pixels at pos
+    pub fn push_bitmap(
+        &mut self,
+        texture: &'a Win32GameBitmap,
+        source: &Rect,
+        pos: Point<i32>,
+        size: Point<u32>,
+        flip: Flip,
+        filter: Filter,
+    ) {
+        self.commands.push((
+            Bounds::from_size(pos, size),
+            RenderCommand::Bitmap {
+                texture,
+                source: *source,
+                pos,
+                size,
+                flip,
+                filter,
+            },
+        ));
+    }
+
+    pub fn push_rotated_bitmap(
+        &mut self,
+        texture: &'a Win32GameBitmap,
+        source: &Rect,
+        transform: &SpriteTransform,
+        filter: Filter,
+    ) {
+        // Whatever the angle, the sprite stays inside the circle through its furthest corner
+        let reach_x = transform.pivot.x.max(transform.size.x - transform.pivot.x);
+        let reach_y = transform.pivot.y.max(transform.size.y - transform.pivot.y);
+        let radius = (reach_x * reach_x + reach_y * reach_y).sqrt();
+        let pos = transform.pos;
+
+        self.commands.push((
+            Bounds::from_points(&[
+                Point::new(pos.x - radius, pos.y - radius),
+                Point::new(pos.x + radius, pos.y + radius),
+            ]),
+            RenderCommand::RotatedBitmap {
+                texture,
+                source: *source,
+                transform: *transform,
+                filter,
+            },
+        ));
+    }
+
+    pub fn push_triangle(&mut self, vertices: [Vertex; 3], texture: Option<TriangleTexture<'a>>) {
+        let bounds = Bounds::from_points(&[vertices[0].pos, vertices[1].pos, vertices[2].pos]);
+
+        self.commands
+            .push((bounds, RenderCommand::Triangle { vertices, texture }));
+    }
+
+    pub fn push_text(&mut self, font: &'a dyn Font, text: &str, pos: Point<i32>, color: &Color) {
+        self.commands.push((
+            Bounds::from_ink(pos, font.ink_bounds(text)),
+            RenderCommand::Text {
+                font,
+                text: text.to_string(),
+                pos,
+                color: color.to_u32(),
+            },
+        ));
+    }
+
+    // Puts the commands in the order of the given indices, each index used once
+    pub fn reorder(&mut self, order: &[usize]) {
+        assert!(
+            order.len() == self.commands.len(),
+            "order doesn't cover every command"
+        );
+
+        let mut commands: Vec<Option<(Bounds, RenderCommand<'a>)>> =
+            self.commands.drain(..).map(Some).collect();
+        self.commands.extend(
+            order
+                .iter()
+                .map(|&index| commands[index].take().expect("index used twice")),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // Drops the commands but keeps the memory for the next frame
+    pub fn reset(&mut self) {
+        self.commands.clear();
+    }
+}
+
+// Draws a command into a tile whose top left corner is at origin in the back buffer
+fn draw_command(command: &RenderCommand, origin: Point<i32>, tile: &mut Win32GameBitmap) {
+    let offset = |pos: Point<i32>| Point::new(pos.x - origin.x, pos.y - origin.y);
+    let offset_f32 = |pos: Point<f32>| Point::new(pos.x - origin.x as f32, pos.y - origin.y as f32);
+
+    match command {
+        RenderCommand::Clear { color } => simd::fill(tile.pixels_mut(), *color),
+        RenderCommand::Rectangle { pos, size, color } => {
+            raster::fill_rect(tile, offset(*pos), *size, *color)
+        }
+        RenderCommand::Line { from, to, color } => {
+            raster::draw_line(tile, offset(*from), offset(*to), *color)
+        }
+        RenderCommand::Bitmap {
+            texture,
+            source,
+            pos,
+            size,
+            flip,
+            filter,
+        } => {
+            if *flip == Flip::NONE && size.x == source.w && size.y == source.h {
+                texture.draw_bmp_region(source, offset(*pos), tile);
+            } else {
+                texture.draw_bmp_scaled(source, offset(*pos), *size, *flip, *filter, tile);
+            }
+        }
+        RenderCommand::RotatedBitmap {
+            texture,
+            source,
+            transform,
+            filter,
+        } => {
+            let mut transform = *transform;
+            transform.pos = offset_f32(transform.pos);
+            texture.draw_bmp_rotated(source, &transform, *filter, tile);
+        }
+        RenderCommand::Triangle { vertices, texture } => {
+            let mut vertices = *vertices;
+            for vertex in &mut vertices {
+                vertex.pos = offset_f32(vertex.pos);
+            }
+            triangle::draw_triangle(
+                tile,
+                &vertices[0],
+                &vertices[1],
+                &vertices[2],
+                texture.as_ref(),
+            );
+        }
+        RenderCommand::Text {
+            font,
+            text,
+            pos,
+            color,
+        } => font.draw_text(text, offset(*pos), *color, tile),
+    }
+}
+
+// The job a batch runs for every tile, with the index of the thread running it.
+// The lifetime is erased, WorkerPool::run doesn't return until every tile is
+// done so the borrow outlives all uses.
+#[derive(Clone, Copy)]
+struct Job(*const (dyn Fn(usize, usize) + Sync));
+
+unsafe impl Send for Job {}
+
+struct WorkState {
+    job: Option<Job>,
+    job_count: usize,
+    generation: u64, // Bumped for every batch so workers can tell a new one started
+    working: usize,  // Workers still on the current batch
+    panicked: bool,
+    quit: bool,
+}
+
+struct WorkShared {
+    state: Mutex<WorkState>,
+    start: Condvar,
+    finished: Condvar,
+    next_job: AtomicUsize,
+}
+
+pub struct WorkerPool {
+    shared: Arc<WorkShared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    // The calling thread helps out too, so 0 threads runs everything on it
+    pub fn new(thread_count: usize) -> Self {
+        let shared = Arc::new(WorkShared {
+            state: Mutex::new(WorkState {
+                job: None,
+                job_count: 0,
+                generation: 0,
+                working: 0,
+                panicked: false,
+                quit: false,
+            }),
+            start: Condvar::new(),
+            finished: Condvar::new(),
+            next_job: AtomicUsize::new(0),
+        });
+
+        let threads = (0..thread_count)
+            .map(|index| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("render worker {}", index))
+                    .spawn(move || worker_loop(&shared, index + 1))
+                    .expect("Failed to spawn render worker")
+            })
+            .collect();
+
+        Self { shared, threads }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len() + 1
+    }
+
+    // Calls job(index, thread) for every index in 0..count across all threads and
+    // waits for them to finish. Thread 0 is the caller.
+    pub fn run(&self, count: usize, job: &(dyn Fn(usize, usize) + Sync)) {
+        let job = Job(unsafe {
+            std::mem::transmute::<
+                *const (dyn Fn(usize, usize) + Sync + '_),
+                *const (dyn Fn(usize, usize) + Sync + 'static),
+            >(job)
+        });
+
+        {
+            let mut state = self
+                .shared
+                .state
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            state.job = Some(job);
+            state.job_count = count;
+            state.generation += 1;
+            state.working = self.threads.len();
+            self.shared.next_job.store(0, Ordering::SeqCst);
+        }
+        self.shared.start.notify_all();
+
+        // Waits even if a job panics on this thread, the workers still hold the job
+        let wait = WaitForWorkers(&self.shared);
+        run_jobs(&self.shared, job, count, 0);
+        drop(wait);
+
+        let mut state = self
+            .shared
+            .state
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        if state.panicked {
+            state.panicked = false;
+            panic!("A render worker panicked");
+        }
+    }
+}
+
+struct WaitForWorkers<'a>(&'a WorkShared);
+
+impl<'a> Drop for WaitForWorkers<'a> {
+    fn drop(&mut self) {
+        let mut state = self
+            .0
+            .state
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        while state.working > 0 {
+            state = self
+                .0
+                .finished
+                .wait(state)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+        state.job = None;
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .quit = true;
+        self.shared.start.notify_all();
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_jobs(shared: &WorkShared, job: Job, count: usize, thread: usize) {
+    loop {
+        let index = shared.next_job.fetch_add(1, Ordering::SeqCst);
+        if index >= count {
+            break;
+        }
+
+        unsafe { (*job.0)(index, thread) };
+    }
+}
+
+fn worker_loop(shared: &WorkShared, thread: usize) {
+    let mut generation = 0;
+
+    loop {
+        let (job, count) = {
+            let mut state = shared
+                .state
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            while state.generation == generation && !state.quit {
+                state = shared
+                    .start
+                    .wait(state)
+                    .unwrap_or_else(|error| error.into_inner());
+            }
+            if state.quit {
+                return;
+            }
+
+            generation = state.generation;
+            (state.job, state.job_count)
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(job) = job {
+                run_jobs(shared, job, count, thread);
+            }
+        }));
+
+        let mut state = shared
+            .state
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        state.panicked |= result.is_err();
+        state.working -= 1;
+        if state.working == 0 {
+            shared.finished.notify_all();
+        }
+    }
+}
+
+// Back buffer pixels shared with the workers, every tile writes its own pixels only
+struct SharedPixels {
+    memory: *mut u32,
+    width: i32,
+}
+
+unsafe impl Sync for SharedPixels {}
+
+pub struct TiledRenderer {
+    pool: WorkerPool,
+    scratch: Vec<Mutex<Win32GameBitmap>>, // One tile sized bitmap per thread
+}
+
+impl TiledRenderer {
+    // thread_count 0 picks one worker per extra core
+    pub fn new(thread_count: usize) -> Self {
+        let thread_count = if thread_count == 0 {
+            thread::available_parallelism()
+                .map(|count| count.get() - 1)
+                .unwrap_or(0)
+        } else {
+            thread_count - 1
+        };
+
+        let pool = WorkerPool::new(thread_count);
+        let scratch = (0..pool.thread_count())
+            .map(|_| {
+                let pixels = vec![0; (TILE_SIZE * TILE_SIZE) as usize];
+                Mutex::new(Win32GameBitmap::from_pixels(TILE_SIZE, TILE_SIZE, &pixels))
+            })
+            .collect();
+
+        Self { pool, scratch }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.pool.thread_count()
+    }
+
+    pub fn render(&self, group: &RenderGroup, buffer: &mut Win32GameBitmap) {
+        let width = buffer.get_width();
+        let height = buffer.get_height();
+        let columns = (width + TILE_SIZE - 1) / TILE_SIZE;
+        let rows = (height + TILE_SIZE - 1) / TILE_SIZE;
+
+        let pixels = SharedPixels {
+            memory: buffer.pixels_mut().as_mut_ptr(),
+            width,
+        };
+
+        self.pool.run((columns * rows) as usize, &|index, thread| {
+            let origin = Point::new(
+                (index as i32 % columns) * TILE_SIZE,
+                (index as i32 / columns) * TILE_SIZE,
+            );
+            let tile_width = TILE_SIZE.min(width - origin.x) as usize;
+            let tile_height = TILE_SIZE.min(height - origin.y);
+            let bounds = Bounds::from_size(origin, Point::new(TILE_SIZE as u32, TILE_SIZE as u32));
+
+            // A panicking tile poisons its scratch, but every tile copies the
+            // buffer in first so there's nothing left over to worry about
+            let mut tile = self.scratch[thread]
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+
+            // Tiles start from what's already in the buffer, so a group doesn't have to clear
+            for y in 0..tile_height {
+                let start = ((origin.y + y) * pixels.width + origin.x) as usize;
+                let source =
+                    unsafe { std::slice::from_raw_parts(pixels.memory.add(start), tile_width) };
+                tile.row_mut(y)[..tile_width].copy_from_slice(source);
+            }
+
+            for (command_bounds, command) in &group.commands {
+                if command_bounds.overlaps(&bounds) {
+                    draw_command(command, origin, &mut tile);
+                }
+            }
+
+            for y in 0..tile_height {
+                let start = ((origin.y + y) * pixels.width + origin.x) as usize;
+                let dest =
+                    unsafe { std::slice::from_raw_parts_mut(pixels.memory.add(start), tile_width) };
+                dest.copy_from_slice(&tile.row(y)[..tile_width]);
+            }
+        });
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{
+    blit::{Filter, Flip, SpriteTransform},
+    camera::{Camera2D, CameraTransform},
+    font::{self, Font, TextAlign},
+    math::{Color, Point, Rect},
+    render_group::{RenderGroup, TiledRenderer},
+    sprite_sheet::SpriteSheet,
+    triangle::Vertex,
+    win32_engine::Win32GameBitmap,
+};
+
+// Draw order by layer instead of by submission order. Every command goes in with
+// a DrawOrder, a layer and a sort key (usually the y of an entity's feet, so things lower on
+// screen overlap things above them). Flushing sorts by layer then key and draws
+// everything through the tiled renderer. The sort is stable, equal keys draw in
+// the order they were pushed.
+//
+// With a camera set, commands outside the Ui layer are in world pixels and get
+// moved, zoomed and turned onto the screen as they're pushed.
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Layer {
+    Background,
+    Entities,
+    Foreground,
+    Ui,
+}
+
+// Where a command lands in the draw order
+#[derive(Clone, Copy)]
+pub struct DrawOrder {
+    pub layer: Layer,
+    pub sort_key: f32,
+}
+
+impl DrawOrder {
+    pub fn new(layer: Layer, sort_key: f32) -> Self {
+        Self { layer, sort_key }
+    }
+}
+
+pub struct RenderQueue<'a> {
+    group: RenderGroup<'a>,
+    orders: Vec<DrawOrder>, // One per command in the group
+    camera: CameraTransform,
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> Self {
+        Self {
+            group: RenderGroup::new(),
+            orders: Vec::new(),
+            camera: CameraTransform::identity(),
+        }
+    }
+
+    // Everything but the Ui layer is in world space and goes through the camera.
+    // Commands pushed before this keep the view they were pushed with.
+    pub fn set_camera(&mut self, camera: &Camera2D) {
+        self.camera = camera.get_transform();
+    }
+
+    fn to_screen(&self, layer: Layer, point: Point<f32>) -> Point<f32> {
+        if layer == Layer::Ui {
+            point
+        } else {
+            self.camera.world_to_screen(point)
+        }
+    }
+
+    fn to_screen_i32(&self, layer: Layer, point: Point<i32>) -> Point<i32> {
+        let point = self.to_screen(layer, Point::new(point.x as f32, point.y as f32));
+        Point::new(point.x.round() as i32, point.y.round() as i32)
+    }
+
+    fn is_rotated(&self, layer: Layer) -> bool {
+        layer != Layer::Ui && self.camera.is_rotated()
+    }
+
+    // Screen rect from the rounded corners, so rects that touch in the world still touch
+    fn to_screen_rect(
+        &self,
+        layer: Layer,
+        pos: Point<i32>,
+        size: Point<u32>,
+    ) -> (Point<i32>, Point<u32>) {
+        let min = self.to_screen_i32(layer, pos);
+        let max = self.to_screen_i32(
+            layer,
+            Point::new(pos.x + size.x as i32, pos.y + size.y as i32),
+        );
+
+        (
+            min,
+            Point::new((max.x - min.x).max(0) as u32, (max.y - min.y).max(0) as u32),
+        )
+    }
+
+    // Always sorts before everything else
+    pub fn push_clear(&mut self, color: u32) {
+        self.group.push_clear(color);
+        self.orders
+            .push(DrawOrder::new(Layer::Background, f32::NEG_INFINITY));
+    }
+
+    pub fn push_rect(
+        &mut self,
+        order: DrawOrder,
+        pos: Point<i32>,
+        size: Point<u32>,
+        color: &Color,
+    ) {
+        if self.is_rotated(order.layer) {
+            // Turned rects are two triangles
+            let corners: Vec<Vertex> = [(0, 0), (size.x, 0), (size.x, size.y), (0, size.y)]
+                .iter()
+                .map(|&(x, y)| {
+                    let corner = Point::new((pos.x + x as i32) as f32, (pos.y + y as i32) as f32);
+                    Vertex::new(
+                        self.to_screen(order.layer, corner),
+                        Point::new(0.0, 0.0),
+                        *color,
+                    )
+                })
+                .collect();
+
+            self.group
+                .push_triangle([corners[0], corners[1], corners[2]], None);
+            self.orders.push(order);
+            self.group
+                .push_triangle([corners[0], corners[2], corners[3]], None);
+        } else {
+            let (pos, size) = self.to_screen_rect(order.layer, pos, size);
+            self.group.push_rectangle(pos, size, color);
+        }
+        self.orders.push(order);
+    }
+
+    pub fn push_line(&mut self, order: DrawOrder, from: Point<i32>, to: Point<i32>, color: &Color) {
+        let from = self.to_screen_i32(order.layer, from);
+        let to = self.to_screen_i32(order.layer, to);
+        self.group.push_line(from, to, color);
+        self.orders.push(order);
+    }
+
+    pub fn push_bitmap(
+        &mut self,
+        order: DrawOrder,
+        texture: &'a Win32GameBitmap,
+        source: &Rect,
+        pos: Point<i32>,
+        size: Point<u32>,
+        flip: Flip,
+    ) {
+        if self.is_rotated(order.layer) {
+            let zoom = self.camera.get_zoom();
+            let transform = SpriteTransform {
+                pos: self.to_screen(order.layer, Point::new(pos.x as f32, pos.y as f32)),
+                size: Point::new(size.x as f32 * zoom, size.y as f32 * zoom),
+                pivot: Point::new(0.0, 0.0),
+                angle: self.camera.get_screen_angle(),
+                flip,
+            };
+
+            self.group
+                .push_rotated_bitmap(texture, source, &transform, Filter::Nearest);
+        } else {
+            let (pos, size) = self.to_screen_rect(order.layer, pos, size);
+            self.group
+                .push_bitmap(texture, source, pos, size, flip, Filter::Nearest);
+        }
+        self.orders.push(order);
+    }
+
+    // A sprite sheet frame stretched over size pixels. Queued bitmaps are pixel art,
+    // so they always use nearest filtering.
+    pub fn push_sprite(
+        &mut self,
+        order: DrawOrder,
+        sheet: &'a SpriteSheet,
+        frame: usize,
+        pos: Point<i32>,
+        size: Point<u32>,
+        flip: Flip,
+    ) {
+        self.push_bitmap(
+            order,
+            sheet.get_texture(),
+            sheet.get_frame_rect(frame),
+            pos,
+            size,
+            flip,
+        );
+    }
+
+    pub fn push_text(
+        &mut self,
+        order: DrawOrder,
+        font: &'a dyn Font,
+        text: &str,
+        pos: Point<i32>,
+        color: &Color,
+    ) {
+        // Only the position follows the camera, text stays readable at any zoom
+        let pos = self.to_screen_i32(order.layer, pos);
+        self.group.push_text(font, text, pos, color);
+        self.orders.push(order);
+    }
+
+    // Wrapped and aligned inside rect, a text command per line
+    pub fn push_text_in_rect(
+        &mut self,
+        order: DrawOrder,
+        font: &'a dyn Font,
+        text: &str,
+        rect: &Rect,
+        align: TextAlign,
+        color: &Color,
+    ) {
+        for (line, pos) in font::layout_text(font, text, rect, align) {
+            self.push_text(order, font, &line, pos, color);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    // Puts the commands in draw order
+    pub fn sort(&mut self) {
+        let orders = &self.orders;
+        let mut indices: Vec<usize> = (0..orders.len()).collect();
+        indices.sort_by(|&a, &b| {
+            orders[a]
+                .layer
+                .cmp(&orders[b].layer)
+                .then(orders[a].sort_key.total_cmp(&orders[b].sort_key))
+        });
+
+        self.group.reorder(&indices);
+        self.orders = indices.iter().map(|&index| self.orders[index]).collect();
+    }
+
+    // Sorts, draws and empties the queue
+    pub fn flush(&mut self, renderer: &TiledRenderer, buffer: &mut Win32GameBitmap) {
+        self.sort();
+        renderer.render(&self.group, buffer);
+
+        self.group.reset();
+        self.orders.clear();
+    }
+}
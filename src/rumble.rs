@@ -0,0 +1,292 @@
+// Controller vibration. Effects are played on a RumbleMixer, one per controller,
+// which works out the motor speeds every frame. The mixer doesn't touch XInput,
+// Win32Input sends what it returns, so it runs the same without a controller.
+//
+// The left motor is the heavy low frequency one, the right is the light buzzy
+// one. Each motor plays the strongest effect of the highest priority that's
+// driving it, so a big hit isn't drowned out by engine rumble and footsteps
+// don't add up past full power.
+
+#[derive(Clone, Copy, Debug)]
+pub struct RumbleEffect {
+    pub left: f32, // 0 to 1
+    pub right: f32,
+    pub duration: Option<f32>, // Seconds, None plays until stopped
+    pub attack: f32,           // Seconds to ramp up from nothing
+    pub fade: f32,             // Seconds to ramp down at the end
+    pub priority: i32,         // Higher wins
+}
+
+impl RumbleEffect {
+    pub fn new(left: f32, right: f32, duration: f32) -> Self {
+        Self {
+            left,
+            right,
+            duration: Some(duration),
+            attack: 0.0,
+            fade: 0.0,
+            priority: 0,
+        }
+    }
+
+    // Plays until stopped, a stopped looping effect still fades out
+    pub fn looping(left: f32, right: f32) -> Self {
+        Self {
+            duration: None,
+            ..RumbleEffect::new(left, right, 0.0)
+        }
+    }
+
+    pub fn with_envelope(mut self, attack: f32, fade: f32) -> Self {
+        self.attack = attack;
+        self.fade = fade;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RumbleHandle(u32);
+
+struct PlayingEffect {
+    handle: RumbleHandle,
+    effect: RumbleEffect,
+    time: f32,
+    stop_time: Option<f32>, // When a looping effect was stopped, it fades from there
+}
+
+impl PlayingEffect {
+    fn end_time(&self) -> Option<f32> {
+        match self.stop_time {
+            Some(stop) => Some(stop + self.effect.fade),
+            None => self.effect.duration,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.end_time().is_some_and(|end| self.time >= end)
+    }
+
+    // 0 to 1, how much of the effect's strength comes through right now
+    fn envelope(&self) -> f32 {
+        let mut amount = 1.0f32;
+
+        if self.effect.attack > 0.0 {
+            amount = amount.min(self.time / self.effect.attack);
+        }
+
+        if let Some(end) = self.end_time() {
+            if self.effect.fade > 0.0 {
+                amount = amount.min((end - self.time) / self.effect.fade);
+            }
+        }
+
+        amount.clamp(0.0, 1.0)
+    }
+}
+
+pub struct RumbleMixer {
+    effects: Vec<PlayingEffect>,
+    next_handle: u32,
+    strength: f32,
+    motors: (f32, f32),
+}
+
+impl RumbleMixer {
+    pub fn new() -> Self {
+        Self {
+            effects: Vec::new(),
+            next_handle: 0,
+            strength: 1.0,
+            motors: (0.0, 0.0),
+        }
+    }
+
+    pub fn play(&mut self, effect: RumbleEffect) -> RumbleHandle {
+        let handle = RumbleHandle(self.next_handle);
+        self.next_handle = self.next_handle.wrapping_add(1);
+
+        self.effects.push(PlayingEffect {
+            handle,
+            effect,
+            time: 0.0,
+            stop_time: None,
+        });
+
+        handle
+    }
+
+    // Starts the effect's fade, or removes it right away if it has none
+    pub fn stop(&mut self, handle: RumbleHandle) {
+        if let Some(playing) = self.effects.iter_mut().find(|p| p.handle == handle) {
+            if playing.stop_time.is_none() {
+                playing.stop_time = Some(playing.time);
+            }
+        }
+        self.effects.retain(|playing| !playing.is_finished());
+    }
+
+    // Cuts everything off, for pausing or a lost controller
+    pub fn stop_all(&mut self) {
+        self.effects.clear();
+        self.motors = (0.0, 0.0);
+    }
+
+    pub fn is_playing(&self, handle: RumbleHandle) -> bool {
+        self.effects.iter().any(|playing| playing.handle == handle)
+    }
+
+    // Player setting, 0 turns rumble off
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.clamp(0.0, 1.0);
+    }
+
+    pub fn get_strength(&self) -> f32 {
+        self.strength
+    }
+
+    // Advances every effect, drops the finished ones and returns the left and
+    // right motor speeds for this frame
+    pub fn update(&mut self, dt: f32) -> (f32, f32) {
+        for playing in &mut self.effects {
+            playing.time += dt;
+        }
+        self.effects.retain(|playing| !playing.is_finished());
+
+        let mut left = (i32::MIN, 0.0f32);
+        let mut right = (i32::MIN, 0.0f32);
+
+        for playing in &self.effects {
+            let envelope = playing.envelope();
+            let priority = playing.effect.priority;
+
+            for (motor, amount) in [
+                (&mut left, playing.effect.left),
+                (&mut right, playing.effect.right),
+            ] {
+                let amount = (amount * envelope).clamp(0.0, 1.0);
+                if amount <= 0.0 {
+                    continue;
+                }
+
+                if priority > motor.0 {
+                    *motor = (priority, amount);
+                } else if priority == motor.0 {
+                    motor.1 = motor.1.max(amount);
+                }
+            }
+        }
+
+        self.motors = (left.1 * self.strength, right.1 * self.strength);
+        self.motors
+    }
+
+    // What the last update returned
+    pub fn get_motors(&self) -> (f32, f32) {
+        self.motors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    #[test]
+    fn attack_ramps_up() {
+        let mut mixer = RumbleMixer::new();
+        mixer.play(RumbleEffect::new(1.0, 0.5, 2.0).with_envelope(1.0, 0.0));
+
+        let (left, right) = mixer.update(0.25);
+        assert!(close(left, 0.25) && close(right, 0.125));
+
+        let (left, _) = mixer.update(0.5);
+        assert!(close(left, 0.75));
+
+        let (left, _) = mixer.update(0.5);
+        assert!(close(left, 1.0));
+    }
+
+    #[test]
+    fn timed_effect_fades_out_and_ends() {
+        let mut mixer = RumbleMixer::new();
+        let handle = mixer.play(RumbleEffect::new(1.0, 1.0, 1.0).with_envelope(0.0, 0.5));
+
+        assert!(close(mixer.update(0.25).0, 1.0));
+        assert!(close(mixer.update(0.5).0, 0.5));
+        assert!(mixer.is_playing(handle));
+
+        assert_eq!(mixer.update(0.25), (0.0, 0.0));
+        assert!(!mixer.is_playing(handle));
+    }
+
+    #[test]
+    fn stopping_a_looping_effect_fades_it() {
+        let mut mixer = RumbleMixer::new();
+        let handle = mixer.play(RumbleEffect::looping(0.8, 0.0).with_envelope(0.0, 1.0));
+
+        assert!(close(mixer.update(10.0).0, 0.8));
+
+        mixer.stop(handle);
+        assert!(mixer.is_playing(handle));
+        assert!(close(mixer.update(0.5).0, 0.4));
+
+        mixer.update(0.5);
+        assert!(!mixer.is_playing(handle));
+    }
+
+    #[test]
+    fn stopping_without_a_fade_removes_it() {
+        let mut mixer = RumbleMixer::new();
+        let handle = mixer.play(RumbleEffect::looping(0.8, 0.8));
+
+        mixer.update(0.1);
+        mixer.stop(handle);
+        assert!(!mixer.is_playing(handle));
+        assert_eq!(mixer.update(0.1), (0.0, 0.0));
+    }
+
+    #[test]
+    fn higher_priority_wins_a_motor() {
+        let mut mixer = RumbleMixer::new();
+        mixer.play(RumbleEffect::looping(0.9, 0.2));
+        mixer.play(RumbleEffect::looping(0.3, 0.0).with_priority(1));
+
+        // The weaker hit takes the left motor, the right has nothing above 0
+        let (left, right) = mixer.update(0.1);
+        assert!(close(left, 0.3) && close(right, 0.2));
+    }
+
+    #[test]
+    fn same_priority_takes_the_strongest() {
+        let mut mixer = RumbleMixer::new();
+        mixer.play(RumbleEffect::looping(0.4, 0.6));
+        mixer.play(RumbleEffect::looping(0.7, 0.1));
+
+        let (left, right) = mixer.update(0.1);
+        assert!(close(left, 0.7) && close(right, 0.6));
+    }
+
+    #[test]
+    fn strength_scales_and_clamps() {
+        let mut mixer = RumbleMixer::new();
+        mixer.play(RumbleEffect::looping(1.0, 0.5));
+
+        mixer.set_strength(0.5);
+        let (left, right) = mixer.update(0.1);
+        assert!(close(left, 0.5) && close(right, 0.25));
+
+        mixer.set_strength(2.0);
+        assert!(close(mixer.get_strength(), 1.0));
+
+        mixer.set_strength(0.0);
+        assert_eq!(mixer.update(0.1), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,627 @@
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::math::alpha_blend;
+
+// Row kernels for the software renderer: fills, alpha blends and the texel
+// fetch of nearest neighbour scaling. Each one has an AVX2 (8 pixels), SSE2
+// (4 pixels) and scalar version, picked at runtime by what the CPU supports.
+// All of them give exactly the same pixels as the scalar version, blends use
+// the same rounding as math::alpha_blend.
+//
+// Pixels are 0xAARRGGBB. Blending follows Win32GameBitmap::blend_pixel: alpha 0
+// leaves the pixel alone, anything else writes an opaque result.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SimdLevel {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl SimdLevel {
+    pub fn get_name(self) -> &'static str {
+        match self {
+            SimdLevel::Scalar => "scalar",
+            SimdLevel::Sse2 => "sse2",
+            SimdLevel::Avx2 => "avx2",
+        }
+    }
+}
+
+// Best level this CPU can run, std caches the cpuid lookup
+pub fn detect() -> SimdLevel {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return SimdLevel::Avx2;
+        }
+        if is_x86_feature_detected!("sse2") {
+            return SimdLevel::Sse2;
+        }
+    }
+
+    SimdLevel::Scalar
+}
+
+// Every level this CPU can run, for benchmarks
+pub fn available_levels() -> Vec<SimdLevel> {
+    let mut levels = vec![SimdLevel::Scalar];
+    match detect() {
+        SimdLevel::Avx2 => levels.extend_from_slice(&[SimdLevel::Sse2, SimdLevel::Avx2]),
+        SimdLevel::Sse2 => levels.push(SimdLevel::Sse2),
+        SimdLevel::Scalar => {}
+    }
+
+    levels
+}
+
+// Sets every pixel to color
+pub fn fill(dest: &mut [u32], color: u32) {
+    fill_with(detect(), dest, color);
+}
+
+pub fn fill_with(level: SimdLevel, dest: &mut [u32], color: u32) {
+    let done = match level {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 => unsafe { fill_avx2(dest, color) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 => unsafe { fill_sse2(dest, color) },
+        _ => 0,
+    };
+
+    for pixel in &mut dest[done..] {
+        *pixel = color;
+    }
+}
+
+// Blends one color over every pixel
+pub fn blend_color(dest: &mut [u32], color: u32) {
+    blend_color_with(detect(), dest, color);
+}
+
+pub fn blend_color_with(level: SimdLevel, dest: &mut [u32], color: u32) {
+    match color >> 24 {
+        0 => {}
+        255 => fill_with(level, dest, color),
+        _ => {
+            let done = match level {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                SimdLevel::Avx2 => unsafe { blend_color_avx2(dest, color) },
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                SimdLevel::Sse2 => unsafe { blend_color_sse2(dest, color) },
+                _ => 0,
+            };
+
+            for pixel in &mut dest[done..] {
+                *pixel = alpha_blend(*pixel, color);
+            }
+        }
+    }
+}
+
+// Blends source pixels over dest pixels one to one, the shorter slice decides the length
+pub fn blend(dest: &mut [u32], source: &[u32]) {
+    blend_with(detect(), dest, source);
+}
+
+pub fn blend_with(level: SimdLevel, dest: &mut [u32], source: &[u32]) {
+    let length = dest.len().min(source.len());
+    let dest = &mut dest[..length];
+    let source = &source[..length];
+
+    let done = match level {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 => unsafe { blend_avx2(dest, source) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 => unsafe { blend_sse2(dest, source) },
+        _ => 0,
+    };
+
+    for (pixel, &color) in dest[done..].iter_mut().zip(&source[done..]) {
+        *pixel = blend_scalar(*pixel, color);
+    }
+}
+
+// Same as blend, but every channel of the source is multiplied by color first.
+// White leaves the source as it is, alpha fades it. Glyph atlases are white
+// with the coverage in alpha, so this draws text in color.
+pub fn blend_tinted(dest: &mut [u32], source: &[u32], color: u32) {
+    blend_tinted_with(detect(), dest, source, color);
+}
+
+pub fn blend_tinted_with(level: SimdLevel, dest: &mut [u32], source: &[u32], color: u32) {
+    let length = dest.len().min(source.len());
+    let dest = &mut dest[..length];
+    let source = &source[..length];
+
+    let done = match level {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 => unsafe { blend_tinted_avx2(dest, source, color) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 => unsafe { blend_tinted_sse2(dest, source, color) },
+        _ => 0,
+    };
+
+    for (pixel, &texel) in dest[done..].iter_mut().zip(&source[done..]) {
+        *pixel = blend_scalar(*pixel, tint_scalar(texel, color));
+    }
+}
+
+// Nearest neighbour fetch for scaled blits. Texel i is row[(start + i * step) >> 16],
+// clamped to the row, so start and step are 16.16 fixed point and step can be
+// negative for flipped sprites.
+pub fn gather(dest: &mut [u32], row: &[u32], start: i32, step: i32) {
+    gather_with(detect(), dest, row, start, step);
+}
+
+pub fn gather_with(level: SimdLevel, dest: &mut [u32], row: &[u32], start: i32, step: i32) {
+    if row.is_empty() {
+        return;
+    }
+
+    let done = match level {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Avx2 => unsafe { gather_avx2(dest, row, start, step) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdLevel::Sse2 => unsafe { gather_sse2(dest, row, start, step) },
+        _ => 0,
+    };
+
+    let last = row.len() as i32 - 1;
+    for (i, pixel) in dest.iter_mut().enumerate().skip(done) {
+        let texel = start.wrapping_add((i as i32).wrapping_mul(step)) >> 16;
+        *pixel = row[texel.max(0).min(last) as usize];
+    }
+}
+
+// (a * b + 127) / 255 on every channel
+fn tint_scalar(texel: u32, color: u32) -> u32 {
+    let mut result = 0;
+    for shift in [0, 8, 16, 24].iter() {
+        let a = (texel >> shift) & 0xFF;
+        let b = (color >> shift) & 0xFF;
+        result |= ((a * b + 127) / 255) << shift;
+    }
+
+    result
+}
+
+fn blend_scalar(dest: u32, color: u32) -> u32 {
+    match color >> 24 {
+        0 => dest,
+        255 => color,
+        _ => alpha_blend(dest, color),
+    }
+}
+
+// Returns how many pixels were written, the caller finishes the tail
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn fill_sse2(dest: &mut [u32], color: u32) -> usize {
+    let value = _mm_set1_epi32(color as i32);
+    let count = dest.len() / 4 * 4;
+    let pointer = dest.as_mut_ptr();
+
+    for i in (0..count).step_by(4) {
+        _mm_storeu_si128(pointer.add(i) as *mut __m128i, value);
+    }
+
+    count
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn fill_avx2(dest: &mut [u32], color: u32) -> usize {
+    let value = _mm256_set1_epi32(color as i32);
+    let count = dest.len() / 8 * 8;
+    let pointer = dest.as_mut_ptr();
+
+    for i in (0..count).step_by(8) {
+        _mm256_storeu_si256(pointer.add(i) as *mut __m256i, value);
+    }
+
+    count
+}
+
+// Blends two pixels widened to 16 bit channels. x / 255 is done as
+// (x + 1 + (x >> 8)) >> 8, which is exact for everything a blend can produce.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn blend_half_sse2(dest: __m128i, source: __m128i) -> __m128i {
+    let alpha = _mm_shufflehi_epi16(_mm_shufflelo_epi16(source, 0xFF), 0xFF);
+    let inverse_alpha = _mm_sub_epi16(_mm_set1_epi16(255), alpha);
+
+    let mut value = _mm_add_epi16(
+        _mm_mullo_epi16(source, alpha),
+        _mm_mullo_epi16(dest, inverse_alpha),
+    );
+    value = _mm_add_epi16(value, _mm_set1_epi16(127));
+    value = _mm_add_epi16(
+        value,
+        _mm_add_epi16(_mm_set1_epi16(1), _mm_srli_epi16(value, 8)),
+    );
+
+    _mm_srli_epi16(value, 8)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn blend4_sse2(dest: __m128i, source: __m128i) -> __m128i {
+    let zero = _mm_setzero_si128();
+    let alpha_mask = _mm_set1_epi32(0xFF000000u32 as i32);
+
+    let low = blend_half_sse2(
+        _mm_unpacklo_epi8(dest, zero),
+        _mm_unpacklo_epi8(source, zero),
+    );
+    let high = blend_half_sse2(
+        _mm_unpackhi_epi8(dest, zero),
+        _mm_unpackhi_epi8(source, zero),
+    );
+    let blended = _mm_or_si128(_mm_packus_epi16(low, high), alpha_mask);
+
+    // Fully transparent source pixels keep the old pixel, alpha included
+    let transparent = _mm_cmpeq_epi32(_mm_and_si128(source, alpha_mask), zero);
+    _mm_or_si128(
+        _mm_and_si128(transparent, dest),
+        _mm_andnot_si128(transparent, blended),
+    )
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn blend_sse2(dest: &mut [u32], source: &[u32]) -> usize {
+    let count = dest.len() / 4 * 4;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m128i;
+    let source_pointer = source.as_ptr() as *const __m128i;
+
+    for i in 0..count / 4 {
+        let result = blend4_sse2(
+            _mm_loadu_si128(dest_pointer.add(i)),
+            _mm_loadu_si128(source_pointer.add(i)),
+        );
+        _mm_storeu_si128(dest_pointer.add(i), result);
+    }
+
+    count
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn blend_color_sse2(dest: &mut [u32], color: u32) -> usize {
+    let count = dest.len() / 4 * 4;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m128i;
+    let source = _mm_set1_epi32(color as i32);
+
+    for i in 0..count / 4 {
+        let result = blend4_sse2(_mm_loadu_si128(dest_pointer.add(i)), source);
+        _mm_storeu_si128(dest_pointer.add(i), result);
+    }
+
+    count
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn blend_half_avx2(dest: __m256i, source: __m256i) -> __m256i {
+    let alpha = _mm256_shufflehi_epi16(_mm256_shufflelo_epi16(source, 0xFF), 0xFF);
+    let inverse_alpha = _mm256_sub_epi16(_mm256_set1_epi16(255), alpha);
+
+    let mut value = _mm256_add_epi16(
+        _mm256_mullo_epi16(source, alpha),
+        _mm256_mullo_epi16(dest, inverse_alpha),
+    );
+    value = _mm256_add_epi16(value, _mm256_set1_epi16(127));
+    value = _mm256_add_epi16(
+        value,
+        _mm256_add_epi16(_mm256_set1_epi16(1), _mm256_srli_epi16(value, 8)),
+    );
+
+    _mm256_srli_epi16(value, 8)
+}
+
+// Channels widened to 16 bits times the color's, rounded the same as tint_scalar
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn tint_half_sse2(source: __m128i, color: __m128i) -> __m128i {
+    let mut value = _mm_add_epi16(_mm_mullo_epi16(source, color), _mm_set1_epi16(127));
+    value = _mm_add_epi16(
+        value,
+        _mm_add_epi16(_mm_set1_epi16(1), _mm_srli_epi16(value, 8)),
+    );
+
+    _mm_srli_epi16(value, 8)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn blend_tinted_sse2(dest: &mut [u32], source: &[u32], color: u32) -> usize {
+    let count = dest.len() / 4 * 4;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m128i;
+    let source_pointer = source.as_ptr() as *const __m128i;
+
+    let zero = _mm_setzero_si128();
+    let color = _mm_unpacklo_epi8(_mm_set1_epi32(color as i32), zero);
+
+    for i in 0..count / 4 {
+        let texels = _mm_loadu_si128(source_pointer.add(i));
+        let tinted = _mm_packus_epi16(
+            tint_half_sse2(_mm_unpacklo_epi8(texels, zero), color),
+            tint_half_sse2(_mm_unpackhi_epi8(texels, zero), color),
+        );
+
+        let result = blend4_sse2(_mm_loadu_si128(dest_pointer.add(i)), tinted);
+        _mm_storeu_si128(dest_pointer.add(i), result);
+    }
+
+    count
+}
+
+// Unpack and pack both work within 128 bit lanes, so the pixel order comes back out as it went in
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn blend8_avx2(dest: __m256i, source: __m256i) -> __m256i {
+    let zero = _mm256_setzero_si256();
+    let alpha_mask = _mm256_set1_epi32(0xFF000000u32 as i32);
+
+    let low = blend_half_avx2(
+        _mm256_unpacklo_epi8(dest, zero),
+        _mm256_unpacklo_epi8(source, zero),
+    );
+    let high = blend_half_avx2(
+        _mm256_unpackhi_epi8(dest, zero),
+        _mm256_unpackhi_epi8(source, zero),
+    );
+    let blended = _mm256_or_si256(_mm256_packus_epi16(low, high), alpha_mask);
+
+    let transparent = _mm256_cmpeq_epi32(_mm256_and_si256(source, alpha_mask), zero);
+    _mm256_blendv_epi8(blended, dest, transparent)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn blend_avx2(dest: &mut [u32], source: &[u32]) -> usize {
+    let count = dest.len() / 8 * 8;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m256i;
+    let source_pointer = source.as_ptr() as *const __m256i;
+
+    for i in 0..count / 8 {
+        let result = blend8_avx2(
+            _mm256_loadu_si256(dest_pointer.add(i)),
+            _mm256_loadu_si256(source_pointer.add(i)),
+        );
+        _mm256_storeu_si256(dest_pointer.add(i), result);
+    }
+
+    count
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn blend_color_avx2(dest: &mut [u32], color: u32) -> usize {
+    let count = dest.len() / 8 * 8;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m256i;
+    let source = _mm256_set1_epi32(color as i32);
+
+    for i in 0..count / 8 {
+        let result = blend8_avx2(_mm256_loadu_si256(dest_pointer.add(i)), source);
+        _mm256_storeu_si256(dest_pointer.add(i), result);
+    }
+
+    count
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn tint_half_avx2(source: __m256i, color: __m256i) -> __m256i {
+    let mut value = _mm256_add_epi16(_mm256_mullo_epi16(source, color), _mm256_set1_epi16(127));
+    value = _mm256_add_epi16(
+        value,
+        _mm256_add_epi16(_mm256_set1_epi16(1), _mm256_srli_epi16(value, 8)),
+    );
+
+    _mm256_srli_epi16(value, 8)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn blend_tinted_avx2(dest: &mut [u32], source: &[u32], color: u32) -> usize {
+    let count = dest.len() / 8 * 8;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m256i;
+    let source_pointer = source.as_ptr() as *const __m256i;
+
+    let zero = _mm256_setzero_si256();
+    let color = _mm256_unpacklo_epi8(_mm256_set1_epi32(color as i32), zero);
+
+    for i in 0..count / 8 {
+        let texels = _mm256_loadu_si256(source_pointer.add(i));
+        let tinted = _mm256_packus_epi16(
+            tint_half_avx2(_mm256_unpacklo_epi8(texels, zero), color),
+            tint_half_avx2(_mm256_unpackhi_epi8(texels, zero), color),
+        );
+
+        let result = blend8_avx2(_mm256_loadu_si256(dest_pointer.add(i)), tinted);
+        _mm256_storeu_si256(dest_pointer.add(i), result);
+    }
+
+    count
+}
+
+// SSE2 has no gather and no 32 bit min, max or multiply, so the positions are
+// stepped and clamped 4 at a time and the loads are done one by one
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn gather_sse2(dest: &mut [u32], row: &[u32], start: i32, step: i32) -> usize {
+    let count = dest.len() / 4 * 4;
+
+    let last = _mm_set1_epi32(row.len() as i32 - 1);
+    let zero = _mm_setzero_si128();
+    let advance = _mm_set1_epi32(step.wrapping_mul(4));
+    let mut position = _mm_setr_epi32(
+        start,
+        start.wrapping_add(step),
+        start.wrapping_add(step.wrapping_mul(2)),
+        start.wrapping_add(step.wrapping_mul(3)),
+    );
+    let mut texels = [0i32; 4];
+
+    for i in (0..count).step_by(4) {
+        let texel = _mm_srai_epi32(position, 16);
+        let texel = _mm_andnot_si128(_mm_cmplt_epi32(texel, zero), texel);
+        let past_end = _mm_cmpgt_epi32(texel, last);
+        let texel = _mm_or_si128(
+            _mm_and_si128(past_end, last),
+            _mm_andnot_si128(past_end, texel),
+        );
+        _mm_storeu_si128(texels.as_mut_ptr() as *mut __m128i, texel);
+
+        for (pixel, &texel) in dest[i..i + 4].iter_mut().zip(&texels) {
+            *pixel = *row.get_unchecked(texel as usize);
+        }
+
+        position = _mm_add_epi32(position, advance);
+    }
+
+    count
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn gather_avx2(dest: &mut [u32], row: &[u32], start: i32, step: i32) -> usize {
+    let count = dest.len() / 8 * 8;
+    let dest_pointer = dest.as_mut_ptr() as *mut __m256i;
+
+    let last = _mm256_set1_epi32(row.len() as i32 - 1);
+    let zero = _mm256_setzero_si256();
+    let advance = _mm256_set1_epi32(step.wrapping_mul(8));
+    let mut position = _mm256_add_epi32(
+        _mm256_set1_epi32(start),
+        _mm256_mullo_epi32(
+            _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7),
+            _mm256_set1_epi32(step),
+        ),
+    );
+
+    for i in 0..count / 8 {
+        let texel = _mm256_min_epi32(
+            _mm256_max_epi32(_mm256_srai_epi32(position, 16), zero),
+            last,
+        );
+        let pixels = _mm256_i32gather_epi32(row.as_ptr() as *const i32, texel, 4);
+        _mm256_storeu_si256(dest_pointer.add(i), pixels);
+
+        position = _mm256_add_epi32(position, advance);
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, the tests only need something that isn't a pattern
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        // Alpha 0 and 255 take their own paths, so they show up often
+        fn pixel(&mut self) -> u32 {
+            let color = self.next() & 0x00FFFFFF;
+            let alpha = match self.next() % 4 {
+                0 => 0,
+                1 => 0xFF,
+                _ => self.next() & 0xFF,
+            };
+            alpha << 24 | color
+        }
+
+        fn row(&mut self, length: usize) -> Vec<u32> {
+            (0..length).map(|_| self.pixel()).collect()
+        }
+    }
+
+    // Runs check on rows of every length up to 40 at every level this CPU has
+    fn compare(check: impl Fn(SimdLevel, &mut Random, usize)) {
+        for level in available_levels() {
+            for length in 0..40 {
+                for seed in 1..20 {
+                    check(level, &mut Random(seed * 7919 + length as u32), length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blend_matches_scalar() {
+        compare(|level, random, length| {
+            let dest = random.row(length);
+            let source = random.row(length);
+
+            let mut expected = dest.clone();
+            blend_with(SimdLevel::Scalar, &mut expected, &source);
+            let mut result = dest;
+            blend_with(level, &mut result, &source);
+
+            assert_eq!(result, expected, "{} length {}", level.get_name(), length);
+        });
+    }
+
+    #[test]
+    fn blend_color_matches_scalar() {
+        compare(|level, random, length| {
+            let dest = random.row(length);
+            let color = random.pixel();
+
+            let mut expected = dest.clone();
+            blend_color_with(SimdLevel::Scalar, &mut expected, color);
+            let mut result = dest;
+            blend_color_with(level, &mut result, color);
+
+            assert_eq!(result, expected, "{} length {}", level.get_name(), length);
+        });
+    }
+
+    #[test]
+    fn blend_tinted_matches_scalar() {
+        compare(|level, random, length| {
+            let dest = random.row(length);
+            let source = random.row(length);
+            let color = random.pixel();
+
+            let mut expected = dest.clone();
+            blend_tinted_with(SimdLevel::Scalar, &mut expected, &source, color);
+            let mut result = dest;
+            blend_tinted_with(level, &mut result, &source, color);
+
+            assert_eq!(result, expected, "{} length {}", level.get_name(), length);
+        });
+    }
+
+    #[test]
+    fn gather_matches_scalar() {
+        compare(|level, random, length| {
+            let row_length = 1 + random.next() as usize % 40;
+            let row = random.row(row_length);
+            // Up to 4 texels a pixel either way, starting a bit outside the row
+            let step = (random.next() % (8 << 16)) as i32 - (4 << 16);
+            let start = (random.next() % ((row.len() as u32 + 8) << 16)) as i32 - (4 << 16);
+
+            let mut expected = vec![0; length];
+            gather_with(SimdLevel::Scalar, &mut expected, &row, start, step);
+            let mut result = vec![0; length];
+            gather_with(level, &mut result, &row, start, step);
+
+            assert_eq!(result, expected, "{} length {}", level.get_name(), length);
+        });
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::{
+    blit::{Filter, Flip, SpriteTransform},
+    json::{parse_json, JsonValue},
+    math::{Point, Rect},
+    win32_engine::Win32GameBitmap,
+};
+
+// A texture with named sub-rects (frames) so a character's whole animation
+// set can live in one image.
+//
+// Frames come from a grid, a manifest file, or by packing separate images
+// into a new atlas at load time.
+//
+// Text manifest, one frame per line, '#' starts a comment:
+//   image hero.bmpx
+//   down_standing 0 0 16 16
+//   down_walk_1 16 0 16 16
+//
+// Json manifests use the TexturePacker "hash" or "array" layout:
+//   { "frames": { "down_standing": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 } } },
+//     "meta": { "image": "hero.png" } }
+pub struct SpriteSheet {
+    texture: Win32GameBitmap,
+    frames: Vec<Rect>,
+    names: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+    pub fn new(texture: Win32GameBitmap) -> Self {
+        Self {
+            texture,
+            frames: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    // Cuts the texture into equally sized frames, left to right and top to bottom.
+    // Frames are named by their index ("0", "1", ...) until renamed.
+    pub fn from_grid(file_path: &str, frame_width: u32, frame_height: u32) -> Self {
+        assert!(
+            frame_width > 0 && frame_height > 0,
+            "Sprite sheet frames can't be empty"
+        );
+
+        let mut result = SpriteSheet::new(Win32GameBitmap::load_image(file_path));
+
+        let columns = result.texture.get_width() as u32 / frame_width;
+        let rows = result.texture.get_height() as u32 / frame_height;
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let index = result.frames.len();
+                result.add_frame(
+                    &index.to_string(),
+                    Rect::new(
+                        column * frame_width,
+                        row * frame_height,
+                        frame_width,
+                        frame_height,
+                    ),
+                );
+            }
+        }
+
+        result
+    }
+
+    // Reads a .json or text manifest, the image path is relative to the manifest
+    pub fn from_manifest(file_path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+        let directory = Path::new(file_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+
+        let mut image = None;
+        let mut frames = Vec::new();
+
+        if file_path.to_lowercase().ends_with(".json") {
+            let root = parse_json(&text)?;
+
+            image = root
+                .get("meta")
+                .and_then(|meta| meta.get("image"))
+                .and_then(|image| image.as_str())
+                .map(|image| image.to_string());
+
+            // Hash layout is { name: frame }, array layout is [{ filename, frame }]
+            let entries: Vec<(String, &JsonValue)> = match root.get("frames") {
+                Some(JsonValue::Object(members)) => members
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value))
+                    .collect(),
+                Some(JsonValue::Array(values)) => values
+                    .iter()
+                    .map(|value| {
+                        let name = value
+                            .get("filename")
+                            .and_then(|name| name.as_str())
+                            .unwrap_or("");
+                        (name.to_string(), value)
+                    })
+                    .collect(),
+                _ => return Err(format!("{} has no frames", file_path)),
+            };
+
+            for (name, value) in entries {
+                let frame = value
+                    .get("frame")
+                    .ok_or_else(|| format!("Frame '{}' has no rect", name))?;
+                let field = |key: &str| {
+                    frame
+                        .get(key)
+                        .and_then(|field| field.as_f64())
+                        .map(|field| field as u32)
+                        .ok_or_else(|| format!("Frame '{}' is missing '{}'", name, key))
+                };
+
+                frames.push((
+                    name.clone(),
+                    Rect::new(field("x")?, field("y")?, field("w")?, field("h")?),
+                ));
+            }
+        } else {
+            for (line_number, line) in text.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or("").trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                if parts[0] == "image" && parts.len() == 2 {
+                    image = Some(parts[1].to_string());
+                    continue;
+                }
+
+                let numbers: Vec<u32> = parts[1..]
+                    .iter()
+                    .filter_map(|part| part.parse().ok())
+                    .collect();
+                if parts.len() != 5 || numbers.len() != 4 {
+                    return Err(format!(
+                        "{} line {}: expected 'name x y w h'",
+                        file_path,
+                        line_number + 1
+                    ));
+                }
+
+                frames.push((
+                    parts[0].to_string(),
+                    Rect::new(numbers[0], numbers[1], numbers[2], numbers[3]),
+                ));
+            }
+        }
+
+        let image = image.ok_or_else(|| format!("{} doesn't name an image", file_path))?;
+        let image_path = directory.join(image).to_string_lossy().into_owned();
+
+        let texture = Win32GameBitmap::try_load_image(&image_path)?;
+        let width = texture.get_width() as u32;
+        let height = texture.get_height() as u32;
+
+        let mut result = SpriteSheet::new(texture);
+        for (name, rect) in frames {
+            if rect.x.saturating_add(rect.w) > width || rect.y.saturating_add(rect.h) > height {
+                return Err(format!("Frame '{}' is outside the texture", name));
+            }
+            result.add_frame(&name, rect);
+        }
+
+        Ok(result)
+    }
+
+    // Packs separate images into one atlas, every image becomes a frame named after it.
+    // Simple shelf packing: tallest images first, rows fill up to the atlas width.
+    pub fn pack(images: &[(&str, &str)], atlas_width: u32) -> Self {
+        let mut loaded: Vec<(&str, Win32GameBitmap)> = images
+            .iter()
+            .map(|(name, file_path)| (*name, Win32GameBitmap::load_image(file_path)))
+            .collect();
+        loaded.sort_by(|a, b| b.1.get_height().cmp(&a.1.get_height()));
+
+        let mut placements = Vec::with_capacity(loaded.len());
+        let mut shelf_x = 0;
+        let mut shelf_y = 0;
+        let mut shelf_height = 0;
+
+        for (_, image) in &loaded {
+            let width = image.get_width() as u32;
+            let height = image.get_height() as u32;
+            assert!(width <= atlas_width, "Image is wider than the atlas");
+
+            if shelf_x + width > atlas_width {
+                shelf_y += shelf_height;
+                shelf_x = 0;
+                shelf_height = 0;
+            }
+
+            placements.push(Rect::new(shelf_x, shelf_y, width, height));
+            shelf_x += width;
+            shelf_height = shelf_height.max(height);
+        }
+
+        let atlas_height = shelf_y + shelf_height;
+        let mut pixels = vec![0u32; (atlas_width * atlas_height) as usize];
+
+        for ((_, image), rect) in loaded.iter().zip(&placements) {
+            for y in 0..rect.h {
+                for x in 0..rect.w {
+                    pixels[((rect.y + y) * atlas_width + rect.x + x) as usize] =
+                        image.get_pixel(x as i32, y as i32);
+                }
+            }
+        }
+
+        let texture =
+            Win32GameBitmap::from_pixels(atlas_width as i32, atlas_height as i32, &pixels);
+        let mut result = SpriteSheet::new(texture);
+        for ((name, _), rect) in loaded.iter().zip(placements) {
+            result.add_frame(name, rect);
+        }
+
+        result
+    }
+
+    // Returns the frame index, adding a frame with an existing name replaces the name lookup
+    pub fn add_frame(&mut self, name: &str, rect: Rect) -> usize {
+        assert!(
+            rect.x.saturating_add(rect.w) <= self.texture.get_width() as u32
+                && rect.y.saturating_add(rect.h) <= self.texture.get_height() as u32,
+            "Frame '{}' is outside the texture",
+            name
+        );
+        self.frames.push(rect);
+        self.names.insert(name.to_string(), self.frames.len() - 1);
+
+        self.frames.len() - 1
+    }
+
+    pub fn rename_frame(&mut self, index: usize, name: &str) {
+        self.names.retain(|_, frame| *frame != index);
+        self.names.insert(name.to_string(), index);
+    }
+
+    pub fn get_frame(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get_frame_rect(&self, index: usize) -> &Rect {
+        &self.frames[index]
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn get_texture(&self) -> &Win32GameBitmap {
+        &self.texture
+    }
+
+    pub fn draw_frame(&self, index: usize, pos: Point<i32>, buffer: &mut Win32GameBitmap) {
+        self.texture
+            .draw_bmp_region(&self.frames[index], pos, buffer);
+    }
+
+    pub fn draw_frame_scaled(
+        &self,
+        index: usize,
+        pos: Point<i32>,
+        size: Point<u32>,
+        flip: Flip,
+        filter: Filter,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        self.texture
+            .draw_bmp_scaled(&self.frames[index], pos, size, flip, filter, buffer);
+    }
+
+    pub fn draw_frame_rotated(
+        &self,
+        index: usize,
+        transform: &SpriteTransform,
+        filter: Filter,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        self.texture
+            .draw_bmp_rotated(&self.frames[index], transform, filter, buffer);
+    }
+
+    // Draws nothing if there is no frame with that name
+    pub fn draw(&self, name: &str, pos: Point<i32>, buffer: &mut Win32GameBitmap) {
+        if let Some(index) = self.get_frame(name) {
+            self.draw_frame(index, pos, buffer);
+        }
+    }
+}
@@ -0,0 +1,857 @@
+use std::path::Path;
+
+use crate::{
+    entity_manager::EntityManager,
+    inflate::{gzip_decompress, zlib_decompress},
+    json::{parse_json, JsonValue},
+    math::{Point, Rect},
+    parallax::ParallaxLayer,
+    tilemap::{Tilemap, Tileset, EMPTY_TILE},
+    win32_engine::Win32GameBitmap,
+    xml::{parse_xml, XmlElement},
+};
+
+// Loader for maps made in Tiled (https://www.mapeditor.org/).
+// Supports .tmx (csv, xml and base64 with optional zlib/gzip layer data) and
+// .json maps, embedded or external tilesets, tile and group layers, and
+// object layers whose objects get spawned into the EntityManager by type.
+//
+// Tiles with a bool property "solid" set to true block movement.
+//
+// Image layers become parallax backgrounds behind all the tile layers, using
+// their parallax factor, offset and repeat settings. Float properties "scrollx"
+// and "scrolly" make them scroll on their own (pixels per second).
+
+// Tiled keeps flip flags in the top bits of every tile id
+const GID_FLAGS: u32 = 0xF0000000;
+
+struct TiledTileset {
+    first_gid: u32,
+    tile_size: u32,
+    tile_count: u32,
+    margin: u32,
+    spacing: u32,
+    image: Option<String>,
+    tile_images: Vec<(u32, String)>, // Image collection tilesets have an image per tile
+    solid_tiles: Vec<u32>,
+}
+
+struct TiledLayer {
+    name: String,
+    visible: bool,
+    foreground: bool, // Bool property "foreground", drawn over the entities
+    gids: Vec<u32>,
+}
+
+struct TiledImageLayer {
+    image: String,
+    visible: bool,
+    offset: Point<f32>,
+    factor: Point<f32>,
+    repeat_x: bool,
+    repeat_y: bool,
+    scroll_speed: Point<f32>,
+}
+
+struct TiledObject {
+    type_name: String,
+    rect: Rect,
+    properties: Vec<(String, String)>,
+}
+
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    tilesets: Vec<TiledTileset>,
+    layers: Vec<TiledLayer>,
+    image_layers: Vec<TiledImageLayer>,
+    parallax_origin: Point<f32>,
+    objects: Vec<TiledObject>,
+}
+
+// Picks the format from the extension, everything that isn't .tmx is read as json
+pub fn load_tiled_map(
+    file_path: &str,
+    entity_manager: &mut EntityManager,
+) -> Result<Tilemap, String> {
+    let text = std::fs::read_to_string(file_path)
+        .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+    let directory = Path::new(file_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    let map = if file_path.to_lowercase().ends_with(".tmx") {
+        read_tmx_map(&parse_xml(&text)?, directory)?
+    } else {
+        read_json_map(&parse_json(&text)?, directory)?
+    };
+
+    build_tilemap(map, entity_manager)
+}
+
+fn build_tilemap(map: TiledMap, entity_manager: &mut EntityManager) -> Result<Tilemap, String> {
+    let mut tileset = Tileset::new(map.tile_size);
+
+    // Our tiles are numbered in the order they get cut, so remember where every gid ended up
+    let mut gid_to_tile: Vec<u32> = Vec::new();
+
+    for tiled_tileset in &map.tilesets {
+        if tiled_tileset.tile_size != map.tile_size {
+            return Err(format!(
+                "Tileset tiles are {} pixels but the map uses {} pixel tiles",
+                tiled_tileset.tile_size, map.tile_size
+            ));
+        }
+
+        // Gids using the flag bits could never show up in a layer
+        let mut map_tile = |local_id: u32, tile: u32| -> Result<(), String> {
+            let gid = tiled_tileset
+                .first_gid
+                .checked_add(local_id)
+                .filter(|gid| gid & GID_FLAGS == 0)
+                .ok_or_else(|| {
+                    format!(
+                        "Tile {} of the tileset at gid {} is out of range",
+                        local_id, tiled_tileset.first_gid
+                    )
+                })? as usize;
+            if gid_to_tile.len() <= gid {
+                gid_to_tile.resize(gid + 1, EMPTY_TILE);
+            }
+            gid_to_tile[gid] = tile;
+            Ok(())
+        };
+
+        if let Some(image_path) = &tiled_tileset.image {
+            let image = Win32GameBitmap::try_load_image(image_path)?;
+            let first_tile =
+                tileset.add_bitmap_spaced(&image, tiled_tileset.margin, tiled_tileset.spacing);
+
+            for local_id in 0..tileset.tile_count() + 1 - first_tile {
+                map_tile(local_id, first_tile + local_id)?;
+            }
+        }
+
+        for (local_id, image_path) in &tiled_tileset.tile_images {
+            // add_bitmap would cut bigger images into several tiles, and drop
+            // smaller ones, but the gid only has room for one
+            let image = Win32GameBitmap::try_load_image(image_path)?;
+            if image.get_width() != map.tile_size as i32
+                || image.get_height() != map.tile_size as i32
+            {
+                return Err(format!(
+                    "Tile image '{}' is {}x{}, expected {} pixel tiles",
+                    image_path,
+                    image.get_width(),
+                    image.get_height(),
+                    map.tile_size
+                ));
+            }
+            map_tile(*local_id, tileset.add_bitmap(&image))?;
+        }
+
+        for local_id in &tiled_tileset.solid_tiles {
+            let gid = tiled_tileset.first_gid.checked_add(*local_id);
+            if let Some(&tile) = gid.and_then(|gid| gid_to_tile.get(gid as usize)) {
+                tileset.set_solid(tile, true);
+            }
+        }
+
+        if tiled_tileset.tile_count != 0 && tileset.tile_count() == 0 {
+            return Err("Tileset has tiles but no images".to_string());
+        }
+    }
+
+    let tile_count = map
+        .width
+        .checked_mul(map.height)
+        .ok_or_else(|| format!("Map size {}x{} is too large", map.width, map.height))?;
+    let mut tilemap = Tilemap::new(map.width, map.height, tileset);
+
+    for layer in &map.layers {
+        if layer.gids.len() != tile_count as usize {
+            return Err(format!(
+                "Layer '{}' has {} tiles, expected {}",
+                layer.name,
+                layer.gids.len(),
+                tile_count
+            ));
+        }
+
+        let index = tilemap.add_layer(&layer.name);
+        tilemap.set_layer_visible(index, layer.visible);
+        tilemap.set_layer_foreground(index, layer.foreground);
+
+        for (i, gid) in layer.gids.iter().enumerate() {
+            //NOTE: Flipped tiles are drawn unflipped for now
+            let gid = (gid & !GID_FLAGS) as usize;
+            let tile = gid_to_tile.get(gid).copied().unwrap_or(EMPTY_TILE);
+
+            tilemap.set_tile(index, i as u32 % map.width, i as u32 / map.width, tile);
+        }
+    }
+
+    let background = tilemap.get_background_mut();
+    background.set_origin(map.parallax_origin);
+
+    for image_layer in &map.image_layers {
+        let mut layer = ParallaxLayer::new(
+            Win32GameBitmap::try_load_image(&image_layer.image)?,
+            image_layer.factor,
+        );
+        layer.set_offset(image_layer.offset);
+        layer.set_repeat(image_layer.repeat_x, image_layer.repeat_y);
+        layer.set_scroll_speed(image_layer.scroll_speed);
+        layer.set_visible(image_layer.visible);
+
+        background.add_layer(layer);
+    }
+
+    for object in &map.objects {
+        entity_manager.spawn(&object.type_name, object.rect, &object.properties);
+    }
+
+    Ok(tilemap)
+}
+
+fn check_tile_size(width: u32, height: u32) -> Result<u32, String> {
+    if width == 0 {
+        Err("Tiles can't be 0 pixels wide".to_string())
+    } else if width != height {
+        Err(format!(
+            "Tiles are {}x{}, only square tiles are supported",
+            width, height
+        ))
+    } else {
+        Ok(width)
+    }
+}
+
+fn resolve_path(directory: &Path, source: &str) -> String {
+    directory.join(source).to_string_lossy().into_owned()
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let mut accumulator = 0u32;
+    let mut bit_count = 0;
+
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ if byte.is_ascii_whitespace() => continue,
+            _ => return Err(format!("Invalid base64 character '{}'", byte as char)),
+        };
+
+        accumulator = accumulator << 6 | value as u32;
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            result.push((accumulator >> bit_count) as u8);
+        }
+    }
+
+    Ok(result)
+}
+
+// Base64 layer data is little endian u32 gids, optionally compressed
+fn decode_layer_data(text: &str, compression: Option<&str>) -> Result<Vec<u32>, String> {
+    let bytes = decode_base64(text)?;
+
+    let bytes = match compression {
+        None | Some("") => bytes,
+        Some("zlib") => zlib_decompress(&bytes)?,
+        Some("gzip") => gzip_decompress(&bytes)?,
+        Some(other) => return Err(format!("Unsupported layer compression '{}'", other)),
+    };
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+fn parse_csv(text: &str) -> Result<Vec<u32>, String> {
+    text.split(',')
+        .map(|gid| gid.trim())
+        .filter(|gid| !gid.is_empty())
+        .map(|gid| {
+            gid.parse::<u32>()
+                .map_err(|_| format!("Invalid tile id '{}' in csv layer data", gid))
+        })
+        .collect()
+}
+
+// Objects are in pixels, tile objects have their origin at the bottom left
+fn object_rect(x: f64, y: f64, width: f64, height: f64, is_tile: bool) -> Rect {
+    let y = if is_tile { y - height } else { y };
+
+    Rect::new(
+        x.max(0.0) as u32,
+        y.max(0.0) as u32,
+        width.max(0.0) as u32,
+        height.max(0.0) as u32,
+    )
+}
+
+fn is_foreground(properties: &[(String, String)]) -> bool {
+    properties
+        .iter()
+        .any(|(name, value)| name == "foreground" && value == "true")
+}
+
+fn property_f32(properties: &[(String, String)], name: &str) -> f32 {
+    properties
+        .iter()
+        .find(|(key, _)| key == name)
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0.0)
+}
+
+// The object name is passed along as a "name" property unless one is set
+fn object_properties(
+    name: Option<&str>,
+    mut properties: Vec<(String, String)>,
+) -> Vec<(String, String)> {
+    if let Some(name) = name {
+        if !name.is_empty() && !properties.iter().any(|(key, _)| key == "name") {
+            properties.insert(0, ("name".to_string(), name.to_string()));
+        }
+    }
+
+    properties
+}
+
+/*
+    TMX
+*/
+
+fn xml_u32(element: &XmlElement, name: &str) -> Result<u32, String> {
+    element
+        .attribute(name)
+        .ok_or_else(|| format!("<{}> is missing '{}'", element.name, name))?
+        .parse::<u32>()
+        .map_err(|_| format!("<{}> has an invalid '{}'", element.name, name))
+}
+
+// Tiles are square here, a map or tileset with other tiles would be cut wrong
+fn xml_tile_size(element: &XmlElement) -> Result<u32, String> {
+    check_tile_size(
+        xml_u32(element, "tilewidth")?,
+        xml_u32(element, "tileheight")?,
+    )
+}
+
+fn xml_u32_or(element: &XmlElement, name: &str, default: u32) -> u32 {
+    element
+        .attribute(name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn xml_f64(element: &XmlElement, name: &str) -> f64 {
+    xml_f64_or(element, name, 0.0)
+}
+
+fn xml_f64_or(element: &XmlElement, name: &str, default: f64) -> f64 {
+    element
+        .attribute(name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn xml_properties(element: &XmlElement) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+
+    if let Some(properties) = element.child("properties") {
+        for property in properties.children_named("property") {
+            let name = property.attribute("name").unwrap_or("").to_string();
+            // Multi-line strings are stored as text instead of an attribute
+            let value = property
+                .attribute("value")
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| property.text.clone());
+
+            result.push((name, value));
+        }
+    }
+
+    result
+}
+
+fn read_tmx_tileset(
+    element: &XmlElement,
+    first_gid: u32,
+    directory: &Path,
+) -> Result<TiledTileset, String> {
+    // External tilesets keep everything but the first gid in a .tsx file
+    if let Some(source) = element.attribute("source") {
+        let path = resolve_path(directory, source);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|error| format!("Failed to read {}: {}", path, error))?;
+        let tileset_directory = Path::new(&path).parent().unwrap_or_else(|| Path::new(""));
+
+        return read_tmx_tileset(&parse_xml(&text)?, first_gid, tileset_directory);
+    }
+
+    let mut tileset = TiledTileset {
+        first_gid,
+        tile_size: xml_tile_size(element)?,
+        tile_count: xml_u32_or(element, "tilecount", 0),
+        margin: xml_u32_or(element, "margin", 0),
+        spacing: xml_u32_or(element, "spacing", 0),
+        image: None,
+        tile_images: Vec::new(),
+        solid_tiles: Vec::new(),
+    };
+
+    if let Some(image) = element.child("image") {
+        if let Some(source) = image.attribute("source") {
+            tileset.image = Some(resolve_path(directory, source));
+        }
+    }
+
+    for tile in element.children_named("tile") {
+        let id = xml_u32(tile, "id")?;
+
+        if let Some(source) = tile
+            .child("image")
+            .and_then(|image| image.attribute("source"))
+        {
+            tileset
+                .tile_images
+                .push((id, resolve_path(directory, source)));
+        }
+
+        if xml_properties(tile)
+            .iter()
+            .any(|(name, value)| name == "solid" && value == "true")
+        {
+            tileset.solid_tiles.push(id);
+        }
+    }
+
+    Ok(tileset)
+}
+
+fn read_tmx_layer_data(data: &XmlElement) -> Result<Vec<u32>, String> {
+    if data.child("chunk").is_some() {
+        return Err("Infinite maps are not supported".to_string());
+    }
+
+    match data.attribute("encoding") {
+        Some("csv") => parse_csv(&data.text),
+        Some("base64") => decode_layer_data(&data.text, data.attribute("compression")),
+        Some(other) => Err(format!("Unsupported layer encoding '{}'", other)),
+        None => Ok(data
+            .children_named("tile")
+            .map(|tile| xml_u32_or(tile, "gid", 0))
+            .collect()),
+    }
+}
+
+// Tile, image and object layers can be nested inside groups
+fn read_tmx_layers(
+    element: &XmlElement,
+    map: &mut TiledMap,
+    directory: &Path,
+) -> Result<(), String> {
+    for child in &element.children {
+        let visible = child.attribute("visible") != Some("0");
+
+        match child.name.as_str() {
+            "layer" => {
+                let data = child
+                    .child("data")
+                    .ok_or_else(|| "<layer> has no <data>".to_string())?;
+
+                map.layers.push(TiledLayer {
+                    name: child.attribute("name").unwrap_or("").to_string(),
+                    visible,
+                    foreground: is_foreground(&xml_properties(child)),
+                    gids: read_tmx_layer_data(data)?,
+                });
+            }
+            "imagelayer" => {
+                let source = child
+                    .child("image")
+                    .and_then(|image| image.attribute("source"))
+                    .ok_or_else(|| "<imagelayer> has no image".to_string())?;
+                let properties = xml_properties(child);
+
+                map.image_layers.push(TiledImageLayer {
+                    image: resolve_path(directory, source),
+                    visible,
+                    offset: Point::new(
+                        xml_f64(child, "offsetx") as f32,
+                        xml_f64(child, "offsety") as f32,
+                    ),
+                    factor: Point::new(
+                        xml_f64_or(child, "parallaxx", 1.0) as f32,
+                        xml_f64_or(child, "parallaxy", 1.0) as f32,
+                    ),
+                    repeat_x: child.attribute("repeatx") == Some("1"),
+                    repeat_y: child.attribute("repeaty") == Some("1"),
+                    scroll_speed: Point::new(
+                        property_f32(&properties, "scrollx"),
+                        property_f32(&properties, "scrolly"),
+                    ),
+                });
+            }
+            "objectgroup" => {
+                for object in child.children_named("object") {
+                    let type_name = object
+                        .attribute("type")
+                        .or_else(|| object.attribute("class"))
+                        .unwrap_or("");
+
+                    map.objects.push(TiledObject {
+                        type_name: type_name.to_string(),
+                        rect: object_rect(
+                            xml_f64(object, "x"),
+                            xml_f64(object, "y"),
+                            xml_f64(object, "width"),
+                            xml_f64(object, "height"),
+                            object.attribute("gid").is_some(),
+                        ),
+                        properties: object_properties(
+                            object.attribute("name"),
+                            xml_properties(object),
+                        ),
+                    });
+                }
+            }
+            "group" => read_tmx_layers(child, map, directory)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn read_tmx_map(root: &XmlElement, directory: &Path) -> Result<TiledMap, String> {
+    if root.name != "map" {
+        return Err("TMX root element isn't <map>".to_string());
+    }
+    if root.attribute("infinite") == Some("1") {
+        return Err("Infinite maps are not supported".to_string());
+    }
+    if root.attribute("orientation").unwrap_or("orthogonal") != "orthogonal" {
+        return Err("Only orthogonal maps are supported".to_string());
+    }
+
+    let mut map = TiledMap {
+        width: xml_u32(root, "width")?,
+        height: xml_u32(root, "height")?,
+        tile_size: xml_tile_size(root)?,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        image_layers: Vec::new(),
+        parallax_origin: Point::new(
+            xml_f64(root, "parallaxoriginx") as f32,
+            xml_f64(root, "parallaxoriginy") as f32,
+        ),
+        objects: Vec::new(),
+    };
+
+    for tileset in root.children_named("tileset") {
+        map.tilesets.push(read_tmx_tileset(
+            tileset,
+            xml_u32(tileset, "firstgid")?,
+            directory,
+        )?);
+    }
+
+    read_tmx_layers(root, &mut map, directory)?;
+
+    Ok(map)
+}
+
+/*
+    JSON
+*/
+
+fn json_u32(value: &JsonValue, name: &str) -> Result<u32, String> {
+    value
+        .get(name)
+        .and_then(|field| field.as_i64())
+        .map(|field| field as u32)
+        .ok_or_else(|| format!("Missing or invalid '{}' in json map", name))
+}
+
+fn json_tile_size(value: &JsonValue) -> Result<u32, String> {
+    check_tile_size(
+        json_u32(value, "tilewidth")?,
+        json_u32(value, "tileheight")?,
+    )
+}
+
+fn json_u32_or(value: &JsonValue, name: &str, default: u32) -> u32 {
+    value
+        .get(name)
+        .and_then(|field| field.as_i64())
+        .map(|field| field as u32)
+        .unwrap_or(default)
+}
+
+fn json_f64(value: &JsonValue, name: &str) -> f64 {
+    json_f64_or(value, name, 0.0)
+}
+
+fn json_f64_or(value: &JsonValue, name: &str, default: f64) -> f64 {
+    value
+        .get(name)
+        .and_then(|field| field.as_f64())
+        .unwrap_or(default)
+}
+
+fn json_bool(value: &JsonValue, name: &str) -> bool {
+    value
+        .get(name)
+        .and_then(|field| field.as_bool())
+        .unwrap_or(false)
+}
+
+fn json_properties(value: &JsonValue) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+
+    if let Some(properties) = value.get("properties").and_then(|field| field.as_array()) {
+        for property in properties {
+            let name = property.get("name").and_then(|name| name.as_str());
+            let value = property.get("value");
+
+            if let (Some(name), Some(value)) = (name, value) {
+                result.push((name.to_string(), value.to_text()));
+            }
+        }
+    }
+
+    result
+}
+
+fn read_json_tileset(
+    value: &JsonValue,
+    first_gid: u32,
+    directory: &Path,
+) -> Result<TiledTileset, String> {
+    if let Some(source) = value.get("source").and_then(|source| source.as_str()) {
+        let path = resolve_path(directory, source);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|error| format!("Failed to read {}: {}", path, error))?;
+        let tileset_directory = Path::new(&path).parent().unwrap_or_else(|| Path::new(""));
+
+        // Json maps can still point at .tsx tilesets
+        return if path.to_lowercase().ends_with(".tsx") {
+            read_tmx_tileset(&parse_xml(&text)?, first_gid, tileset_directory)
+        } else {
+            read_json_tileset(&parse_json(&text)?, first_gid, tileset_directory)
+        };
+    }
+
+    let mut tileset = TiledTileset {
+        first_gid,
+        tile_size: json_tile_size(value)?,
+        tile_count: json_u32_or(value, "tilecount", 0),
+        margin: json_u32_or(value, "margin", 0),
+        spacing: json_u32_or(value, "spacing", 0),
+        image: value
+            .get("image")
+            .and_then(|image| image.as_str())
+            .map(|image| resolve_path(directory, image)),
+        tile_images: Vec::new(),
+        solid_tiles: Vec::new(),
+    };
+
+    if let Some(tiles) = value.get("tiles").and_then(|tiles| tiles.as_array()) {
+        for tile in tiles {
+            let id = json_u32(tile, "id")?;
+
+            if let Some(image) = tile.get("image").and_then(|image| image.as_str()) {
+                tileset
+                    .tile_images
+                    .push((id, resolve_path(directory, image)));
+            }
+
+            if json_properties(tile)
+                .iter()
+                .any(|(name, value)| name == "solid" && value == "true")
+            {
+                tileset.solid_tiles.push(id);
+            }
+        }
+    }
+
+    Ok(tileset)
+}
+
+fn read_json_layers(
+    layers: &[JsonValue],
+    map: &mut TiledMap,
+    directory: &Path,
+) -> Result<(), String> {
+    for layer in layers {
+        let visible = layer
+            .get("visible")
+            .and_then(|visible| visible.as_bool())
+            .unwrap_or(true);
+        let name = layer
+            .get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        match layer.get("type").and_then(|layer_type| layer_type.as_str()) {
+            Some("tilelayer") => {
+                if layer.get("chunks").is_some() {
+                    return Err("Infinite maps are not supported".to_string());
+                }
+
+                let gids = match layer.get("data") {
+                    Some(JsonValue::Array(gids)) => gids
+                        .iter()
+                        .map(|gid| gid.as_f64().unwrap_or(0.0) as u32)
+                        .collect(),
+                    Some(JsonValue::String(text)) => {
+                        if layer.get("encoding").and_then(|encoding| encoding.as_str())
+                            != Some("base64")
+                        {
+                            return Err(format!("Layer '{}' has an unknown encoding", name));
+                        }
+                        decode_layer_data(
+                            text,
+                            layer
+                                .get("compression")
+                                .and_then(|compression| compression.as_str()),
+                        )?
+                    }
+                    _ => return Err(format!("Layer '{}' has no data", name)),
+                };
+
+                map.layers.push(TiledLayer {
+                    name,
+                    visible,
+                    foreground: is_foreground(&json_properties(layer)),
+                    gids,
+                });
+            }
+            Some("imagelayer") => {
+                let image = layer
+                    .get("image")
+                    .and_then(|image| image.as_str())
+                    .ok_or_else(|| format!("Image layer '{}' has no image", name))?;
+                let properties = json_properties(layer);
+
+                map.image_layers.push(TiledImageLayer {
+                    image: resolve_path(directory, image),
+                    visible,
+                    offset: Point::new(
+                        json_f64(layer, "offsetx") as f32,
+                        json_f64(layer, "offsety") as f32,
+                    ),
+                    factor: Point::new(
+                        json_f64_or(layer, "parallaxx", 1.0) as f32,
+                        json_f64_or(layer, "parallaxy", 1.0) as f32,
+                    ),
+                    repeat_x: json_bool(layer, "repeatx"),
+                    repeat_y: json_bool(layer, "repeaty"),
+                    scroll_speed: Point::new(
+                        property_f32(&properties, "scrollx"),
+                        property_f32(&properties, "scrolly"),
+                    ),
+                });
+            }
+            Some("objectgroup") => {
+                let objects = layer
+                    .get("objects")
+                    .and_then(|objects| objects.as_array())
+                    .unwrap_or(&[]);
+
+                for object in objects {
+                    let type_name = object
+                        .get("type")
+                        .or_else(|| object.get("class"))
+                        .and_then(|type_name| type_name.as_str())
+                        .unwrap_or("");
+
+                    map.objects.push(TiledObject {
+                        type_name: type_name.to_string(),
+                        rect: object_rect(
+                            json_f64(object, "x"),
+                            json_f64(object, "y"),
+                            json_f64(object, "width"),
+                            json_f64(object, "height"),
+                            object.get("gid").is_some(),
+                        ),
+                        properties: object_properties(
+                            object.get("name").and_then(|name| name.as_str()),
+                            json_properties(object),
+                        ),
+                    });
+                }
+            }
+            Some("group") => {
+                if let Some(children) = layer.get("layers").and_then(|children| children.as_array())
+                {
+                    read_json_layers(children, map, directory)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn read_json_map(root: &JsonValue, directory: &Path) -> Result<TiledMap, String> {
+    if root.get("infinite").and_then(|infinite| infinite.as_bool()) == Some(true) {
+        return Err("Infinite maps are not supported".to_string());
+    }
+    if root
+        .get("orientation")
+        .and_then(|orientation| orientation.as_str())
+        .unwrap_or("orthogonal")
+        != "orthogonal"
+    {
+        return Err("Only orthogonal maps are supported".to_string());
+    }
+
+    let mut map = TiledMap {
+        width: json_u32(root, "width")?,
+        height: json_u32(root, "height")?,
+        tile_size: json_tile_size(root)?,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        image_layers: Vec::new(),
+        parallax_origin: Point::new(
+            json_f64(root, "parallaxoriginx") as f32,
+            json_f64(root, "parallaxoriginy") as f32,
+        ),
+        objects: Vec::new(),
+    };
+
+    if let Some(tilesets) = root
+        .get("tilesets")
+        .and_then(|tilesets| tilesets.as_array())
+    {
+        for tileset in tilesets {
+            map.tilesets.push(read_json_tileset(
+                tileset,
+                json_u32(tileset, "firstgid")?,
+                directory,
+            )?);
+        }
+    }
+
+    if let Some(layers) = root.get("layers").and_then(|layers| layers.as_array()) {
+        read_json_layers(layers, &mut map, directory)?;
+    }
+
+    Ok(map)
+}
@@ -0,0 +1,364 @@
+use crate::{
+    blit::Flip,
+    camera::Camera2D,
+    math::{Point, Rect},
+    parallax::ParallaxBackground,
+    render_queue::{DrawOrder, Layer, RenderQueue},
+    win32_engine::Win32GameBitmap,
+};
+
+// Tile index 0 means "nothing here", real tiles start at 1
+pub const EMPTY_TILE: u32 = 0;
+
+pub struct Tileset {
+    tile_size: u32,
+    tiles: Vec<Win32GameBitmap>,
+    solid: Vec<bool>,
+}
+
+impl Tileset {
+    pub fn new(tile_size: u32) -> Self {
+        Self {
+            tile_size,
+            tiles: Vec::new(),
+            solid: Vec::new(),
+        }
+    }
+
+    // Cuts the image into tiles and returns the index of the first one
+    pub fn add_image(&mut self, file_path: &str) -> u32 {
+        let image = Win32GameBitmap::load_image(file_path);
+
+        self.add_bitmap(&image)
+    }
+
+    pub fn add_bitmap(&mut self, image: &Win32GameBitmap) -> u32 {
+        self.add_bitmap_spaced(image, 0, 0)
+    }
+
+    // Tiles are taken left to right, top to bottom, leftover pixels are ignored.
+    // Margin is the border around the image, spacing the gap between tiles.
+    pub fn add_bitmap_spaced(&mut self, image: &Win32GameBitmap, margin: u32, spacing: u32) -> u32 {
+        let first_tile = self.tiles.len() as u32 + 1;
+        let size = self.tile_size as i32;
+        let margin = margin as i32;
+        let step = size + spacing as i32;
+
+        let columns = (image.get_width() - margin * 2 + spacing as i32) / step;
+        let rows = (image.get_height() - margin * 2 + spacing as i32) / step;
+
+        for tile_y in 0..rows {
+            for tile_x in 0..columns {
+                let mut pixels = Vec::with_capacity((size * size) as usize);
+
+                for y in 0..size {
+                    for x in 0..size {
+                        pixels.push(
+                            image.get_pixel(margin + tile_x * step + x, margin + tile_y * step + y),
+                        );
+                    }
+                }
+
+                self.tiles
+                    .push(Win32GameBitmap::from_pixels(size, size, &pixels));
+                self.solid.push(false);
+            }
+        }
+
+        first_tile
+    }
+
+    pub fn set_solid(&mut self, tile: u32, solid: bool) {
+        if tile != EMPTY_TILE && tile as usize <= self.solid.len() {
+            self.solid[tile as usize - 1] = solid;
+        }
+    }
+
+    pub fn is_solid(&self, tile: u32) -> bool {
+        tile != EMPTY_TILE && tile as usize <= self.solid.len() && self.solid[tile as usize - 1]
+    }
+
+    pub fn get_tile(&self, tile: u32) -> Option<&Win32GameBitmap> {
+        if tile == EMPTY_TILE {
+            return None;
+        }
+
+        self.tiles.get(tile as usize - 1)
+    }
+
+    pub fn get_tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    pub fn tile_count(&self) -> u32 {
+        self.tiles.len() as u32
+    }
+}
+
+pub struct TileLayer {
+    name: String,
+    tiles: Vec<u32>,
+    visible: bool,
+    foreground: bool, // Drawn over the entities (tree tops, roofs)
+}
+
+impl TileLayer {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub struct Tilemap {
+    width: u32, // In tiles
+    height: u32,
+    tileset: Tileset,
+    layers: Vec<TileLayer>,
+    background: ParallaxBackground, // Drawn behind every tile layer
+}
+
+impl Tilemap {
+    pub fn new(width: u32, height: u32, tileset: Tileset) -> Self {
+        // Tile indices are computed in u32
+        assert!(
+            width.checked_mul(height).is_some(),
+            "Tilemap {}x{} is too large",
+            width,
+            height
+        );
+
+        Self {
+            width,
+            height,
+            tileset,
+            layers: Vec::new(),
+            background: ParallaxBackground::new(),
+        }
+    }
+
+    // Layers draw in the order they were added, returns the layer index
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            tiles: vec![EMPTY_TILE; (self.width * self.height) as usize],
+            visible: true,
+            foreground: false,
+        });
+
+        self.layers.len() - 1
+    }
+
+    // Tiles outside the map or on layers that don't exist are ignored
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, tile: u32) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            if x < self.width && y < self.height {
+                layer.tiles[(y * self.width + x) as usize] = tile;
+            }
+        }
+    }
+
+    pub fn get_tile(&self, layer: usize, x: u32, y: u32) -> u32 {
+        match self.layers.get(layer) {
+            Some(layer) if x < self.width && y < self.height => {
+                layer.tiles[(y * self.width + x) as usize]
+            }
+            _ => EMPTY_TILE,
+        }
+    }
+
+    // Area is in tiles, not pixels
+    pub fn fill(&mut self, layer: usize, area: &Rect, tile: u32) {
+        for y in area.y..area.y + area.h {
+            for x in area.x..area.x + area.w {
+                self.set_tile(layer, x, y, tile);
+            }
+        }
+    }
+
+    pub fn set_layer_visible(&mut self, layer: usize, visible: bool) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.visible = visible;
+        }
+    }
+
+    pub fn set_layer_foreground(&mut self, layer: usize, foreground: bool) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.foreground = foreground;
+        }
+    }
+
+    pub fn get_layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn get_tileset(&self) -> &Tileset {
+        &self.tileset
+    }
+
+    pub fn get_tileset_mut(&mut self) -> &mut Tileset {
+        &mut self.tileset
+    }
+
+    pub fn get_background(&self) -> &ParallaxBackground {
+        &self.background
+    }
+
+    pub fn get_background_mut(&mut self) -> &mut ParallaxBackground {
+        &mut self.background
+    }
+
+    // Scrolls the background
+    pub fn update(&mut self, dt: f32) {
+        self.background.update(dt);
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel_width(&self) -> u32 {
+        self.width * self.tileset.tile_size
+    }
+
+    pub fn pixel_height(&self) -> u32 {
+        self.height * self.tileset.tile_size
+    }
+
+    // View is the map pixel that ends up in the top left corner of the buffer
+    pub fn draw(&self, view: Point<i32>, buffer: &mut Win32GameBitmap) {
+        let size = self.tileset.tile_size as i32;
+
+        // Only walk the tiles that overlap the buffer
+        let first_x = (view.x.max(0) / size) as u32;
+        let first_y = (view.y.max(0) / size) as u32;
+        let last_x = (((view.x + buffer.get_width()) / size + 1).max(0) as u32).min(self.width);
+        let last_y = (((view.y + buffer.get_height()) / size + 1).max(0) as u32).min(self.height);
+
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            for y in first_y..last_y {
+                for x in first_x..last_x {
+                    let tile = layer.tiles[(y * self.width + x) as usize];
+
+                    if let Some(bitmap) = self.tileset.get_tile(tile) {
+                        let pos = Point::new(x as i32 * size - view.x, y as i32 * size - view.y);
+                        bitmap.draw_bmp(pos, buffer);
+                    }
+                }
+            }
+        }
+    }
+
+    // Same as draw but queued, foreground layers end up over the entities
+    pub fn queue_draw<'a>(&'a self, camera: &Camera2D, queue: &mut RenderQueue<'a>) {
+        let size = self.tileset.tile_size as i32;
+
+        // Only the tiles the camera can see
+        let (min, max) = camera.visible_area();
+        let first_x = (min.x.floor() as i32).div_euclid(size).max(0) as u32;
+        let first_y = (min.y.floor() as i32).div_euclid(size).max(0) as u32;
+        let last_x =
+            ((max.x.ceil() as i32).div_euclid(size) + 1).clamp(0, self.width as i32) as u32;
+        let last_y =
+            ((max.y.ceil() as i32).div_euclid(size) + 1).clamp(0, self.height as i32) as u32;
+
+        self.background.queue_draw(camera, queue);
+
+        let whole = Rect::new(0, 0, size as u32, size as u32);
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !layer.visible {
+                continue;
+            }
+
+            let queue_layer = if layer.foreground {
+                Layer::Foreground
+            } else {
+                Layer::Background
+            };
+
+            for y in first_y..last_y {
+                for x in first_x..last_x {
+                    let tile = layer.tiles[(y * self.width + x) as usize];
+
+                    if let Some(bitmap) = self.tileset.get_tile(tile) {
+                        let pos = Point::new(x as i32 * size, y as i32 * size);
+
+                        queue.push_bitmap(
+                            DrawOrder::new(queue_layer, index as f32),
+                            bitmap,
+                            &whole,
+                            pos,
+                            Point::new(size as u32, size as u32),
+                            Flip::NONE,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Anything outside the map counts as solid so entities can't walk off it
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return true;
+        }
+
+        self.layers.iter().any(|layer| {
+            self.tileset
+                .is_solid(layer.tiles[(y as u32 * self.width + x as u32) as usize])
+        })
+    }
+
+    // Pixel area check against every tile the area touches
+    pub fn area_collides(&self, x: i32, y: i32, w: u32, h: u32) -> bool {
+        let size = self.tileset.tile_size as i32;
+
+        let first_x = x.div_euclid(size);
+        let first_y = y.div_euclid(size);
+        let last_x = (x + w as i32 - 1).div_euclid(size);
+        let last_y = (y + h as i32 - 1).div_euclid(size);
+
+        for tile_y in first_y..=last_y {
+            for tile_x in first_x..=last_x {
+                if self.is_solid(tile_x, tile_y) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    pub fn rect_collides(&self, rect: &Rect) -> bool {
+        self.area_collides(rect.x as i32, rect.y as i32, rect.w, rect.h)
+    }
+
+    // Moves one axis at a time, a pixel at a time, stopping at the first solid tile.
+    // Returns which axes got blocked.
+    pub fn move_rect(&self, rect: &mut Rect, dx: i32, dy: i32) -> Point<bool> {
+        let mut blocked = Point::new(false, false);
+
+        for _ in 0..dx.abs() {
+            let next_x = rect.x as i32 + dx.signum();
+            if self.area_collides(next_x, rect.y as i32, rect.w, rect.h) {
+                blocked.x = true;
+                break;
+            }
+            rect.x = next_x as u32;
+        }
+
+        for _ in 0..dy.abs() {
+            let next_y = rect.y as i32 + dy.signum();
+            if self.area_collides(rect.x as i32, next_y, rect.w, rect.h) {
+                blocked.y = true;
+                break;
+            }
+            rect.y = next_y as u32;
+        }
+
+        blocked
+    }
+}
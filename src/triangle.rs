@@ -0,0 +1,282 @@
+use crate::{
+    blit::{sample_bilinear, Filter},
+    math::{Color, Point, Rect},
+    win32_engine::Win32GameBitmap,
+};
+
+// Textured and vertex colored triangles, for distorted sprites, trails and
+// fake 3D. Positions are in buffer pixels with 8 bits of sub-pixel precision,
+// the covered pixels follow the same top-left rule as raster::fill_triangle.
+//
+// The bounding box is walked in tiles, tiles completely outside an edge are
+// skipped and tiles completely inside skip the per pixel coverage test.
+
+const SUB_PIXEL: i64 = 256;
+const TILE_SIZE: i32 = 16;
+
+#[derive(Clone, Copy)]
+pub struct Vertex {
+    pub pos: Point<f32>,
+    pub uv: Point<f32>, // 0 to 1 across the texture source rect
+    pub color: Color,   // Multiplied with the texel, or the whole color without a texture
+    pub depth: f32,     // Distance from the camera, 1.0 everywhere gives affine mapping
+}
+
+impl Vertex {
+    pub fn new(pos: Point<f32>, uv: Point<f32>, color: Color) -> Self {
+        Self {
+            pos,
+            uv,
+            color,
+            depth: 1.0,
+        }
+    }
+
+    pub fn with_depth(mut self, depth: f32) -> Self {
+        self.depth = depth.max(0.0001);
+        self
+    }
+}
+
+pub struct TriangleTexture<'a> {
+    pub bitmap: &'a Win32GameBitmap,
+    pub source: Rect,
+    pub filter: Filter,
+}
+
+impl<'a> TriangleTexture<'a> {
+    // The whole bitmap
+    pub fn new(bitmap: &'a Win32GameBitmap, filter: Filter) -> Self {
+        Self {
+            bitmap,
+            source: Rect::new(0, 0, bitmap.get_width() as u32, bitmap.get_height() as u32),
+            filter,
+        }
+    }
+
+    fn sample(&self, u: f32, v: f32) -> u32 {
+        let u = self.source.x as f32 + u * self.source.w as f32;
+        let v = self.source.y as f32 + v * self.source.h as f32;
+
+        match self.filter {
+            Filter::Nearest => {
+                let texel_x = (u.floor() as i32)
+                    .max(self.source.x as i32)
+                    .min((self.source.x + self.source.w) as i32 - 1);
+                let texel_y = (v.floor() as i32)
+                    .max(self.source.y as i32)
+                    .min((self.source.y + self.source.h) as i32 - 1);
+                self.bitmap.get_pixel(texel_x, texel_y)
+            }
+            Filter::Bilinear => sample_bilinear(self.bitmap, &self.source, u, v),
+        }
+    }
+}
+
+// Edge function stepped across the buffer in whole pixels
+struct Edge {
+    step_x: i64,
+    step_y: i64,
+    origin: i64, // Value at the center of pixel (0, 0)
+    bias: i64,   // Keeps pixel centers on bottom and right edges out
+}
+
+impl Edge {
+    fn new(from: Point<i64>, to: Point<i64>) -> Self {
+        let edge_x = to.x - from.x;
+        let edge_y = to.y - from.y;
+        let center = SUB_PIXEL / 2;
+
+        // Winding is clockwise on screen, so top edges run right and left edges run up
+        let is_top_left = (edge_y == 0 && edge_x > 0) || edge_y < 0;
+
+        Self {
+            step_x: -edge_y * SUB_PIXEL,
+            step_y: edge_x * SUB_PIXEL,
+            origin: edge_x * (center - from.y) - edge_y * (center - from.x),
+            bias: if is_top_left { 0 } else { -1 },
+        }
+    }
+
+    fn at(&self, x: i32, y: i32) -> i64 {
+        self.origin + x as i64 * self.step_x + y as i64 * self.step_y
+    }
+}
+
+pub fn draw_triangle(
+    buffer: &mut Win32GameBitmap,
+    a: &Vertex,
+    b: &Vertex,
+    c: &Vertex,
+    texture: Option<&TriangleTexture>,
+) {
+    // Sampling reads the bitmap unchecked, keep the source inside it
+    let clipped = match texture {
+        Some(texture) => match texture.bitmap.clip_source(&texture.source) {
+            Some(source) => Some(TriangleTexture { source, ..*texture }),
+            None => return,
+        },
+        None => None,
+    };
+    let texture = clipped.as_ref();
+
+    let fixed = |point: Point<f32>| {
+        Point::new(
+            (point.x * SUB_PIXEL as f32).round() as i64,
+            (point.y * SUB_PIXEL as f32).round() as i64,
+        )
+    };
+
+    let (mut b, mut c) = (b, c);
+    let area = {
+        let (a, b, c) = (fixed(a.pos), fixed(b.pos), fixed(c.pos));
+        (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    };
+    if area == 0 {
+        return;
+    }
+    if area < 0 {
+        std::mem::swap(&mut b, &mut c);
+    }
+    let area = area.abs() as f32;
+
+    let vertices = [a, b, c];
+    let positions = [fixed(a.pos), fixed(b.pos), fixed(c.pos)];
+
+    // Edge i is opposite vertex i, so its value is that vertex's barycentric weight
+    let edges = [
+        Edge::new(positions[1], positions[2]),
+        Edge::new(positions[2], positions[0]),
+        Edge::new(positions[0], positions[1]),
+    ];
+
+    let min_x = positions.iter().map(|pos| pos.x).min().unwrap_or(0);
+    let min_y = positions.iter().map(|pos| pos.y).min().unwrap_or(0);
+    let max_x = positions.iter().map(|pos| pos.x).max().unwrap_or(0);
+    let max_y = positions.iter().map(|pos| pos.y).max().unwrap_or(0);
+
+    let lower_x = (min_x / SUB_PIXEL).max(0) as i32;
+    let lower_y = (min_y / SUB_PIXEL).max(0) as i32;
+    let upper_x = ((max_x + SUB_PIXEL - 1) / SUB_PIXEL).min(buffer.get_width() as i64) as i32;
+    let upper_y = ((max_y + SUB_PIXEL - 1) / SUB_PIXEL).min(buffer.get_height() as i64) as i32;
+    if lower_x >= upper_x || lower_y >= upper_y {
+        return;
+    }
+
+    // Interpolating attributes divided by depth, then dividing by the interpolated
+    // 1 / depth, is what keeps textures from swimming in perspective
+    let inverse_depth = [1.0 / a.depth, 1.0 / b.depth, 1.0 / c.depth];
+
+    let mut tile_y = lower_y;
+    while tile_y < upper_y {
+        let tile_bottom = (tile_y + TILE_SIZE).min(upper_y);
+
+        let mut tile_x = lower_x;
+        while tile_x < upper_x {
+            let tile_right = (tile_x + TILE_SIZE).min(upper_x);
+
+            // Edge functions are linear, so the corners bound the whole tile
+            let mut outside = false;
+            let mut inside = true;
+            for edge in &edges {
+                let corners = [
+                    edge.at(tile_x, tile_y),
+                    edge.at(tile_right - 1, tile_y),
+                    edge.at(tile_x, tile_bottom - 1),
+                    edge.at(tile_right - 1, tile_bottom - 1),
+                ];
+
+                if corners.iter().all(|&value| value + edge.bias < 0) {
+                    outside = true;
+                }
+                if corners.iter().any(|&value| value + edge.bias < 0) {
+                    inside = false;
+                }
+            }
+
+            if !outside {
+                for y in tile_y..tile_bottom {
+                    let mut weights = [
+                        edges[0].at(tile_x, y),
+                        edges[1].at(tile_x, y),
+                        edges[2].at(tile_x, y),
+                    ];
+
+                    for x in tile_x..tile_right {
+                        let covered = inside
+                            || weights
+                                .iter()
+                                .zip(&edges)
+                                .all(|(&weight, edge)| weight + edge.bias >= 0);
+
+                        if covered {
+                            let color = shade(&vertices, &inverse_depth, &weights, area, texture);
+                            buffer.blend_pixel(x, y, color);
+                        }
+
+                        for (weight, edge) in weights.iter_mut().zip(&edges) {
+                            *weight += edge.step_x;
+                        }
+                    }
+                }
+            }
+
+            tile_x += TILE_SIZE;
+        }
+
+        tile_y += TILE_SIZE;
+    }
+}
+
+fn shade(
+    vertices: &[&Vertex; 3],
+    inverse_depth: &[f32; 3],
+    weights: &[i64; 3],
+    area: f32,
+    texture: Option<&TriangleTexture>,
+) -> u32 {
+    let mut perspective = [0.0; 3];
+    let mut total = 0.0;
+    for i in 0..3 {
+        perspective[i] = weights[i] as f32 / area * inverse_depth[i];
+        total += perspective[i];
+    }
+
+    let mut u = 0.0;
+    let mut v = 0.0;
+    let mut channels = [0.0; 4];
+    for i in 0..3 {
+        let weight = perspective[i] / total;
+        let vertex = vertices[i];
+
+        u += weight * vertex.uv.x;
+        v += weight * vertex.uv.y;
+        channels[0] += weight * vertex.color.a as f32;
+        channels[1] += weight * vertex.color.r as f32;
+        channels[2] += weight * vertex.color.g as f32;
+        channels[3] += weight * vertex.color.b as f32;
+    }
+
+    // Modulate the texel by the vertex color, white leaves the texture as it is
+    if let Some(texture) = texture {
+        let texel = texture.sample(u, v);
+        for (i, channel) in channels.iter_mut().enumerate() {
+            *channel *= ((texel >> (24 - i * 8)) & 0xFF) as f32 / 255.0;
+        }
+    }
+
+    channels.iter().fold(0, |color, &channel| {
+        color << 8 | (channel.clamp(0.0, 255.0) + 0.5) as u32
+    })
+}
+
+// Two triangles, corners in order around the quad. Handy for stretching a sprite
+// into any four sided shape.
+pub fn draw_quad(
+    buffer: &mut Win32GameBitmap,
+    corners: &[Vertex; 4],
+    texture: Option<&TriangleTexture>,
+) {
+    draw_triangle(buffer, &corners[0], &corners[1], &corners[2], texture);
+    draw_triangle(buffer, &corners[0], &corners[2], &corners[3], texture);
+}
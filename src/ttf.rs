@@ -0,0 +1,923 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    font::{self, Font, Glyph},
+    math::{Point, Rect},
+    win32_engine::Win32GameBitmap,
+};
+
+// TrueType (.ttf) fonts. TrueTypeFace parses the file once, TrueTypeFont is a
+// face at one pixel size. Glyphs get rasterized with anti-aliasing the first
+// time they're used and packed into an atlas texture, after that drawing them
+// is a blit out of the atlas like a sprite sheet frame.
+//
+// Supports glyf outlines (simple and composite), cmap formats 4 and 12, hmtx
+// advances and format 0 kern tables. CFF (.otf) outlines and hinting aren't.
+
+const PLATFORM_UNICODE: u16 = 0;
+const PLATFORM_WINDOWS: u16 = 3;
+
+// Composite glyph flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+// Reads past the end of the data come back as zero, a broken font draws
+// garbage instead of panicking
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    data.get(at..at + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .unwrap_or(0)
+}
+
+fn read_i16(data: &[u8], at: usize) -> i16 {
+    read_u16(data, at) as i16
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    data.get(at..at + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Copy)]
+struct Table {
+    offset: usize,
+    length: usize,
+}
+
+// A point of a glyph outline in font units
+#[derive(Clone, Copy)]
+struct OutlinePoint {
+    x: f32,
+    y: f32,
+    on_curve: bool,
+}
+
+pub struct TrueTypeFace {
+    data: Vec<u8>,
+    units_per_em: u16,
+    long_offsets: bool, // loca uses u32 offsets
+    glyph_count: u16,
+    ascender: i16,
+    descender: i16,
+    line_gap: i16,
+    metric_count: u16,          // Glyphs with their own advance in hmtx
+    cmap: Option<(usize, u16)>, // Offset and format of the subtable we use
+    loca: Table,
+    glyf: Table,
+    hmtx: Table,
+    kern: Option<Table>,
+}
+
+impl TrueTypeFace {
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let data = std::fs::read(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+
+        TrueTypeFace::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        // Collections hold several fonts, use the first one
+        let font_offset = match data.get(0..4) {
+            Some(b"ttcf") => read_u32(&data, 12) as usize,
+            Some(b"OTTO") => return Err("CFF outlines are not supported".to_string()),
+            Some(_) => 0,
+            None => return Err("Font file is empty".to_string()),
+        };
+
+        let table_count = read_u16(&data, font_offset + 4) as usize;
+        let mut tables = HashMap::new();
+
+        for index in 0..table_count {
+            let record = font_offset + 12 + index * 16;
+            let tag = data
+                .get(record..record + 4)
+                .ok_or_else(|| "Font table directory is cut off".to_string())?;
+            let table = Table {
+                offset: read_u32(&data, record + 8) as usize,
+                length: read_u32(&data, record + 12) as usize,
+            };
+
+            if table.offset + table.length > data.len() {
+                return Err(format!(
+                    "Font table '{}' is cut off",
+                    String::from_utf8_lossy(tag)
+                ));
+            }
+            tables.insert(tag.to_vec(), table);
+        }
+
+        let table = |tag: &[u8]| {
+            tables
+                .get(tag)
+                .copied()
+                .ok_or_else(|| format!("Font has no '{}' table", String::from_utf8_lossy(tag)))
+        };
+
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+        let cmap = table(b"cmap")?;
+
+        let mut face = TrueTypeFace {
+            units_per_em: read_u16(&data, head.offset + 18),
+            long_offsets: read_i16(&data, head.offset + 50) != 0,
+            glyph_count: read_u16(&data, maxp.offset + 4),
+            ascender: read_i16(&data, hhea.offset + 4),
+            descender: read_i16(&data, hhea.offset + 6),
+            line_gap: read_i16(&data, hhea.offset + 8),
+            metric_count: read_u16(&data, hhea.offset + 34),
+            cmap: None,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            hmtx: table(b"hmtx")?,
+            kern: tables.get(&b"kern"[..]).copied(),
+            data: Vec::new(),
+        };
+
+        if face.units_per_em == 0 {
+            return Err("Font has no units per em".to_string());
+        }
+
+        face.cmap = find_cmap(&data, cmap.offset);
+        if face.cmap.is_none() {
+            return Err("Font has no unicode character map".to_string());
+        }
+
+        face.data = data;
+        Ok(face)
+    }
+
+    // Scale from font units to pixels for a line of pixel_height pixels
+    pub fn scale_for_pixel_height(&self, pixel_height: f32) -> f32 {
+        pixel_height / (self.ascender as f32 - self.descender as f32)
+    }
+
+    // 0 is the missing glyph
+    pub fn glyph_index(&self, c: char) -> u16 {
+        let (offset, format) = match self.cmap {
+            Some(cmap) => cmap,
+            None => return 0,
+        };
+        let data = &self.data;
+        let c = c as u32;
+
+        match format {
+            4 => {
+                if c > 0xFFFF {
+                    return 0;
+                }
+                let segment_count = read_u16(data, offset + 6) as usize / 2;
+                let end_codes = offset + 14;
+                let start_codes = end_codes + segment_count * 2 + 2;
+                let deltas = start_codes + segment_count * 2;
+                let range_offsets = deltas + segment_count * 2;
+
+                for segment in 0..segment_count {
+                    if c > read_u16(data, end_codes + segment * 2) as u32 {
+                        continue;
+                    }
+
+                    let start = read_u16(data, start_codes + segment * 2) as u32;
+                    if c < start {
+                        return 0;
+                    }
+
+                    let delta = read_u16(data, deltas + segment * 2) as u32;
+                    let range_offset = read_u16(data, range_offsets + segment * 2) as usize;
+                    if range_offset == 0 {
+                        return (c + delta) as u16;
+                    }
+
+                    // The offset is relative to where it's stored
+                    let glyph = read_u16(
+                        data,
+                        range_offsets + segment * 2 + range_offset + (c - start) as usize * 2,
+                    ) as u32;
+                    return if glyph == 0 {
+                        0
+                    } else {
+                        (glyph + delta) as u16
+                    };
+                }
+
+                0
+            }
+            12 => {
+                let group_count = read_u32(data, offset + 12) as usize;
+
+                for group in 0..group_count {
+                    let at = offset + 16 + group * 12;
+                    let start = read_u32(data, at);
+                    let end = read_u32(data, at + 4);
+
+                    if c >= start && c <= end {
+                        return (read_u32(data, at + 8) + c - start) as u16;
+                    }
+                }
+
+                0
+            }
+            _ => 0,
+        }
+    }
+
+    // Advance width in font units
+    pub fn advance(&self, glyph: u16) -> u16 {
+        // Glyphs past the last metric share its advance
+        let metric = glyph.min(self.metric_count.max(1) - 1) as usize;
+        read_u16(&self.data, self.hmtx.offset + metric * 4)
+    }
+
+    // Kerning between two glyphs in font units, from a format 0 kern table
+    pub fn kerning(&self, left: u16, right: u16) -> i16 {
+        let kern = match self.kern {
+            Some(kern) => kern,
+            None => return 0,
+        };
+        let data = &self.data;
+
+        // Only the original Microsoft version of the table
+        if read_u16(data, kern.offset) != 0 {
+            return 0;
+        }
+
+        let subtable_count = read_u16(data, kern.offset + 2) as usize;
+        let mut subtable = kern.offset + 4;
+        let key = (left as u32) << 16 | right as u32;
+
+        for _ in 0..subtable_count {
+            let length = read_u16(data, subtable + 2) as usize;
+            let coverage = read_u16(data, subtable + 4);
+
+            // Horizontal format 0 pairs, sorted so they can be searched
+            if coverage & 0x0001 != 0 && coverage >> 8 == 0 {
+                let pair_count = read_u16(data, subtable + 6) as usize;
+                let pairs = subtable + 14;
+
+                let mut low = 0;
+                let mut high = pair_count;
+                while low < high {
+                    let middle = (low + high) / 2;
+                    let pair_key = read_u32(data, pairs + middle * 6);
+
+                    if pair_key == key {
+                        return read_i16(data, pairs + middle * 6 + 4);
+                    } else if pair_key < key {
+                        low = middle + 1;
+                    } else {
+                        high = middle;
+                    }
+                }
+            }
+
+            if length == 0 {
+                break;
+            }
+            subtable += length;
+        }
+
+        0
+    }
+
+    fn glyph_data(&self, glyph: u16) -> Option<&[u8]> {
+        if glyph >= self.glyph_count {
+            return None;
+        }
+
+        let (start, end) = if self.long_offsets {
+            let at = self.loca.offset + glyph as usize * 4;
+            (
+                read_u32(&self.data, at) as usize,
+                read_u32(&self.data, at + 4) as usize,
+            )
+        } else {
+            let at = self.loca.offset + glyph as usize * 2;
+            (
+                read_u16(&self.data, at) as usize * 2,
+                read_u16(&self.data, at + 2) as usize * 2,
+            )
+        };
+
+        // Empty glyphs (spaces) have no data
+        if end <= start || end > self.glyf.length {
+            return None;
+        }
+
+        self.data
+            .get(self.glyf.offset + start..self.glyf.offset + end)
+    }
+
+    // Bounding box in font units, (x min, y min, x max, y max)
+    fn glyph_box(&self, glyph: u16) -> Option<(i16, i16, i16, i16)> {
+        self.glyph_data(glyph).map(|data| {
+            (
+                read_i16(data, 2),
+                read_i16(data, 4),
+                read_i16(data, 6),
+                read_i16(data, 8),
+            )
+        })
+    }
+
+    // Contours of the glyph, composite glyphs get their parts transformed and joined
+    fn outline(&self, glyph: u16, depth: u32, contours: &mut Vec<Vec<OutlinePoint>>) {
+        let data = match self.glyph_data(glyph) {
+            Some(data) => data,
+            None => return,
+        };
+
+        let contour_count = read_i16(data, 0);
+        if contour_count >= 0 {
+            read_simple_glyph(data, contour_count as usize, contours);
+            return;
+        }
+
+        if depth >= MAX_COMPONENT_DEPTH {
+            return;
+        }
+
+        let mut at = 10;
+        loop {
+            let flags = read_u16(data, at);
+            let component = read_u16(data, at + 2);
+            at += 4;
+
+            let (dx, dy) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                at += 4;
+                (read_i16(data, at - 4) as f32, read_i16(data, at - 2) as f32)
+            } else {
+                at += 2;
+                (
+                    *data.get(at - 2).unwrap_or(&0) as i8 as f32,
+                    *data.get(at - 1).unwrap_or(&0) as i8 as f32,
+                )
+            };
+            // Matching points instead of offsets is rare enough to just place it at 0,0
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0.0, 0.0)
+            };
+
+            // 2.14 fixed point transform
+            let fixed = |at: usize| read_i16(data, at) as f32 / 16384.0;
+            let (a, b, c, d) = if flags & WE_HAVE_A_SCALE != 0 {
+                at += 2;
+                (fixed(at - 2), 0.0, 0.0, fixed(at - 2))
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                at += 4;
+                (fixed(at - 4), 0.0, 0.0, fixed(at - 2))
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                at += 8;
+                (fixed(at - 8), fixed(at - 6), fixed(at - 4), fixed(at - 2))
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+
+            let first = contours.len();
+            self.outline(component, depth + 1, contours);
+            for contour in &mut contours[first..] {
+                for point in contour.iter_mut() {
+                    let (x, y) = (point.x, point.y);
+                    point.x = a * x + c * y + dx;
+                    point.y = b * x + d * y + dy;
+                }
+            }
+
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+    }
+}
+
+// Prefers the full unicode tables (format 12) over the BMP only ones (format 4)
+fn find_cmap(data: &[u8], cmap: usize) -> Option<(usize, u16)> {
+    let subtable_count = read_u16(data, cmap + 2) as usize;
+    let mut best = None;
+
+    for index in 0..subtable_count {
+        let record = cmap + 4 + index * 8;
+        let platform = read_u16(data, record);
+        let offset = cmap + read_u32(data, record + 4) as usize;
+        let format = read_u16(data, offset);
+
+        if platform != PLATFORM_UNICODE && platform != PLATFORM_WINDOWS {
+            continue;
+        }
+        match (format, best) {
+            (12, _) => return Some((offset, 12)),
+            (4, None) => best = Some((offset, 4)),
+            _ => {}
+        }
+    }
+
+    best
+}
+
+fn read_simple_glyph(data: &[u8], contour_count: usize, contours: &mut Vec<Vec<OutlinePoint>>) {
+    let end_points: Vec<usize> = (0..contour_count)
+        .map(|contour| read_u16(data, 10 + contour * 2) as usize)
+        .collect();
+    let point_count = end_points.last().map_or(0, |last| last + 1);
+
+    let instruction_length = read_u16(data, 10 + contour_count * 2) as usize;
+    let mut at = 12 + contour_count * 2 + instruction_length;
+
+    // Flags are run length encoded
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = *data.get(at).unwrap_or(&0);
+        at += 1;
+        flags.push(flag);
+
+        if flag & 0x08 != 0 {
+            let repeat = *data.get(at).unwrap_or(&0);
+            at += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    // Coordinates are deltas, a byte with a sign flag or an i16 (or unchanged)
+    let mut read_coordinates = |short_bit: u8, same_bit: u8| -> Vec<f32> {
+        let mut value = 0i32;
+        flags
+            .iter()
+            .map(|&flag| {
+                if flag & short_bit != 0 {
+                    let delta = *data.get(at).unwrap_or(&0) as i32;
+                    at += 1;
+                    value += if flag & same_bit != 0 { delta } else { -delta };
+                } else if flag & same_bit == 0 {
+                    value += read_i16(data, at) as i32;
+                    at += 2;
+                }
+                value as f32
+            })
+            .collect()
+    };
+    let xs = read_coordinates(0x02, 0x10);
+    let ys = read_coordinates(0x04, 0x20);
+
+    let mut start = 0;
+    for end in end_points {
+        if end < start || end >= point_count {
+            break;
+        }
+
+        contours.push(
+            (start..=end)
+                .map(|point| OutlinePoint {
+                    x: xs[point],
+                    y: ys[point],
+                    on_curve: flags[point] & 0x01 != 0,
+                })
+                .collect(),
+        );
+        start = end + 1;
+    }
+}
+
+/*
+    Rasterizer
+*/
+
+// Anti-aliased coverage by accumulating signed area (like font-rs). Every line
+// adds how much it covers to the cells it crosses, a running sum along each
+// row turns that into coverage.
+struct Rasterizer {
+    width: usize,
+    height: usize,
+    accumulation: Vec<f32>,
+}
+
+impl Rasterizer {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            // Room for lines that end on the right edge
+            accumulation: vec![0.0; width * height + 4],
+        }
+    }
+
+    fn add(&mut self, index: isize, value: f32) {
+        if index >= 0 && (index as usize) < self.accumulation.len() {
+            self.accumulation[index as usize] += value;
+        }
+    }
+
+    fn line(&mut self, from: Point<f32>, to: Point<f32>) {
+        if (from.y - to.y).abs() <= f32::EPSILON {
+            return;
+        }
+
+        let (direction, top, bottom) = if from.y < to.y {
+            (1.0, from, to)
+        } else {
+            (-1.0, to, from)
+        };
+        let dxdy = (bottom.x - top.x) / (bottom.y - top.y);
+
+        let mut x = top.x;
+        if top.y < 0.0 {
+            x -= top.y * dxdy;
+        }
+
+        let first_row = top.y.max(0.0) as usize;
+        let last_row = (bottom.y.ceil().max(0.0) as usize).min(self.height);
+
+        for y in first_row..last_row {
+            let row_start = (y * self.width) as isize;
+            let dy = ((y + 1) as f32).min(bottom.y) - (y as f32).max(top.y);
+            let next_x = x + dxdy * dy;
+            let area = dy * direction;
+
+            let (x0, x1) = if x < next_x { (x, next_x) } else { (next_x, x) };
+            let x0_floor = x0.floor();
+            let x0_cell = x0_floor as isize;
+            let x1_ceil = x1.ceil();
+            let x1_cell = x1_ceil as isize;
+
+            if x1_cell <= x0_cell + 1 {
+                // Stays inside one cell
+                let middle = 0.5 * (x + next_x) - x0_floor;
+                self.add(row_start + x0_cell, area - area * middle);
+                self.add(row_start + x0_cell + 1, area * middle);
+            } else {
+                // Crosses cells, the first and last get triangles and the ones between get slices
+                let step = (x1 - x0).recip();
+                let x0_fraction = x0 - x0_floor;
+                let first = 0.5 * step * (1.0 - x0_fraction) * (1.0 - x0_fraction);
+                let x1_fraction = x1 - x1_ceil + 1.0;
+                let last = 0.5 * step * x1_fraction * x1_fraction;
+
+                self.add(row_start + x0_cell, area * first);
+                if x1_cell == x0_cell + 2 {
+                    self.add(row_start + x0_cell + 1, area * (1.0 - first - last));
+                } else {
+                    let second = step * (1.5 - x0_fraction);
+                    self.add(row_start + x0_cell + 1, area * (second - first));
+                    for cell in x0_cell + 2..x1_cell - 1 {
+                        self.add(row_start + cell, area * step);
+                    }
+                    let before_last = second + (x1_cell - x0_cell - 3) as f32 * step;
+                    self.add(row_start + x1_cell - 1, area * (1.0 - before_last - last));
+                }
+                self.add(row_start + x1_cell, area * last);
+            }
+
+            x = next_x;
+        }
+    }
+
+    // Flattens the curve into enough lines that it looks smooth at this size
+    fn quadratic(&mut self, from: Point<f32>, control: Point<f32>, to: Point<f32>) {
+        let deviation_x = from.x - 2.0 * control.x + to.x;
+        let deviation_y = from.y - 2.0 * control.y + to.y;
+        let deviation = (deviation_x * deviation_x + deviation_y * deviation_y).sqrt();
+        let segments = ((deviation * 2.0).sqrt().ceil() as usize).clamp(1, 32);
+
+        let mut previous = from;
+        for segment in 1..=segments {
+            let t = segment as f32 / segments as f32;
+            let u = 1.0 - t;
+            let point = Point::new(
+                u * u * from.x + 2.0 * u * t * control.x + t * t * to.x,
+                u * u * from.y + 2.0 * u * t * control.y + t * t * to.y,
+            );
+
+            self.line(previous, point);
+            previous = point;
+        }
+    }
+
+    // Coverage 0 to 255 per pixel, non-zero winding
+    fn coverage(&self) -> Vec<u8> {
+        let mut sum = 0.0;
+
+        self.accumulation[..self.width * self.height]
+            .iter()
+            .map(|value| {
+                sum += value;
+                (sum.abs().min(1.0) * 255.0 + 0.5) as u8
+            })
+            .collect()
+    }
+}
+
+// Draws the contours, transform takes font units to pixels
+fn rasterize_contours(
+    contours: &[Vec<OutlinePoint>],
+    transform: impl Fn(&OutlinePoint) -> Point<f32>,
+    rasterizer: &mut Rasterizer,
+) {
+    for contour in contours {
+        if contour.len() < 2 {
+            continue;
+        }
+
+        // Start on a point that's on the curve, between two off curve points there's an implied one
+        let count = contour.len();
+        let start_index = contour.iter().position(|point| point.on_curve);
+        let start = match start_index {
+            Some(index) => transform(&contour[index]),
+            None => {
+                let a = transform(&contour[0]);
+                let b = transform(&contour[1]);
+                Point::new((a.x + b.x) * 0.5, (a.y + b.y) * 0.5)
+            }
+        };
+        let first = start_index.map_or(1, |index| index + 1);
+
+        let mut current = start;
+        let mut control: Option<Point<f32>> = None;
+
+        for step in 0..count {
+            let point = &contour[(first + step) % count];
+            let position = transform(point);
+
+            match (point.on_curve, control) {
+                (true, None) => {
+                    rasterizer.line(current, position);
+                    current = position;
+                }
+                (true, Some(previous)) => {
+                    rasterizer.quadratic(current, previous, position);
+                    current = position;
+                    control = None;
+                }
+                (false, None) => control = Some(position),
+                (false, Some(previous)) => {
+                    let middle = Point::new(
+                        (previous.x + position.x) * 0.5,
+                        (previous.y + position.y) * 0.5,
+                    );
+                    rasterizer.quadratic(current, previous, middle);
+                    current = middle;
+                    control = Some(position);
+                }
+            }
+        }
+
+        // Close the contour
+        match control {
+            Some(previous) => rasterizer.quadratic(current, previous, start),
+            None => rasterizer.line(current, start),
+        }
+    }
+}
+
+/*
+    Font
+*/
+
+const ATLAS_START_SIZE: i32 = 256;
+
+#[derive(Clone, Copy)]
+struct AtlasGlyph {
+    source: Rect,       // Empty for glyphs with nothing to draw
+    offset: Point<i32>, // From the pen at the top of the line
+}
+
+// White pixels with the coverage in alpha, packed in rows (shelves)
+struct GlyphAtlas {
+    texture: Win32GameBitmap,
+    glyphs: HashMap<u16, AtlasGlyph>,
+    shelf_x: i32,
+    shelf_y: i32,
+    shelf_height: i32,
+}
+
+impl GlyphAtlas {
+    fn new() -> Self {
+        Self {
+            texture: Win32GameBitmap::from_pixels(
+                ATLAS_START_SIZE,
+                ATLAS_START_SIZE,
+                &vec![0; (ATLAS_START_SIZE * ATLAS_START_SIZE) as usize],
+            ),
+            glyphs: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    // Finds room for a width x height image, growing the texture when it's full
+    fn allocate(&mut self, width: i32, height: i32) -> Point<i32> {
+        // One pixel gap so filtering never bleeds between glyphs
+        let (width, height) = (width + 1, height + 1);
+
+        if self.shelf_x + width > self.texture.get_width() {
+            self.shelf_x = 0;
+            self.shelf_y += self.shelf_height;
+            self.shelf_height = 0;
+        }
+
+        let needed_width = self.texture.get_width().max(width);
+        let mut needed_height = self.texture.get_height();
+        while self.shelf_y + height > needed_height {
+            needed_height *= 2;
+        }
+        if needed_width != self.texture.get_width() || needed_height != self.texture.get_height() {
+            self.grow(needed_width, needed_height);
+        }
+
+        let pos = Point::new(self.shelf_x, self.shelf_y);
+        self.shelf_x += width;
+        self.shelf_height = self.shelf_height.max(height);
+
+        pos
+    }
+
+    // Glyphs already packed keep their place
+    fn grow(&mut self, width: i32, height: i32) {
+        let mut pixels = vec![0; (width * height) as usize];
+        for y in 0..self.texture.get_height() {
+            let row = self.texture.row(y);
+            let start = (y * width) as usize;
+            pixels[start..start + row.len()].copy_from_slice(row);
+        }
+
+        self.texture = Win32GameBitmap::from_pixels(width, height, &pixels);
+    }
+}
+
+pub struct TrueTypeFont {
+    face: Arc<TrueTypeFace>,
+    scale: f32, // Pixels per font unit
+    ascent: i32,
+    line_height: u32,
+    atlas: RwLock<GlyphAtlas>,
+}
+
+impl TrueTypeFont {
+    // pixel_height is the distance from the highest ascender to the lowest descender.
+    // Share a face between sizes with an Arc so the file is only parsed once.
+    pub fn new(face: Arc<TrueTypeFace>, pixel_height: f32) -> Self {
+        let scale = face.scale_for_pixel_height(pixel_height);
+        let ascent = (face.ascender as f32 * scale).round() as i32;
+        let line_height =
+            ((face.ascender as f32 - face.descender as f32 + face.line_gap as f32) * scale).round();
+
+        Self {
+            face,
+            scale,
+            ascent,
+            line_height: line_height.max(1.0) as u32,
+            atlas: RwLock::new(GlyphAtlas::new()),
+        }
+    }
+
+    pub fn load(file_path: &str, pixel_height: f32) -> Result<Self, String> {
+        Ok(TrueTypeFont::new(
+            Arc::new(TrueTypeFace::load(file_path)?),
+            pixel_height,
+        ))
+    }
+
+    pub fn get_face(&self) -> &Arc<TrueTypeFace> {
+        &self.face
+    }
+
+    // Rasterizes the glyph into the atlas the first time it's asked for
+    fn cached_glyph(&self, glyph: u16) -> AtlasGlyph {
+        if let Some(cached) = self.atlas.read().unwrap().glyphs.get(&glyph) {
+            return *cached;
+        }
+
+        let mut atlas = self.atlas.write().unwrap();
+        // Another thread might have added it while we waited for the lock
+        if let Some(cached) = atlas.glyphs.get(&glyph) {
+            return *cached;
+        }
+
+        let cached = self.rasterize(glyph, &mut atlas);
+        atlas.glyphs.insert(glyph, cached);
+        cached
+    }
+
+    fn rasterize(&self, glyph: u16, atlas: &mut GlyphAtlas) -> AtlasGlyph {
+        let empty = AtlasGlyph {
+            source: Rect::new(0, 0, 0, 0),
+            offset: Point::new(0, 0),
+        };
+
+        let (x_min, y_min, x_max, y_max) = match self.face.glyph_box(glyph) {
+            Some(bounds) => bounds,
+            None => return empty,
+        };
+
+        // Pixel box, y goes down
+        let left = (x_min as f32 * self.scale).floor() as i32;
+        let top = (-y_max as f32 * self.scale).floor() as i32;
+        let right = (x_max as f32 * self.scale).ceil() as i32;
+        let bottom = (-y_min as f32 * self.scale).ceil() as i32;
+        let width = right - left;
+        let height = bottom - top;
+        if width <= 0 || height <= 0 {
+            return empty;
+        }
+
+        let mut contours = Vec::new();
+        self.face.outline(glyph, 0, &mut contours);
+
+        let mut rasterizer = Rasterizer::new(width as usize, height as usize);
+        rasterize_contours(
+            &contours,
+            |point| {
+                Point::new(
+                    point.x * self.scale - left as f32,
+                    -point.y * self.scale - top as f32,
+                )
+            },
+            &mut rasterizer,
+        );
+
+        let pos = atlas.allocate(width, height);
+        for (y, row) in rasterizer.coverage().chunks(width as usize).enumerate() {
+            let atlas_row = atlas.texture.row_mut(pos.y + y as i32);
+            for (x, &coverage) in row.iter().enumerate() {
+                atlas_row[pos.x as usize + x] = (coverage as u32) << 24 | 0x00FFFFFF;
+            }
+        }
+
+        AtlasGlyph {
+            source: Rect::new(pos.x as u32, pos.y as u32, width as u32, height as u32),
+            offset: Point::new(left, self.ascent + top),
+        }
+    }
+}
+
+impl Font for TrueTypeFont {
+    fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        let glyph = self.face.glyph_index(c);
+        if glyph == 0 {
+            return None;
+        }
+
+        let cached = self.cached_glyph(glyph);
+        Some(Glyph {
+            offset: cached.offset,
+            size: Point::new(cached.source.w, cached.source.h),
+            advance: (self.face.advance(glyph) as f32 * self.scale).round() as i32,
+        })
+    }
+
+    fn kerning(&self, first: char, second: char) -> i32 {
+        let amount = self
+            .face
+            .kerning(self.face.glyph_index(first), self.face.glyph_index(second));
+
+        (amount as f32 * self.scale).round() as i32
+    }
+
+    fn draw_glyph(
+        &self,
+        c: char,
+        pos: Point<i32>,
+        color: u32,
+        clip: &Rect,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        let glyph = self.face.glyph_index(c);
+        if glyph == 0 {
+            return;
+        }
+
+        let cached = self.cached_glyph(glyph);
+        if cached.source.w == 0 {
+            return;
+        }
+
+        // Atlas texels are white with the coverage in alpha, so tinting by the
+        // texel color gives the same pixels and takes the row blit path
+        let atlas = self.atlas.read().unwrap();
+        font::draw_glyph_texture(
+            &atlas.texture,
+            &cached.source,
+            Point::new(pos.x + cached.offset.x, pos.y + cached.offset.y),
+            15,
+            color,
+            clip,
+            buffer,
+        );
+    }
+}
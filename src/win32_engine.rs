@@ -1,18 +1,33 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::ffi::CString;
-use std::io::Read;
 use std::mem;
 use std::process::exit;
 
+use crate::font::Font;
+use crate::input::{
+    ButtonState, GamepadAxis, GamepadSettings, InputDevice, InputEvents, InputFrame, InputMap,
+    InputSnapshot, KeyEvent, MouseButton, MouseEvent, PlayerFrame, RebindCapture, RebindResult,
+    Stick, TextEvent,
+};
 use crate::language_layer::{create_wide_char, INVALID_HANDLE_VALUE, OPEN_EXISTING};
+use crate::png::decode_png;
+use crate::raster;
+use crate::rumble::{RumbleEffect, RumbleHandle, RumbleMixer};
+use crate::simd;
+use crate::triangle::{self, TriangleTexture, Vertex};
 
 use winapi::shared::minwindef::{HINSTANCE, LPARAM, LPDWORD, LPVOID, LRESULT, UINT, WORD, WPARAM};
 use winapi::shared::ntdef::{LPCSTR, LPCWSTR};
-use winapi::shared::windef::{HBRUSH, HDC, HICON, HMENU, HWND, RECT};
+use winapi::shared::windef::{HBRUSH, HDC, HICON, HMENU, HWND, POINT, RECT};
 use winapi::shared::winerror::ERROR_SUCCESS;
 use winapi::um::wingdi::{
     StretchDIBits, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, RGBQUAD, SRCCOPY,
 };
 
+use winapi::um::imm::{
+    ImmGetCompositionStringW, ImmGetContext, ImmReleaseContext, GCS_COMPSTR, GCS_CURSORPOS,
+};
 use winapi::um::winnt::{
     FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, GENERIC_READ, HANDLE, HEAP_ZERO_MEMORY, MEM_COMMIT,
     MEM_RELEASE, MEM_RESERVE, PAGE_READWRITE,
@@ -21,27 +36,108 @@ use winapi::um::winuser::*;
 
 use kernel32::*;
 use winapi::um::xinput::{
-    XInputGetState, XINPUT_GAMEPAD, XINPUT_GAMEPAD_DPAD_DOWN, XINPUT_GAMEPAD_DPAD_LEFT,
-    XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP, XINPUT_STATE, XUSER_MAX_COUNT,
+    XInputGetState, XInputSetState, XINPUT_GAMEPAD, XINPUT_STATE, XINPUT_VIBRATION, XUSER_MAX_COUNT,
 };
 
-use crate::math::{Color, Point, Rect};
+use crate::math::{alpha_blend, Color, Point, Rect};
 
 //TODO:
 /*
 - Read & Write functions
-- load & draw bmp
-- Figure out how to handle alpha when it comes to loading and drawing bmps
 */
 
 pub trait Win32Drawable {
     fn draw_rectangle(&self, color: &Color, rect: &Rect, buffer: &mut Win32GameBitmap);
+    fn draw_rectangle_outline(
+        &self,
+        color: &Color,
+        rect: &Rect,
+        thickness: u32,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_line(
+        &self,
+        color: &Color,
+        from: Point<i32>,
+        to: Point<i32>,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_line_aa(
+        &self,
+        color: &Color,
+        from: Point<f32>,
+        to: Point<f32>,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_circle(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: i32,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn fill_circle(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: i32,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_ellipse(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: Point<i32>,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn fill_ellipse(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: Point<i32>,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_polygon(&self, color: &Color, points: &[Point<f32>], buffer: &mut Win32GameBitmap);
+    fn fill_polygon(&self, color: &Color, points: &[Point<f32>], buffer: &mut Win32GameBitmap);
+    fn fill_triangle(
+        &self,
+        color: &Color,
+        a: Point<f32>,
+        b: Point<f32>,
+        c: Point<f32>,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_triangle(
+        &self,
+        vertices: &[Vertex; 3],
+        texture: Option<&TriangleTexture>,
+        buffer: &mut Win32GameBitmap,
+    );
+    fn draw_text(
+        &self,
+        font: &dyn Font,
+        text: &str,
+        pos: Point<i32>,
+        color: &Color,
+        buffer: &mut Win32GameBitmap,
+    );
 }
 
 static mut IS_WINDOW_CLOSED: bool = false;
 
+// IME messages are sent straight to window_proc instead of going through the
+// message queue, so they wait here until process_window_messages gets back from
+// dispatching the message that caused them
+thread_local! {
+    static IME_EVENTS: RefCell<VecDeque<TextEvent>> = const { RefCell::new(VecDeque::new()) };
+}
+
 pub enum WindowMessages {
     WindowClosed,
+    Key { event: KeyEvent, repeat: bool },
+    Mouse(MouseEvent),
+    Char(u16), // UTF-16, characters past 0xFFFF come in two
+    Ime(TextEvent),
 }
 
 // Storage for Screen data that excludes the windows bar
@@ -91,6 +187,11 @@ pub struct Win32GameBitmap {
     pub memory: *const winapi::ctypes::c_void,
 }
 
+// The memory belongs to the bitmap alone, so textures can be read from render
+// worker threads (see render_group.rs)
+unsafe impl Send for Win32GameBitmap {}
+unsafe impl Sync for Win32GameBitmap {}
+
 impl Win32GameBitmap {
     // This creation function is just meant for the background buffer
     pub fn new(window: &HWND) -> Self {
@@ -175,36 +276,22 @@ impl Win32GameBitmap {
         }
     }
 
-    // These functions and methods are meant for BMP Textures
-    pub fn load_bmp(file_path: &str) -> Win32GameBitmap {
-        let mut result = Win32GameBitmap::empty_bitmap();
-
-        let mut fhandle = std::fs::File::open(file_path).expect("Failed to open file.");
-        let metadata = std::fs::metadata(&file_path).expect("Unable to read metadata.");
-
-        let mut bm_read = vec![0; metadata.len() as usize];
-
-        fhandle
-            .read_exact(&mut bm_read)
-            .expect("Failed to read file.");
-
-        println!("{:?}", String::from_utf8(bm_read.clone()));
+    // Creates a top-down 32 bit bitmap from 0xAARRGGBB pixels
+    pub fn from_pixels(width: i32, height: i32, pixels: &[u32]) -> Self {
+        assert!(
+            pixels.len() == (width * height) as usize,
+            "pixel count doesn't match the bitmap size"
+        );
 
-        //NOTE:Literally just setting everything from the index of the vector
-        // Maybe make this less manual and sorta dumb?? idk shouldn't create
-        // problems as all bmp files i'm loading are the same :shrug:
-        result.bitmap_info.bmiHeader.biSize = bm_read[14] as u32;
-        result.bitmap_info.bmiHeader.biWidth = bm_read[18] as i32;
-        result.bitmap_info.bmiHeader.biHeight = bm_read[22] as i32;
-        result.bitmap_info.bmiHeader.biPlanes = bm_read[24] as u16;
-        result.bitmap_info.bmiHeader.biPlanes = bm_read[26] as u16;
-        result.bitmap_info.bmiHeader.biBitCount = bm_read[28] as u16;
-        result.bitmap_info.bmiHeader.biCompression = bm_read[30] as u32;
+        let mut result = Win32GameBitmap::empty_bitmap();
 
-        // Subract 122 bytes from length to get the right size
-        // the 122 comes from biSize (108) + 14 (cuz it's 14 bytes in the array)
-        result.bitmap_info.bmiHeader.biSizeImage =
-            metadata.len() as u32 - (result.bitmap_info.bmiHeader.biSize + 14);
+        result.bitmap_info.bmiHeader.biSize = mem::size_of::<BITMAPINFOHEADER>() as u32;
+        result.bitmap_info.bmiHeader.biWidth = width;
+        result.bitmap_info.bmiHeader.biHeight = -height;
+        result.bitmap_info.bmiHeader.biPlanes = 1;
+        result.bitmap_info.bmiHeader.biBitCount = 32;
+        result.bitmap_info.bmiHeader.biCompression = BI_RGB;
+        result.bitmap_info.bmiHeader.biSizeImage = (width * height * 4) as u32;
 
         unsafe {
             result.memory = HeapAlloc(
@@ -212,33 +299,236 @@ impl Win32GameBitmap {
                 HEAP_ZERO_MEMORY,
                 result.bitmap_info.bmiHeader.biSizeImage as u64,
             ) as *const winapi::ctypes::c_void;
+
+            std::ptr::copy_nonoverlapping(pixels.as_ptr(), result.memory as *mut u32, pixels.len());
         }
 
         result
     }
 
-    //NOTE: ONLY CALL ON BMP TEXTURES
-    pub fn draw_bmp(&self, pos: Point<u32>, buffer: &mut Win32GameBitmap) {
+    pub fn get_width(&self) -> i32 {
+        self.bitmap_info.bmiHeader.biWidth
+    }
+
+    // Height is negative for top-down bitmaps
+    pub fn get_height(&self) -> i32 {
+        self.bitmap_info.bmiHeader.biHeight.abs()
+    }
+
+    //NOTE: No bounds checking, callers clip before touching pixels
+    pub fn get_pixel(&self, x: i32, y: i32) -> u32 {
+        unsafe { *(self.memory as *const u32).add((y * self.get_width() + x) as usize) }
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u32) {
         unsafe {
-            let pixel = self.memory as *mut u8; // Starting pixel
-            let buff = buffer.memory as *mut u8;
-
-            let lower_y = pos.y;
-            let lower_x = pos.x;
-            let upper_y = lower_y + self.bitmap_info.bmiHeader.biHeight as u32;
-            let upper_x = lower_x + self.bitmap_info.bmiHeader.biWidth as u32;
-
-            let mut counter = 0;
-            for y in lower_y..upper_y as u32 {
-                for x in lower_x..upper_x as u32 {
-                    counter = y * buffer.bitmap_info.bmiHeader.biWidth as u32 + x;
-
-                    *buff.add((counter * 4 + 0) as usize) = *pixel.add(1);
-                    *buff.add((counter * 4 + 1) as usize) = *pixel.add(1);
-                    *buff.add((counter * 4 + 2) as usize) = *pixel.add(1);
-                }
+            *(self.memory as *mut u32).add((y * self.get_width() + x) as usize) = color;
+        }
+    }
+
+    // Writes the pixel using its alpha, fully transparent pixels are skipped
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: u32) {
+        match color >> 24 {
+            0 => {}
+            255 => self.set_pixel(x, y, color),
+            _ => {
+                let dest = self.get_pixel(x, y);
+                self.set_pixel(x, y, alpha_blend(dest, color));
+            }
+        }
+    }
+
+    // Pixels as slices, for the row kernels in simd.rs
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        let count = (self.get_width() * self.get_height()) as usize;
+        unsafe { std::slice::from_raw_parts_mut(self.memory as *mut u32, count) }
+    }
+
+    pub fn row(&self, y: i32) -> &[u32] {
+        assert!(y >= 0 && y < self.get_height(), "row is outside the bitmap");
+        let width = self.get_width() as usize;
+        unsafe {
+            std::slice::from_raw_parts((self.memory as *const u32).add(y as usize * width), width)
+        }
+    }
+
+    pub fn row_mut(&mut self, y: i32) -> &mut [u32] {
+        assert!(y >= 0 && y < self.get_height(), "row is outside the bitmap");
+        let width = self.get_width() as usize;
+        unsafe {
+            std::slice::from_raw_parts_mut((self.memory as *mut u32).add(y as usize * width), width)
+        }
+    }
+
+    // These functions and methods are meant for BMP Textures
+    pub fn load_bmp(file_path: &str) -> Win32GameBitmap {
+        Win32GameBitmap::try_load_bmp(file_path).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_load_bmp(file_path: &str) -> Result<Win32GameBitmap, String> {
+        let bm_read = std::fs::read(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+
+        let read_u16 = |at: usize| u16::from_le_bytes([bm_read[at], bm_read[at + 1]]);
+        let read_u32 = |at: usize| {
+            u32::from_le_bytes([
+                bm_read[at],
+                bm_read[at + 1],
+                bm_read[at + 2],
+                bm_read[at + 3],
+            ])
+        };
+
+        if bm_read.len() < 54 || read_u16(0) != 0x4D42 {
+            return Err(format!("{} is not a bmp file", file_path));
+        }
+
+        let bitmap_offset = read_u32(10) as usize;
+        let header_size = read_u32(14);
+        let width = read_u32(18) as i32;
+        let height = read_u32(22) as i32;
+        let bits_per_pixel = read_u16(28);
+        let compression = read_u32(30);
+
+        if bits_per_pixel != 24 && bits_per_pixel != 32 {
+            return Err(format!(
+                "{}: only 24 and 32 bit bmps are supported",
+                file_path
+            ));
+        }
+
+        // BI_RGB files have no alpha, bitfield files tell us where every channel is
+        let (mut red_mask, mut green_mask, mut blue_mask, mut alpha_mask) =
+            (0x00FF0000, 0x0000FF00, 0x000000FF, 0);
+        if compression == 3 {
+            if bm_read.len() < 70 {
+                return Err(format!("{} has a truncated header", file_path));
+            }
+            red_mask = read_u32(54);
+            green_mask = read_u32(58);
+            blue_mask = read_u32(62);
+            if header_size >= 56 {
+                alpha_mask = read_u32(66);
+            }
+        }
+
+        let channel = |value: u32, mask: u32| {
+            if mask == 0 {
+                0
+            } else {
+                (value & mask) >> mask.trailing_zeros()
+            }
+        };
+
+        let bytes_per_pixel = (bits_per_pixel / 8) as usize;
+        if width <= 0 || height == 0 {
+            return Err(format!("{} has no pixels", file_path));
+        }
+        let row_size = (width as usize * bytes_per_pixel + 3) & !3; // rows are padded to 4 bytes
+        let pixel_end = row_size
+            .checked_mul(height.unsigned_abs() as usize)
+            .and_then(|size| size.checked_add(bitmap_offset));
+        if !matches!(pixel_end, Some(end) if end <= bm_read.len()) {
+            return Err(format!("{} is truncated", file_path));
+        }
+        let mut pixels = Vec::with_capacity((width * height.abs()) as usize);
+
+        for y in 0..height.abs() {
+            // Positive height means the rows are stored bottom to top
+            let row = if height > 0 { height - 1 - y } else { y } as usize;
+
+            for x in 0..width as usize {
+                let at = bitmap_offset + row * row_size + x * bytes_per_pixel;
+                let value = if bytes_per_pixel == 4 {
+                    read_u32(at)
+                } else {
+                    u32::from_le_bytes([bm_read[at], bm_read[at + 1], bm_read[at + 2], 0])
+                };
+
+                let alpha = if alpha_mask == 0 {
+                    255
+                } else {
+                    channel(value, alpha_mask)
+                };
+
+                pixels.push(
+                    alpha << 24
+                        | channel(value, red_mask) << 16
+                        | channel(value, green_mask) << 8
+                        | channel(value, blue_mask),
+                );
             }
         }
+
+        Ok(Win32GameBitmap::from_pixels(width, height.abs(), &pixels))
+    }
+
+    pub fn load_png(file_path: &str) -> Win32GameBitmap {
+        Win32GameBitmap::try_load_png(file_path).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_load_png(file_path: &str) -> Result<Win32GameBitmap, String> {
+        let bytes = std::fs::read(file_path)
+            .map_err(|error| format!("Failed to read {}: {}", file_path, error))?;
+
+        match decode_png(&bytes) {
+            Ok((width, height, pixels)) => Ok(Win32GameBitmap::from_pixels(width, height, &pixels)),
+            Err(error) => Err(format!("Failed to load {}: {}", file_path, error)),
+        }
+    }
+
+    // Picks the loader from the file extension, anything that isn't a png is treated as a bmp
+    pub fn load_image(file_path: &str) -> Win32GameBitmap {
+        if file_path.to_lowercase().ends_with(".png") {
+            Win32GameBitmap::load_png(file_path)
+        } else {
+            Win32GameBitmap::load_bmp(file_path)
+        }
+    }
+
+    // Same as load_image, but a missing or broken file is an error instead of a crash
+    pub fn try_load_image(file_path: &str) -> Result<Win32GameBitmap, String> {
+        if file_path.to_lowercase().ends_with(".png") {
+            Win32GameBitmap::try_load_png(file_path)
+        } else {
+            Win32GameBitmap::try_load_bmp(file_path)
+        }
+    }
+
+    //NOTE: ONLY CALL ON BMP TEXTURES
+    pub fn draw_bmp(&self, pos: Point<i32>, buffer: &mut Win32GameBitmap) {
+        let whole = Rect::new(0, 0, self.get_width() as u32, self.get_height() as u32);
+
+        self.draw_bmp_region(&whole, pos, buffer);
+    }
+
+    // Draws just the source rect of the texture (a sprite sheet frame, a tile...)
+    pub fn draw_bmp_region(&self, source: &Rect, pos: Point<i32>, buffer: &mut Win32GameBitmap) {
+        // Keep the source inside the texture
+        let source_w = (source.w as i32).min(self.get_width() - source.x as i32);
+        let source_h = (source.h as i32).min(self.get_height() - source.y as i32);
+
+        // Clip to the buffer so textures can hang off the edges
+        let lower_x = pos.x.max(0);
+        let lower_y = pos.y.max(0);
+        let upper_x = (pos.x + source_w).min(buffer.get_width());
+        let upper_y = (pos.y + source_h).min(buffer.get_height());
+
+        let offset_x = source.x as i32 - pos.x;
+        let offset_y = source.y as i32 - pos.y;
+
+        if lower_x >= upper_x {
+            return;
+        }
+
+        for y in lower_y..upper_y {
+            let source_row = &self.row(y + offset_y)
+                [(lower_x + offset_x) as usize..(upper_x + offset_x) as usize];
+            simd::blend(
+                &mut buffer.row_mut(y)[lower_x as usize..upper_x as usize],
+                source_row,
+            );
+        }
     }
 }
 
@@ -253,6 +543,42 @@ unsafe extern "system" fn window_proc(
         PostQuitMessage(0);
     }
 
+    // The finished text still comes as WM_CHAR through DefWindowProc
+    if msg == WM_IME_COMPOSITION && l_param as u32 & GCS_COMPSTR != 0 {
+        let context = ImmGetContext(h_wnd);
+        let bytes = ImmGetCompositionStringW(context, GCS_COMPSTR, std::ptr::null_mut(), 0);
+
+        if bytes >= 0 {
+            let mut text = vec![0u16; bytes as usize / 2];
+            ImmGetCompositionStringW(
+                context,
+                GCS_COMPSTR,
+                text.as_mut_ptr() as LPVOID,
+                bytes as u32,
+            );
+            let cursor = ImmGetCompositionStringW(context, GCS_CURSORPOS, std::ptr::null_mut(), 0)
+                .clamp(0, text.len() as i32) as usize;
+
+            IME_EVENTS.with(|events| {
+                events.borrow_mut().push_back(TextEvent::Composition {
+                    text: String::from_utf16_lossy(&text),
+                    cursor: String::from_utf16_lossy(&text[..cursor]).chars().count(),
+                })
+            });
+        }
+
+        ImmReleaseContext(h_wnd, context);
+    }
+
+    if msg == WM_IME_ENDCOMPOSITION {
+        IME_EVENTS.with(|events| {
+            events.borrow_mut().push_back(TextEvent::Composition {
+                text: String::new(),
+                cursor: 0,
+            })
+        });
+    }
+
     DefWindowProcW(h_wnd, msg, w_param, l_param)
 }
 
@@ -327,6 +653,13 @@ pub struct Win32Engine {
     hwnd: HWND,
     screen_data: ClientData,
     device_context: HDC,
+    events: InputEvents,
+    text_input: bool,
+    high_surrogate: Option<u16>, // First half of a character from WM_CHAR
+    last_cursor: Option<POINT>,  // Screen coordinates, for the mouse delta
+    cursor_visible: bool,
+    cursor_confined: bool,
+    relative_mouse: bool,
 }
 
 impl Win32Engine {
@@ -374,6 +707,13 @@ impl Win32Engine {
                 hwnd: window,
                 screen_data: get_client_data(&window),
                 device_context: GetDC(window),
+                events: InputEvents::new(),
+                text_input: false,
+                high_surrogate: None,
+                last_cursor: None,
+                cursor_visible: true,
+                cursor_confined: false,
+                relative_mouse: false,
             }
         }
     }
@@ -382,14 +722,68 @@ impl Win32Engine {
         unsafe {
             let mut msg: MSG = std::mem::zeroed();
 
-            // Process messages
-            while PeekMessageA(&mut msg, self.hwnd, 0, 0, PM_REMOVE) > 0 {
+            // Hand out what the IME sent during the last dispatch before moving
+            // on, so it stays in order with the keys and chars around it
+            if let Some(event) = IME_EVENTS.with(|events| events.borrow_mut().pop_front()) {
+                return Some(WindowMessages::Ime(event));
+            }
+
+            // Process messages, the W versions so WM_CHAR is Unicode
+            while PeekMessageW(&mut msg, self.hwnd, 0, 0, PM_REMOVE) > 0 {
                 TranslateMessage(&msg);
-                DispatchMessageA(&msg);
+                DispatchMessageW(&msg);
 
                 if IS_WINDOW_CLOSED {
                     return Some(WindowMessages::WindowClosed);
                 }
+
+                // Bit 30 is set for auto repeat
+                match msg.message {
+                    WM_KEYDOWN | WM_SYSKEYDOWN => {
+                        return Some(WindowMessages::Key {
+                            event: KeyEvent {
+                                key: msg.wParam as i32,
+                                down: true,
+                            },
+                            repeat: msg.lParam & (1 << 30) != 0,
+                        });
+                    }
+                    WM_KEYUP | WM_SYSKEYUP => {
+                        return Some(WindowMessages::Key {
+                            event: KeyEvent {
+                                key: msg.wParam as i32,
+                                down: false,
+                            },
+                            repeat: false,
+                        });
+                    }
+                    WM_CHAR => return Some(WindowMessages::Char(msg.wParam as u16)),
+                    _ => {}
+                }
+
+                let button = |button: MouseButton, down: bool| {
+                    Some(WindowMessages::Mouse(MouseEvent::Button { button, down }))
+                };
+                // The high word of wParam, in 120ths of a notch
+                let wheel = (msg.wParam >> 16) as u16 as i16 as f32 / WHEEL_DELTA as f32;
+
+                match msg.message {
+                    WM_LBUTTONDOWN => return button(MouseButton::Left, true),
+                    WM_LBUTTONUP => return button(MouseButton::Left, false),
+                    WM_RBUTTONDOWN => return button(MouseButton::Right, true),
+                    WM_RBUTTONUP => return button(MouseButton::Right, false),
+                    WM_MBUTTONDOWN => return button(MouseButton::Middle, true),
+                    WM_MBUTTONUP => return button(MouseButton::Middle, false),
+                    WM_MOUSEWHEEL => return Some(WindowMessages::Mouse(MouseEvent::Wheel(wheel))),
+                    WM_MOUSEHWHEEL => {
+                        return Some(WindowMessages::Mouse(MouseEvent::HorizontalWheel(wheel)))
+                    }
+                    _ => {}
+                }
+
+                if let Some(event) = IME_EVENTS.with(|events| events.borrow_mut().pop_front()) {
+                    return Some(WindowMessages::Ime(event));
+                }
             }
 
             None
@@ -439,24 +833,8 @@ impl Win32Engine {
     }
 
     pub fn clear_screen(&self, color: u32, buffer: &mut Win32GameBitmap) {
-        unsafe {
-            /*  let pixel = buffer.memory as *mut u32;
-
-            let mut counter = 0;
-            for _y in 0..self.screen_data.height {
-                for _x in 0..self.screen_data.width {
-                    *pixel.add(counter) = color;
-                    counter += 1;
-                    // Confused me because in C
-                    // it's *pixel++ = color;
-                }
-            }*/
-            libc::memset(
-                buffer.memory as *mut libc::c_void,
-                color as i32,
-                (self.screen_data.width * self.screen_data.height * 4) as usize,
-            );
-        }
+        // memset only worked for colors with four identical bytes
+        simd::fill(buffer.pixels_mut(), color);
     }
 
     pub fn render_buffer_to_screen(&mut self, buffer: &mut Win32GameBitmap) {
@@ -498,10 +876,203 @@ impl Win32Engine {
                 WindowMessages::WindowClosed => {
                     self.running = false;
                 }
+                WindowMessages::Key { event, repeat } => {
+                    // Only real presses count for the key state
+                    if !repeat {
+                        self.events.keys.push(event);
+                    }
+                    if self.text_input && event.down {
+                        self.events.text.push(TextEvent::KeyDown {
+                            key: event.key,
+                            repeat,
+                        });
+                    }
+                }
+                WindowMessages::Char(unit) => self.push_char(unit),
+                WindowMessages::Ime(event) => {
+                    if self.text_input {
+                        self.events.text.push(event);
+                    }
+                }
+                WindowMessages::Mouse(event) => {
+                    // Keeps sending the mouse to the window while a button is held,
+                    // so letting go outside of it still counts
+                    unsafe {
+                        match event {
+                            MouseEvent::Button { down: true, .. } => {
+                                SetCapture(self.hwnd);
+                            }
+                            MouseEvent::Button { down: false, .. } => {
+                                ReleaseCapture();
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    self.events.mouse.push(event);
+                }
+            }
+        }
+
+        self.poll_cursor();
+    }
+
+    // Everything since the last call, for Win32Input::update
+    pub fn take_input_events(&mut self) -> InputEvents {
+        mem::replace(&mut self.events, InputEvents::new())
+    }
+
+    fn push_char(&mut self, unit: u16) {
+        let units = match (self.high_surrogate.take(), unit) {
+            (_, 0xD800..=0xDBFF) => {
+                self.high_surrogate = Some(unit);
+                return;
+            }
+            (Some(high), 0xDC00..=0xDFFF) => vec![high, unit],
+            (_, _) => vec![unit],
+        };
+
+        let c = match char::decode_utf16(units).next() {
+            Some(Ok(c)) => c,
+            _ => return,
+        };
+
+        // Backspace, enter, tab and ctrl combos come through here too, they're
+        // left to the KeyDown events
+        if self.text_input && !c.is_control() {
+            self.events.text.push(TextEvent::Char(c));
+        }
+    }
+
+    // Turn on while a text field is focused, text events are dropped otherwise
+    pub fn set_text_input(&mut self, enabled: bool) {
+        self.text_input = enabled;
+    }
+
+    pub fn is_text_input(&self) -> bool {
+        self.text_input
+    }
+
+    // The client area in screen coordinates
+    fn client_screen_rect(&self) -> RECT {
+        unsafe {
+            let mut rect: RECT = mem::zeroed();
+            GetClientRect(self.hwnd, &mut rect);
+
+            let mut top_left = POINT {
+                x: rect.left,
+                y: rect.top,
+            };
+            let mut bottom_right = POINT {
+                x: rect.right,
+                y: rect.bottom,
+            };
+            ClientToScreen(self.hwnd, &mut top_left);
+            ClientToScreen(self.hwnd, &mut bottom_right);
+
+            RECT {
+                left: top_left.x,
+                top: top_left.y,
+                right: bottom_right.x,
+                bottom: bottom_right.y,
+            }
+        }
+    }
+
+    // Reads where the cursor is once a frame. In relative mode it's put back in
+    // the middle of the window every frame so it never hits the screen edge.
+    fn poll_cursor(&mut self) {
+        let focused = self.check_focus();
+
+        unsafe {
+            let mut cursor: POINT = mem::zeroed();
+            if GetCursorPos(&mut cursor) == 0 {
+                return;
+            }
+
+            let delta = match self.last_cursor {
+                Some(last) => Point::new(cursor.x - last.x, cursor.y - last.y),
+                None => Point::new(0, 0),
+            };
+            self.last_cursor = Some(cursor);
+
+            let mut client = cursor;
+            ScreenToClient(self.hwnd, &mut client);
+            let inside = client.x >= 0
+                && client.y >= 0
+                && client.x < self.screen_data.width
+                && client.y < self.screen_data.height;
+
+            self.events.mouse.push(MouseEvent::Move {
+                position: if inside && focused {
+                    Some(Point::new(client.x, client.y))
+                } else {
+                    None
+                },
+                delta,
+            });
+
+            if !focused {
+                return;
+            }
+
+            // Windows drops the clip when the window loses focus or moves
+            let rect = self.client_screen_rect();
+            if self.cursor_confined || self.relative_mouse {
+                ClipCursor(&rect);
+            }
+
+            if self.relative_mouse {
+                let center = POINT {
+                    x: (rect.left + rect.right) / 2,
+                    y: (rect.top + rect.bottom) / 2,
+                };
+                SetCursorPos(center.x, center.y);
+                self.last_cursor = Some(center);
+            }
+        }
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        if visible != self.cursor_visible {
+            // ShowCursor is a counter, only step it when the state changes
+            unsafe {
+                ShowCursor(visible as i32);
+            }
+            self.cursor_visible = visible;
+        }
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    // Keeps the cursor inside the window while it has focus
+    pub fn set_cursor_confined(&mut self, confined: bool) {
+        self.cursor_confined = confined;
+        if !confined && !self.relative_mouse {
+            unsafe {
+                ClipCursor(std::ptr::null());
+            }
+        }
+    }
+
+    // Hides and locks the cursor so only the mouse delta means anything, for
+    // mouse look style controls. The position reads as the window center.
+    pub fn set_relative_mouse(&mut self, relative: bool) {
+        self.relative_mouse = relative;
+        self.set_cursor_visible(!relative);
+        if !relative && !self.cursor_confined {
+            unsafe {
+                ClipCursor(std::ptr::null());
             }
         }
     }
 
+    pub fn is_relative_mouse(&self) -> bool {
+        self.relative_mouse
+    }
+
     pub fn is_running(&self) -> bool {
         self.running
     }
@@ -539,39 +1110,148 @@ impl Win32Engine {
 
 // Win32 Draw functions/methods
 impl Win32Drawable for Win32Engine {
-    // Thank you Ryan Fleury, I was too stupid to figure this out so I just
-    // translated your C code to rust. :) :dumb:
+    // Opaque, the color's alpha is ignored
     fn draw_rectangle(&self, color: &Color, rect: &Rect, buffer: &mut Win32GameBitmap) {
-        unsafe {
-            let pixel = buffer.memory as *mut u8; // Starting pixel
-
-            let lower_bound_x = rect.x;
-            let lower_bound_y = rect.y;
-            let upper_bound_x = lower_bound_x + rect.w;
-            let upper_bound_y = lower_bound_y + rect.h;
-
-            let mut _pixel_index = 0;
-            for y in lower_bound_y..upper_bound_y {
-                for x in lower_bound_x..upper_bound_x {
-                    _pixel_index = y * self.screen_data.width as u32 + x;
-                    // Pixel index is the pixel coord we wanna change the color of
-                    *pixel.add((_pixel_index * 4 + 2) as usize) = color.r * 255;
-                    *pixel.add((_pixel_index * 4 + 1) as usize) = color.g * 255;
-                    *pixel.add((_pixel_index * 4 + 0) as usize) = color.b * 255;
-                }
-            }
+        let lower_x = (rect.x as i32).min(buffer.get_width()) as usize;
+        let upper_x = ((rect.x + rect.w) as i32).min(buffer.get_width()) as usize;
+        let upper_y = ((rect.y + rect.h) as i32).min(buffer.get_height());
+
+        for y in rect.y as i32..upper_y {
+            simd::fill(
+                &mut buffer.row_mut(y)[lower_x..upper_x],
+                color.to_u32() | 0xFF000000,
+            );
         }
-        //println!("{}", rect.x);
+    }
+
+    // Everything below is clipped and blended with the color's alpha, see raster.rs
+    fn draw_rectangle_outline(
+        &self,
+        color: &Color,
+        rect: &Rect,
+        thickness: u32,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::draw_rect_outline(
+            buffer,
+            Point::new(rect.x as i32, rect.y as i32),
+            Point::new(rect.w, rect.h),
+            thickness,
+            color.to_u32(),
+        );
+    }
+
+    fn draw_line(
+        &self,
+        color: &Color,
+        from: Point<i32>,
+        to: Point<i32>,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::draw_line(buffer, from, to, color.to_u32());
+    }
+
+    fn draw_line_aa(
+        &self,
+        color: &Color,
+        from: Point<f32>,
+        to: Point<f32>,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::draw_line_aa(buffer, from, to, color.to_u32());
+    }
+
+    fn draw_circle(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: i32,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::draw_circle(buffer, center, radius, color.to_u32());
+    }
+
+    fn fill_circle(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: i32,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::fill_circle(buffer, center, radius, color.to_u32());
+    }
+
+    fn draw_ellipse(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: Point<i32>,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::draw_ellipse(buffer, center, radius, color.to_u32());
+    }
+
+    fn fill_ellipse(
+        &self,
+        color: &Color,
+        center: Point<i32>,
+        radius: Point<i32>,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::fill_ellipse(buffer, center, radius, color.to_u32());
+    }
+
+    fn draw_polygon(&self, color: &Color, points: &[Point<f32>], buffer: &mut Win32GameBitmap) {
+        raster::draw_polygon(buffer, points, color.to_u32());
+    }
+
+    fn fill_polygon(&self, color: &Color, points: &[Point<f32>], buffer: &mut Win32GameBitmap) {
+        raster::fill_polygon(buffer, points, color.to_u32());
+    }
+
+    fn fill_triangle(
+        &self,
+        color: &Color,
+        a: Point<f32>,
+        b: Point<f32>,
+        c: Point<f32>,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        raster::fill_triangle(buffer, a, b, c, color.to_u32());
+    }
+
+    // Textured and vertex colored, see triangle.rs
+    fn draw_triangle(
+        &self,
+        vertices: &[Vertex; 3],
+        texture: Option<&TriangleTexture>,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        triangle::draw_triangle(buffer, &vertices[0], &vertices[1], &vertices[2], texture);
+    }
+
+    fn draw_text(
+        &self,
+        font: &dyn Font,
+        text: &str,
+        pos: Point<i32>,
+        color: &Color,
+        buffer: &mut Win32GameBitmap,
+    ) {
+        font.draw_text(text, pos, color.to_u32(), buffer);
     }
 }
 
-pub struct Win32Input {
-    game_pad_state: XINPUT_STATE,
-    game_pad_id: i8,
+// What XInput last said about one controller slot
+struct GamepadSlot {
+    state: XINPUT_STATE,
+    connected: bool,
+    rumble: RumbleMixer,
+    motor_speeds: (u16, u16), // Last sent to XInputSetState
 }
 
-impl Win32Input {
-    pub fn new() -> Self {
+impl GamepadSlot {
+    fn new() -> Self {
         // Null init XINPUT structures
         let gamepad_struct = XINPUT_GAMEPAD {
             wButtons: 0,
@@ -583,96 +1263,503 @@ impl Win32Input {
             sThumbRY: 0,
         };
 
-        let state = XINPUT_STATE {
-            dwPacketNumber: 0,
-            Gamepad: gamepad_struct,
-        };
+        Self {
+            state: XINPUT_STATE {
+                dwPacketNumber: 0,
+                Gamepad: gamepad_struct,
+            },
+            connected: false,
+            rumble: RumbleMixer::new(),
+            motor_speeds: (0, 0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GamepadEvent {
+    Connected(u32), // XInput slot, 0 to 3
+    Disconnected(u32),
+}
+
+// A local player, reading from the keyboard and mouse and/or one controller.
+// Each gets their own snapshot from the shared bindings.
+struct Player {
+    keyboard: bool,
+    gamepad: Option<u32>,
+    snapshot: InputSnapshot,
+}
+
+// Checking empty slots is slow in XInput, so it's only done this often
+const GAMEPAD_CONNECT_INTERVAL: f32 = 1.0;
+
+pub struct Win32Input {
+    gamepads: Vec<GamepadSlot>,
+    connect_timer: f32,
+    gamepad_events: Vec<GamepadEvent>,
+    players: Vec<Player>,
+    bindings: InputMap,
+    rebind: Option<RebindCapture>,
+    rebind_result: Option<RebindResult>,
+    gamepad_settings: GamepadSettings,
+    frame: InputFrame,
+}
 
+impl Win32Input {
+    // Starts with one player on the keyboard, controllers go to the first player
+    // without one as they connect
+    pub fn new() -> Self {
         Self {
-            game_pad_state: state,
-            game_pad_id: -1,
-        }
-    }
-
-    pub fn get_controller(&mut self) {
-        if self.game_pad_id == -1 {
-            for i in 0..XUSER_MAX_COUNT {
-                let empty_gamepad_struct = XINPUT_GAMEPAD {
-                    wButtons: 0,
-                    bLeftTrigger: 0,
-                    bRightTrigger: 0,
-                    sThumbLX: 0,
-                    sThumbLY: 0,
-                    sThumbRX: 0,
-                    sThumbRY: 0,
-                };
+            gamepads: (0..XUSER_MAX_COUNT).map(|_| GamepadSlot::new()).collect(),
+            connect_timer: 0.0,
+            gamepad_events: Vec::new(),
+            players: vec![Player {
+                keyboard: true,
+                gamepad: None,
+                snapshot: InputSnapshot::new(),
+            }],
+            bindings: InputMap::with_defaults(),
+            rebind: None,
+            rebind_result: None,
+            gamepad_settings: GamepadSettings::new(),
+            frame: InputFrame::new(0.0),
+        }
+    }
 
-                let mut state = XINPUT_STATE {
-                    dwPacketNumber: 0,
-                    Gamepad: empty_gamepad_struct,
+    // Reads every connected controller, a failed read means it was unplugged.
+    // Empty slots are checked every GAMEPAD_CONNECT_INTERVAL.
+    fn poll_gamepads(&mut self, dt: f32) {
+        self.connect_timer -= dt;
+        let check_empty = self.connect_timer <= 0.0;
+        if check_empty {
+            self.connect_timer = GAMEPAD_CONNECT_INTERVAL;
+        }
+
+        for (index, slot) in self.gamepads.iter_mut().enumerate() {
+            if !slot.connected && !check_empty {
+                continue;
+            }
+
+            let mut state = GamepadSlot::new().state;
+            let connected = unsafe { XInputGetState(index as u32, &mut state) == ERROR_SUCCESS };
+
+            if connected != slot.connected {
+                self.gamepad_events.push(if connected {
+                    GamepadEvent::Connected(index as u32)
+                } else {
+                    slot.rumble.stop_all();
+                    slot.motor_speeds = (0, 0);
+                    GamepadEvent::Disconnected(index as u32)
+                });
+            }
+
+            slot.state = state;
+            slot.connected = connected;
+        }
+    }
+
+    // Runs the rumble effects and only calls XInput when the speeds change
+    fn update_rumble(&mut self, dt: f32) {
+        for (index, slot) in self.gamepads.iter_mut().enumerate() {
+            let (left, right) = slot.rumble.update(dt);
+            if !slot.connected {
+                continue;
+            }
+
+            let speeds = ((left * 65535.0) as u16, (right * 65535.0) as u16);
+            if speeds != slot.motor_speeds {
+                let mut vibration = XINPUT_VIBRATION {
+                    wLeftMotorSpeed: speeds.0,
+                    wRightMotorSpeed: speeds.1,
                 };
 
                 unsafe {
-                    if XInputGetState(i, &mut state) == ERROR_SUCCESS {
-                        self.game_pad_id = i as i8;
-                        println!("Found controller!");
+                    if XInputSetState(index as u32, &mut vibration) == ERROR_SUCCESS {
+                        slot.motor_speeds = speeds;
                     }
                 }
             }
         }
     }
 
-    pub fn left(&mut self) -> bool {
-        unsafe {
-            if GetAsyncKeyState(0x41) != 0 || GetAsyncKeyState(VK_LEFT) != 0 {
-                return true;
-            }
+    // Call once at the start of a frame, after the engine's handle_events. Reads
+    // the devices and captures the snapshots the rest of the frame uses.
+    pub fn update(&mut self, engine: &mut Win32Engine, dt: f32) {
+        self.poll(engine, dt);
+
+        let frame = mem::replace(&mut self.frame, InputFrame::new(dt));
+        self.apply_frame(&frame);
+        self.frame = frame;
+    }
 
-            if XInputGetState(self.game_pad_id as u32, &mut self.game_pad_state) == ERROR_SUCCESS {
-                if self.game_pad_state.Gamepad.wButtons & XINPUT_GAMEPAD_DPAD_LEFT != 0 {
-                    return true;
+    // Reads the devices into get_frame without capturing the snapshots, for when
+    // a recording is played instead. Controllers and rebinding still run.
+    pub fn poll(&mut self, engine: &mut Win32Engine, dt: f32) {
+        let events = engine.take_input_events();
+
+        let first_event = self.gamepad_events.len();
+        self.poll_gamepads(dt);
+
+        // New controllers go to whoever doesn't have one. A controller that comes
+        // back in the same slot is still assigned to its player.
+        for index in first_event..self.gamepad_events.len() {
+            if let GamepadEvent::Connected(slot) = self.gamepad_events[index] {
+                if self.get_gamepad_player(slot).is_none() {
+                    if let Some(player) = self.players.iter_mut().find(|p| p.gamepad.is_none()) {
+                        player.gamepad = Some(slot);
+                    }
                 }
             }
         }
 
-        false
-    }
-
-    pub fn right(&mut self) -> bool {
-        unsafe {
-            if GetAsyncKeyState(0x44) != 0 || GetAsyncKeyState(VK_RIGHT) != 0 {
-                return true;
+        if let Some(mut capture) = self.rebind.take() {
+            // The capture reads from self while changing the bindings
+            let mut bindings = mem::replace(&mut self.bindings, InputMap::new());
+            match capture.poll(self, &mut bindings) {
+                Some(result) => self.rebind_result = Some(result),
+                None => self.rebind = Some(capture),
             }
+            self.bindings = bindings;
+        }
 
-            if self.game_pad_state.Gamepad.wButtons & XINPUT_GAMEPAD_DPAD_RIGHT != 0 {
-                return true;
+        // Nothing counts while the window is in the background
+        let focused = engine.check_focus();
+
+        let mut frame = InputFrame::new(dt);
+        for player in &self.players {
+            if !focused {
+                frame.players.push(PlayerFrame::new());
+                continue;
             }
+
+            let device = PlayerDevice {
+                input: self,
+                keyboard: player.keyboard,
+                gamepad: player.gamepad,
+            };
+            // The keyboard, mouse and text go together
+            let events = if player.keyboard {
+                events.clone()
+            } else {
+                InputEvents::new()
+            };
+
+            frame.players.push(PlayerFrame::read(&device, events));
         }
-        false
+
+        self.frame = frame;
     }
 
-    pub fn up(&mut self) -> bool {
-        unsafe {
-            if GetAsyncKeyState(0x57) != 0 || GetAsyncKeyState(VK_UP) != 0 {
-                return true;
-            }
+    // Captures every player's snapshot from a frame of input, from update or a
+    // recording. Players missing from the frame get nothing held. Rumble runs on
+    // the frame time here so replays and the headless run step it the same way.
+    pub fn apply_frame(&mut self, frame: &InputFrame) {
+        self.update_rumble(frame.dt);
 
-            if self.game_pad_state.Gamepad.wButtons & XINPUT_GAMEPAD_DPAD_UP != 0 {
-                return true;
-            }
+        let nothing = PlayerFrame::new();
+
+        for (index, player) in self.players.iter_mut().enumerate() {
+            let input = frame.players.get(index).unwrap_or(&nothing);
+            player
+                .snapshot
+                .capture(input, &self.bindings, &input.events, frame.dt);
         }
-        false
     }
 
-    pub fn down(&mut self) -> bool {
-        unsafe {
-            if GetAsyncKeyState(0x53) != 0 || GetAsyncKeyState(VK_DOWN) != 0 {
-                return true;
-            }
-            if self.game_pad_state.Gamepad.wButtons & XINPUT_GAMEPAD_DPAD_DOWN != 0 {
-                return true;
+    // The input poll read this frame, for recording
+    pub fn get_frame(&self) -> &InputFrame {
+        &self.frame
+    }
+
+    // Connects and disconnects since the last call
+    pub fn take_gamepad_events(&mut self) -> Vec<GamepadEvent> {
+        mem::take(&mut self.gamepad_events)
+    }
+
+    pub fn is_gamepad_connected(&self, slot: u32) -> bool {
+        self.gamepads
+            .get(slot as usize)
+            .is_some_and(|gamepad| gamepad.connected)
+    }
+
+    /* Players */
+
+    // Extra players start with no devices. There's always at least one player,
+    // get_snapshot is player 0.
+    pub fn set_player_count(&mut self, count: usize) {
+        let count = count.max(1);
+        self.players.truncate(count);
+        while self.players.len() < count {
+            self.players.push(Player {
+                keyboard: false,
+                gamepad: None,
+                snapshot: InputSnapshot::new(),
+            });
+        }
+    }
+
+    pub fn get_player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    // Gives the controller in the slot to a player, taking it off anyone else.
+    // None leaves the player without one.
+    pub fn assign_gamepad(&mut self, player: usize, slot: Option<u32>) {
+        if slot.is_some() {
+            for other in &mut self.players {
+                if other.gamepad == slot {
+                    other.gamepad = None;
+                }
             }
         }
-        false
+
+        if let Some(player) = self.players.get_mut(player) {
+            player.gamepad = slot;
+        }
+    }
+
+    pub fn get_player_gamepad(&self, player: usize) -> Option<u32> {
+        self.players.get(player).and_then(|player| player.gamepad)
+    }
+
+    pub fn get_gamepad_player(&self, slot: u32) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|player| player.gamepad == Some(slot))
+    }
+
+    // More than one player can share the keyboard
+    pub fn set_player_keyboard(&mut self, player: usize, keyboard: bool) {
+        if let Some(player) = self.players.get_mut(player) {
+            player.keyboard = keyboard;
+        }
+    }
+
+    // A player's input this frame, None past the player count
+    pub fn get_player(&self, player: usize) -> Option<&InputSnapshot> {
+        self.players.get(player).map(|player| &player.snapshot)
+    }
+
+    // Every player's last snapshot. Presses and held times are worked out from
+    // the snapshot before, so a looping replay puts these back at the loop start.
+    pub fn save_state(&self) -> Vec<InputSnapshot> {
+        self.players
+            .iter()
+            .map(|player| player.snapshot.clone())
+            .collect()
+    }
+
+    pub fn restore_state(&mut self, state: &[InputSnapshot]) {
+        for (player, snapshot) in self.players.iter_mut().zip(state) {
+            player.snapshot = snapshot.clone();
+        }
+    }
+
+    /* Rumble */
+
+    // The vibration on a player's controller, None if they don't have one
+    pub fn get_rumble_mut(&mut self, player: usize) -> Option<&mut RumbleMixer> {
+        let slot = self.get_player_gamepad(player)?;
+        self.gamepads
+            .get_mut(slot as usize)
+            .map(|gamepad| &mut gamepad.rumble)
+    }
+
+    pub fn rumble(&mut self, player: usize, effect: RumbleEffect) -> Option<RumbleHandle> {
+        self.get_rumble_mut(player)
+            .map(|rumble| rumble.play(effect))
+    }
+
+    // 0 to 1 on every controller, 0 turns rumble off
+    pub fn set_rumble_strength(&mut self, strength: f32) {
+        for gamepad in &mut self.gamepads {
+            gamepad.rumble.set_strength(strength);
+        }
+    }
+
+    /* Rebinding */
+
+    // Binds the next key or button pressed to the action, replacing the binding at
+    // slot (or adding one). Check take_rebind_result each frame to see what happened.
+    pub fn start_rebind(&mut self, action: &str, slot: Option<usize>, scale: f32) {
+        self.rebind = Some(RebindCapture::new(action, slot, scale));
+        self.rebind_result = None;
+    }
+
+    pub fn cancel_rebind(&mut self) {
+        if self.rebind.take().is_some() {
+            self.rebind_result = Some(RebindResult::Cancelled);
+        }
+    }
+
+    pub fn is_rebinding(&self) -> bool {
+        self.rebind.is_some()
+    }
+
+    pub fn take_rebind_result(&mut self) -> Option<RebindResult> {
+        self.rebind_result.take()
+    }
+
+    pub fn get_bindings(&self) -> &InputMap {
+        &self.bindings
+    }
+
+    pub fn get_bindings_mut(&mut self) -> &mut InputMap {
+        &mut self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: InputMap) {
+        self.bindings = bindings;
+    }
+
+    // Deadzones and response curves for the sticks and triggers
+    pub fn get_gamepad_settings(&self) -> &GamepadSettings {
+        &self.gamepad_settings
+    }
+
+    pub fn set_gamepad_settings(&mut self, settings: GamepadSettings) {
+        self.gamepad_settings = settings;
+    }
+
+    /* Player one */
+
+    pub fn get_snapshot(&self) -> &InputSnapshot {
+        &self.players[0].snapshot
+    }
+
+    // Named actions as of the last update, see input.rs
+    pub fn value(&self, action: &str) -> f32 {
+        self.get_snapshot().value(action)
+    }
+
+    pub fn is_down(&self, action: &str) -> bool {
+        self.get_snapshot().is_down(action)
+    }
+
+    pub fn was_pressed(&self, action: &str) -> bool {
+        self.get_snapshot().was_pressed(action)
+    }
+
+    pub fn was_released(&self, action: &str) -> bool {
+        self.get_snapshot().was_released(action)
+    }
+
+    pub fn held_time(&self, action: &str) -> f32 {
+        self.get_snapshot().held_time(action)
+    }
+
+    // -1 to 1 on each axis after the deadzone, up is positive
+    pub fn get_stick(&self, stick: Stick) -> Point<f32> {
+        self.get_snapshot().get_stick(stick)
+    }
+
+    // Any XINPUT_GAMEPAD_* button, including the ones nothing is bound to
+    pub fn gamepad_button(&self, button: u16) -> ButtonState {
+        self.get_snapshot().get_gamepad_button(button)
+    }
+
+    /* Raw reads */
+
+    fn key_down(key: i32) -> bool {
+        unsafe { GetAsyncKeyState(key) as u16 & 0x8000 != 0 }
+    }
+
+    fn mouse_down(button: MouseButton) -> bool {
+        let key = match button {
+            MouseButton::Left => VK_LBUTTON,
+            MouseButton::Right => VK_RBUTTON,
+            MouseButton::Middle => VK_MBUTTON,
+        };
+
+        Win32Input::key_down(key)
+    }
+
+    fn gamepad_button_down(&self, slot: u32, button: u16) -> bool {
+        match self.gamepads.get(slot as usize) {
+            Some(gamepad) => gamepad.state.Gamepad.wButtons & button != 0,
+            None => false,
+        }
+    }
+
+    // Sticks need both axes for a radial deadzone, so the whole stick is worked
+    // out and one axis picked
+    fn gamepad_axis(&self, slot: u32, axis: GamepadAxis) -> f32 {
+        let pad = match self.gamepads.get(slot as usize) {
+            Some(gamepad) => &gamepad.state.Gamepad,
+            None => return 0.0,
+        };
+        let settings = &self.gamepad_settings;
+
+        // -32768 would go just past -1
+        let stick = |x: i16, y: i16, stick: Stick| {
+            let raw = Point::new(
+                (x as f32 / 32767.0).max(-1.0),
+                (y as f32 / 32767.0).max(-1.0),
+            );
+            settings.get_stick(stick).apply(raw)
+        };
+
+        match axis {
+            GamepadAxis::LeftX => stick(pad.sThumbLX, pad.sThumbLY, Stick::Left).x,
+            GamepadAxis::LeftY => stick(pad.sThumbLX, pad.sThumbLY, Stick::Left).y,
+            GamepadAxis::RightX => stick(pad.sThumbRX, pad.sThumbRY, Stick::Right).x,
+            GamepadAxis::RightY => stick(pad.sThumbRX, pad.sThumbRY, Stick::Right).y,
+            GamepadAxis::LeftTrigger => settings.apply_trigger(pad.bLeftTrigger as f32 / 255.0),
+            GamepadAxis::RightTrigger => settings.apply_trigger(pad.bRightTrigger as f32 / 255.0),
+        }
+    }
+}
+
+// The keyboard, mouse and every connected controller together, for rebinding
+impl InputDevice for Win32Input {
+    fn key_down(&self, key: i32) -> bool {
+        Win32Input::key_down(key)
+    }
+
+    fn mouse_down(&self, button: MouseButton) -> bool {
+        Win32Input::mouse_down(button)
+    }
+
+    fn gamepad_button_down(&self, button: u16) -> bool {
+        (0..self.gamepads.len() as u32).any(|slot| self.gamepad_button_down(slot, button))
+    }
+
+    // Whichever controller is pushed furthest
+    fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        (0..self.gamepads.len() as u32)
+            .map(|slot| self.gamepad_axis(slot, axis))
+            .fold(0.0, |best, value| {
+                if value.abs() > best.abs() {
+                    value
+                } else {
+                    best
+                }
+            })
+    }
+}
+
+// What one player's snapshot is captured from
+struct PlayerDevice<'a> {
+    input: &'a Win32Input,
+    keyboard: bool,
+    gamepad: Option<u32>,
+}
+
+impl<'a> InputDevice for PlayerDevice<'a> {
+    fn key_down(&self, key: i32) -> bool {
+        self.keyboard && Win32Input::key_down(key)
+    }
+
+    fn mouse_down(&self, button: MouseButton) -> bool {
+        self.keyboard && Win32Input::mouse_down(button)
+    }
+
+    fn gamepad_button_down(&self, button: u16) -> bool {
+        self.gamepad
+            .is_some_and(|slot| self.input.gamepad_button_down(slot, button))
+    }
+
+    fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad
+            .map_or(0.0, |slot| self.input.gamepad_axis(slot, axis))
     }
 }
@@ -0,0 +1,238 @@
+// Minimal XML reader for TMX/TSX files. No namespaces or DTDs, just
+// elements, attributes and text.
+
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    pub text: String,
+}
+
+impl XmlElement {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+// Returns the root element
+pub fn parse_xml(text: &str) -> Result<XmlElement, String> {
+    let mut parser = XmlParser { text, pos: 0 };
+
+    parser.skip_misc()?;
+    let root = parser.parse_element()?;
+    parser.skip_misc()?;
+
+    if parser.pos != text.len() {
+        return Err(parser.error("Content after the root element"));
+    }
+
+    Ok(root)
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(std::char::from_u32),
+            _ => None,
+        };
+
+        match decoded {
+            Some(character) => {
+                result.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                // Unknown entity, keep it as is
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+struct XmlParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        format!("{} in xml on line {}", message, line)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, pattern: &str) -> Result<(), String> {
+        match self.rest().find(pattern) {
+            Some(index) => {
+                self.pos += index + pattern.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("Missing '{}'", pattern))),
+        }
+    }
+
+    // Prolog, comments, doctypes and whitespace between elements
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_whitespace();
+
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<&'a str, String> {
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+            .unwrap_or(rest.len());
+
+        if length == 0 {
+            return Err(self.error("Expected a name"));
+        }
+
+        self.pos += length;
+        Ok(&rest[..length])
+    }
+
+    fn parse_element(&mut self) -> Result<XmlElement, String> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("Expected an element"));
+        }
+        self.pos += 1;
+
+        let mut element = XmlElement {
+            name: self.parse_name()?.to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+        };
+
+        // Attributes
+        loop {
+            self.skip_whitespace();
+
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let key = self.parse_name()?.to_string();
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("Expected '=' after attribute name"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => quote,
+                _ => return Err(self.error("Expected a quoted attribute value")),
+            };
+            self.pos += 1;
+
+            let end = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.error("Unterminated attribute value"))?;
+            let value = decode_entities(&self.rest()[..end]);
+            self.pos += end + 1;
+
+            element.attributes.push((key, value));
+        }
+
+        // Content
+        loop {
+            let rest = self.rest();
+
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.parse_name()?;
+                if name != element.name {
+                    return Err(self.error(&format!(
+                        "Expected </{}> but found </{}>",
+                        element.name, name
+                    )));
+                }
+                self.skip_past(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                let end = self
+                    .rest()
+                    .find("]]>")
+                    .ok_or_else(|| self.error("Unterminated CDATA"))?;
+                element.text.push_str(&self.rest()[..end]);
+                self.pos += end + 3;
+            } else if rest.starts_with('<') {
+                element.children.push(self.parse_element()?);
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("Missing </{}>", element.name)));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&decode_entities(&rest[..end]));
+                self.pos += end;
+            }
+        }
+    }
+}